@@ -0,0 +1,134 @@
+use futures::stream::{self, StreamExt};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// Maximum number of documents in flight at once.
+    pub concurrency: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+}
+
+/// Reported once per finished item, in completion order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchProgress {
+    /// Position of the finished item in the input.
+    pub index: usize,
+    pub completed: usize,
+    pub total: usize,
+}
+
+/// Runs `task` over every input with at most `options.concurrency` tasks in
+/// flight; a finished task frees its slot at once, even while an earlier
+/// one is still running. Outputs come back in input order regardless of
+/// completion order; `on_progress` fires as each task finishes.
+pub async fn run_batch<I, T, F, Fut, P>(
+    inputs: Vec<I>,
+    options: &BatchOptions,
+    task: F,
+    on_progress: P,
+) -> Vec<T>
+where
+    F: Fn(usize, I) -> Fut,
+    Fut: Future<Output = T>,
+    P: Fn(BatchProgress),
+{
+    let total = inputs.len();
+    let completed = AtomicUsize::new(0);
+    let task = &task;
+    let on_progress = &on_progress;
+    let completed = &completed;
+
+    let mut finished = stream::iter(inputs.into_iter().enumerate())
+        .map(|(index, input)| async move {
+            let output = task(index, input).await;
+            let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+            on_progress(BatchProgress {
+                index,
+                completed: done,
+                total,
+            });
+            (index, output)
+        })
+        .buffer_unordered(options.concurrency.max(1));
+
+    let mut outputs: Vec<Option<T>> = std::iter::repeat_with(|| None).take(total).collect();
+    while let Some((index, output)) = finished.next().await {
+        outputs[index] = Some(output);
+    }
+    outputs
+        .into_iter()
+        .map(|output| output.expect("every task finishes"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_preserves_order_and_bounds_concurrency() {
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+        let finished = Mutex::new(Vec::new());
+
+        // Earlier items sleep longer, so they finish last.
+        let outputs = run_batch(
+            vec![40u64, 30, 20, 10, 0],
+            &BatchOptions { concurrency: 2 },
+            |index, delay| {
+                let in_flight = &in_flight;
+                let max_in_flight = &max_in_flight;
+                async move {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    index * 10
+                }
+            },
+            |progress| finished.lock().unwrap().push(progress),
+        )
+        .await;
+
+        assert_eq!(outputs, vec![0, 10, 20, 30, 40]);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+
+        let finished = finished.into_inner().unwrap();
+        assert_eq!(finished.len(), 5);
+        assert_eq!(finished.last().unwrap().completed, 5);
+        assert!(finished.iter().all(|p| p.total == 5));
+    }
+
+    #[tokio::test]
+    async fn test_slow_item_does_not_hold_back_later_ones() {
+        let finished = Mutex::new(Vec::new());
+
+        let outputs = run_batch(
+            vec![300u64, 10, 10, 10, 10],
+            &BatchOptions { concurrency: 2 },
+            |index, delay| async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                index
+            },
+            |progress| finished.lock().unwrap().push(progress.index),
+        )
+        .await;
+
+        assert_eq!(outputs, vec![0, 1, 2, 3, 4]);
+        // The other slot keeps working through the rest while the first
+        // item sleeps.
+        assert_eq!(finished.into_inner().unwrap(), vec![1, 2, 3, 4, 0]);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::llm_provider::LLMRequest;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+pub const DEFAULT_MAX_BYTES: u64 = 50 * 1024 * 1024;
+
+/// Content-addressed store of raw completions, one JSON file per request.
+/// Entries expire after `ttl`; once the directory grows past `max_bytes` the
+/// oldest entries are removed. Cache failures are logged and otherwise
+/// ignored, so a broken cache never fails an analysis.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_bytes: u64,
+    refresh: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    created_at: u64,
+    model: String,
+    response: String,
+}
+
+impl ResponseCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: DEFAULT_TTL,
+            max_bytes: DEFAULT_MAX_BYTES,
+            refresh: false,
+        }
+    }
+
+    /// `$XDG_CACHE_HOME/financial-llm-poc`, falling back to `~/.cache`.
+    pub fn default_dir() -> PathBuf {
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir);
+        base.join("financial-llm-poc")
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Ignores existing entries but still stores fresh completions.
+    pub fn refreshing(mut self) -> Self {
+        self.refresh = true;
+        self
+    }
+
+    /// The cache key: a hash of the prompt text sent (which contains the
+    /// document), the model id, the temperature and the prompt-template
+    /// version.
+    pub fn key(request: &LLMRequest, prompt_version: &str) -> String {
+        let mut text = Sha256::new();
+        for message in &request.messages {
+            text.update(message.role.as_bytes());
+            text.update([0]);
+            text.update(message.content.as_bytes());
+            text.update([0]);
+        }
+
+        let mut key = Sha256::new();
+        key.update(text.finalize());
+        key.update(request.model.as_bytes());
+        key.update([0]);
+        key.update(request.temperature.to_bits().to_be_bytes());
+        key.update(prompt_version.as_bytes());
+        hex::encode(key.finalize())
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        if self.refresh {
+            return None;
+        }
+        let path = self.path(key);
+        let bytes = std::fs::read(&path).ok()?;
+        let entry: CacheEntry = match serde_json::from_slice(&bytes) {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Ignoring unreadable cache entry {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        // An expired entry is left on disk: a cache with a longer TTL may
+        // still use it, and the next `put` replaces it.
+        if now().saturating_sub(entry.created_at) > self.ttl.as_secs() {
+            log::debug!("Cache entry {} expired", key);
+            return None;
+        }
+        log::info!("Cache hit for {} ({})", entry.model, key);
+        Some(entry.response)
+    }
+
+    pub fn put(&self, key: &str, model: &str, response: &str) {
+        let entry = CacheEntry {
+            created_at: now(),
+            model: model.to_string(),
+            response: response.to_string(),
+        };
+        let result = std::fs::create_dir_all(&self.dir)
+            .and_then(|()| {
+                let json = serde_json::to_vec(&entry).map_err(std::io::Error::other)?;
+                // Write then rename, so a concurrent reader never sees half a file.
+                let temp = self.dir.join(format!("{}.tmp", key));
+                std::fs::write(&temp, json)?;
+                std::fs::rename(&temp, self.path(key))
+            })
+            .and_then(|()| self.evict());
+        if let Err(e) = result {
+            log::warn!("Could not write cache entry {}: {}", key, e);
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    // Removes the oldest entries until the cache fits in `max_bytes`.
+    fn evict(&self) -> std::io::Result<()> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let metadata = entry.metadata()?;
+                entries.push((metadata.modified()?, metadata.len(), path));
+            }
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            std::fs::remove_file(&path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_provider::Message;
+
+    fn request(model: &str, text: &str, temperature: f32) -> LLMRequest {
+        LLMRequest {
+            model: model.to_string(),
+            messages: vec![Message::user(text)],
+            temperature,
+            max_tokens: 100,
+            response_format: None,
+        }
+    }
+
+    #[test]
+    fn test_key_covers_text_model_temperature_and_version() {
+        let base = ResponseCache::key(&request("m", "doc", 0.1), "1");
+        assert_eq!(base, ResponseCache::key(&request("m", "doc", 0.1), "1"));
+        assert_ne!(base, ResponseCache::key(&request("m", "doc 2", 0.1), "1"));
+        assert_ne!(base, ResponseCache::key(&request("n", "doc", 0.1), "1"));
+        assert_ne!(base, ResponseCache::key(&request("m", "doc", 0.2), "1"));
+        assert_ne!(base, ResponseCache::key(&request("m", "doc", 0.1), "2"));
+    }
+
+    #[test]
+    fn test_ttl_refresh_and_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResponseCache::new(dir.path());
+
+        cache.put("a", "model", "first");
+        assert_eq!(cache.get("a").as_deref(), Some("first"));
+        assert_eq!(cache.clone().refreshing().get("a"), None);
+
+        // Backdate the entry so it is older than a zero TTL.
+        let path = dir.path().join("a.json");
+        let mut entry: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        entry["created_at"] = (now() - 10).into();
+        std::fs::write(&path, entry.to_string()).unwrap();
+        assert_eq!(cache.clone().with_ttl(Duration::ZERO).get("a"), None);
+        assert_eq!(cache.get("a").as_deref(), Some("first"));
+
+        // Each entry is ~60 bytes, so only one fits.
+        let small = ResponseCache::new(dir.path()).with_max_bytes(80);
+        std::thread::sleep(Duration::from_millis(20));
+        small.put("b", "model", "second");
+        assert_eq!(small.get("a"), None);
+        assert_eq!(small.get("b").as_deref(), Some("second"));
+    }
+}
@@ -0,0 +1,339 @@
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use financial_llm_poc::batch::DEFAULT_CONCURRENCY;
+use financial_llm_poc::einvoice;
+use financial_llm_poc::ingest::{self, PagedText};
+use rust_decimal::Decimal;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub const EXIT_OK: u8 = 0;
+pub const EXIT_PROCESSING_ERROR: u8 = 1;
+pub const EXIT_VALIDATION_FAILED: u8 = 3;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "financial-llm-poc",
+    about = "Analyze, validate and convert financial documents",
+    after_help = "Exit status: 0 success, 1 processing error, 2 usage error, 3 validation failed"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Extract structured data from documents
+    Analyze(CommandArgs),
+    /// Analyze documents and check them for completeness and consistency
+    Validate(CommandArgs),
+    /// Convert documents into free-form JSON
+    Convert(CommandArgs),
+    /// Score models against a labelled corpus of documents
+    Eval(EvalArgs),
+    /// Run the built-in sample documents (default when no command is given)
+    Demo,
+}
+
+#[derive(Debug, Args)]
+pub struct EvalArgs {
+    /// Directory of `<name>.txt` documents with `<name>.expected.json` labels
+    pub corpus: PathBuf,
+
+    /// Replay recorded completions from this directory instead of calling OpenRouter
+    #[arg(long, conflicts_with = "base_url")]
+    pub recordings: Option<PathBuf>,
+
+    /// Evaluate the models of a local OpenAI-compatible server instead of OpenRouter
+    #[arg(long)]
+    pub base_url: Option<String>,
+
+    /// Model id to evaluate; repeat to compare models (default: the OpenRouter
+    /// models, or every model of a local server)
+    #[arg(long = "model")]
+    pub models: Vec<String>,
+
+    /// API key; defaults to OPENROUTER_API_KEY
+    #[arg(long)]
+    pub api_key: Option<String>,
+
+    /// Largest difference at which two amounts still match
+    #[arg(long, default_value = "0.01")]
+    pub tolerance: Decimal,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CommandArgs {
+    /// Files, directories or glob patterns; `-` or nothing reads stdin
+    pub inputs: Vec<String>,
+
+    /// Backend to use; `auto` picks OpenRouter when a key is set, otherwise rules
+    #[arg(long, value_enum, default_value_t = ProviderKind::Auto)]
+    pub provider: ProviderKind,
+
+    /// Model id; repeat to build a fallback chain
+    #[arg(long = "model")]
+    pub models: Vec<String>,
+
+    /// API key; defaults to OPENROUTER_API_KEY or OPENAI_API_KEY
+    #[arg(long)]
+    pub api_key: Option<String>,
+
+    /// Address of the local server (default: LOCAL_LLM_URL or http://localhost:8080/v1)
+    #[arg(long)]
+    pub base_url: Option<String>,
+
+    /// Output format
+    #[arg(long, short, value_enum, default_value_t = OutputFormat::Pretty)]
+    pub format: OutputFormat,
+
+    /// Maximum number of documents processed at once
+    #[arg(long, short = 'j', default_value_t = DEFAULT_CONCURRENCY)]
+    pub concurrency: usize,
+
+    /// Fail instead of falling back to rule-based extraction when all models fail
+    #[arg(long)]
+    pub no_fallback: bool,
+
+    /// Ask the model for a second opinion on top of the local validation checks
+    #[arg(long)]
+    pub llm_validation: bool,
+
+    /// Send documents verbatim instead of redacting SSNs, EINs, IBANs, card and
+    /// account numbers and email addresses first
+    #[arg(long)]
+    pub no_redact: bool,
+
+    /// Always call the model instead of reusing cached completions
+    #[arg(long, conflicts_with = "refresh")]
+    pub no_cache: bool,
+
+    /// Ignore cached completions but store the fresh ones
+    #[arg(long)]
+    pub refresh: bool,
+
+    /// Where completions are cached (default: ~/.cache/financial-llm-poc)
+    #[arg(long)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Directory of prompt templates (*.toml) overriding the built-in ones
+    #[arg(long)]
+    pub prompts_dir: Option<PathBuf>,
+
+    /// Stop sending requests once this many USD have been spent. Models
+    /// without a price are refused, and so is every request after a
+    /// response that reported no token usage. Requests already in flight
+    /// still complete, so with -j N the total can exceed it by up to N
+    /// requests
+    #[arg(long)]
+    pub budget: Option<Decimal>,
+
+    /// TOML price table (USD per million tokens) overriding the built-in prices
+    #[arg(long)]
+    pub prices: Option<PathBuf>,
+
+    /// Stream completions, showing progress and keeping what arrived if the
+    /// connection drops
+    #[arg(long)]
+    pub stream: bool,
+
+    /// TOML file naming the columns of a bank's CSV export; CSV, OFX and QIF
+    /// statements are imported directly instead of being sent to a model
+    #[arg(long)]
+    pub csv_mapping: Option<PathBuf>,
+
+    /// Write every invoice as a Peppol BIS 3.0 UBL file into this directory,
+    /// checked against the schema, EN 16931 and the Peppol rules. Existing
+    /// files are never overwritten; a name already taken gets a -2, -3, ... suffix
+    #[arg(long)]
+    pub ubl_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ProviderKind {
+    Auto,
+    Openrouter,
+    Openai,
+    /// An OpenAI-compatible server such as llama.cpp or Ollama
+    Local,
+    Rules,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Pretty,
+    Json,
+    Jsonl,
+}
+
+/// A document to process, labelled with where it came from.
+pub struct InputDocument {
+    pub source: String,
+    /// The document's text, or why it could not be read. An unreadable file
+    /// fails on its own instead of stopping the batch.
+    pub content: Result<DocumentText>,
+}
+
+pub struct DocumentText {
+    pub text: String,
+    /// The text by page, for PDFs.
+    pub pages: Option<PagedText>,
+}
+
+impl InputDocument {
+    fn from_bytes(source: String, bytes: Vec<u8>) -> Self {
+        let content = DocumentText::from_bytes(&source, bytes);
+        Self { source, content }
+    }
+}
+
+impl DocumentText {
+    /// Reads PDFs through their text layer and everything else as UTF-8.
+    /// Factur-X and ZUGFeRD PDFs are read through their attached XML.
+    fn from_bytes(source: &str, bytes: Vec<u8>) -> Result<Self> {
+        if ingest::is_pdf(&bytes) {
+            let xml = einvoice::xml_from_pdf(&bytes)
+                .with_context(|| format!("Failed to read the attachments of {}", source))?;
+            if let Some(xml) = xml {
+                return Ok(Self {
+                    text: xml,
+                    pages: PagedText::from_pdf(&bytes).ok(),
+                });
+            }
+            let pages = PagedText::from_pdf(&bytes)
+                .with_context(|| format!("Failed to extract text from {}", source))?;
+            return Ok(Self {
+                text: pages.text(),
+                pages: Some(pages),
+            });
+        }
+        let text = String::from_utf8(bytes)
+            .with_context(|| format!("{} is neither text nor a PDF", source))?;
+        Ok(Self { text, pages: None })
+    }
+}
+
+/// Expands the positional inputs into documents. Directories contribute every
+/// regular file inside them (recursively, sorted); patterns with `*`, `?` or
+/// `[` are expanded as globs.
+pub fn collect_inputs(inputs: &[String]) -> Result<Vec<InputDocument>> {
+    if inputs.is_empty() || inputs == ["-"] {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .context("Failed to read stdin")?;
+        return Ok(vec![InputDocument::from_bytes(
+            "<stdin>".to_string(),
+            bytes,
+        )]);
+    }
+
+    let mut paths = Vec::new();
+    for input in inputs {
+        if input.contains(['*', '?', '[']) {
+            let matches = glob::glob(input).with_context(|| format!("Invalid glob: {}", input))?;
+            let before = paths.len();
+            for entry in matches {
+                let path = entry?;
+                if path.is_file() {
+                    paths.push(path);
+                }
+            }
+            if paths.len() == before {
+                anyhow::bail!("No files match {}", input);
+            }
+        } else {
+            let path = PathBuf::from(input);
+            if path.is_dir() {
+                collect_dir(&path, &mut paths)?;
+            } else if path.is_file() {
+                paths.push(path);
+            } else {
+                anyhow::bail!("No such file or directory: {}", input);
+            }
+        }
+    }
+
+    Ok(paths
+        .into_iter()
+        .map(|path| {
+            let source = path.display().to_string();
+            match std::fs::read(&path) {
+                Ok(bytes) => InputDocument::from_bytes(source, bytes),
+                Err(e) => InputDocument {
+                    content: Err(
+                        anyhow::Error::new(e).context(format!("Failed to read {}", source))
+                    ),
+                    source,
+                },
+            }
+        })
+        .collect())
+}
+
+fn collect_dir(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            collect_dir(&path, paths)?;
+        } else if path.is_file() {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_inputs_from_dirs_and_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        std::fs::write(dir.path().join("b.txt"), "B").unwrap();
+        std::fs::write(dir.path().join("a.txt"), "A").unwrap();
+        std::fs::write(dir.path().join("nested/c.csv"), "C").unwrap();
+
+        let from_dir = collect_inputs(&[dir.path().display().to_string()]).unwrap();
+        let texts: Vec<_> = from_dir
+            .iter()
+            .map(|d| d.content.as_ref().unwrap().text.as_str())
+            .collect();
+        assert_eq!(texts, vec!["A", "B", "C"]);
+
+        let pattern = format!("{}/*.txt", dir.path().display());
+        assert_eq!(collect_inputs(&[pattern]).unwrap().len(), 2);
+
+        let missing = format!("{}/*.pdf", dir.path().display());
+        assert!(collect_inputs(&[missing]).is_err());
+    }
+
+    #[test]
+    fn test_unreadable_file_fails_on_its_own() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "A").unwrap();
+        std::fs::write(dir.path().join("b.bin"), [0xff, 0xfe, 0x00]).unwrap();
+        std::fs::write(dir.path().join("c.txt"), "C").unwrap();
+
+        let inputs = collect_inputs(&[dir.path().display().to_string()]).unwrap();
+
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(inputs[0].content.as_ref().unwrap().text, "A");
+        let error = inputs[1].content.as_ref().err().unwrap();
+        assert!(error
+            .to_string()
+            .ends_with("b.bin is neither text nor a PDF"));
+        assert_eq!(inputs[2].content.as_ref().unwrap().text, "C");
+    }
+}
@@ -0,0 +1,340 @@
+use crate::error::{AnalyzerError, Result};
+use crate::llm_provider::Usage;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+
+const ONE_MILLION: Decimal = Decimal::from_parts(1_000_000, 0, 0, false, 0);
+
+/// USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ModelPrice {
+    pub prompt: Decimal,
+    pub completion: Decimal,
+}
+
+impl ModelPrice {
+    pub fn per_million(prompt: Decimal, completion: Decimal) -> Self {
+        Self { prompt, completion }
+    }
+
+    pub fn cost(&self, usage: &Usage) -> Decimal {
+        (self.prompt * Decimal::from(usage.prompt_tokens)
+            + self.completion * Decimal::from(usage.completion_tokens))
+            / ONE_MILLION
+    }
+}
+
+/// Prices by model id. OpenRouter `:free` models cost nothing; a model with
+/// a provider prefix (`openai/gpt-4o-mini`) falls back to its bare name.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PriceTable {
+    #[serde(default)]
+    models: HashMap<String, ModelPrice>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        Self::new()
+            .with_price(
+                "gpt-4o-mini",
+                ModelPrice::per_million(Decimal::new(15, 2), Decimal::new(60, 2)),
+            )
+            .with_price(
+                "gpt-4o",
+                ModelPrice::per_million(Decimal::new(250, 2), Decimal::new(1000, 2)),
+            )
+    }
+}
+
+impl PriceTable {
+    /// An empty table; see `Default` for the built-in prices.
+    pub fn new() -> Self {
+        Self {
+            models: HashMap::new(),
+        }
+    }
+
+    pub fn with_price(mut self, model: impl Into<String>, price: ModelPrice) -> Self {
+        self.models.insert(model.into(), price);
+        self
+    }
+
+    /// Loads a TOML table such as
+    ///
+    /// ```toml
+    /// [models."gpt-4o-mini"]
+    /// prompt = 0.15
+    /// completion = 0.60
+    /// ```
+    ///
+    /// Listed models override the built-in prices.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let source = std::fs::read_to_string(path)?;
+        let loaded: PriceTable = toml::from_str(&source).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })?;
+        let mut table = Self::default();
+        table.models.extend(loaded.models);
+        Ok(table)
+    }
+
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        if model.ends_with(":free") {
+            return Some(ModelPrice::per_million(Decimal::ZERO, Decimal::ZERO));
+        }
+        self.models.get(model).copied().or_else(|| {
+            let (_, bare) = model.split_once('/')?;
+            self.models.get(bare).copied()
+        })
+    }
+}
+
+/// Tokens and cost of one or more requests.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub requests: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// In USD, for the requests with a known price.
+    pub cost: Decimal,
+    /// Requests to models missing from the price table.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub unpriced_requests: u32,
+    /// Requests to paid models whose response reported no token usage,
+    /// such as streams cut off before the final chunk. Their cost is
+    /// unknown, not zero.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub unmetered_requests: u32,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+impl UsageReport {
+    /// The report for a single request to `model`. A response without a
+    /// `usage` block is counted as unmetered unless the model is free.
+    pub fn for_request(model: &str, usage: Option<Usage>, prices: &PriceTable) -> Self {
+        let price = prices.price(model);
+        if price.is_none() {
+            log::warn!("No price for {}; its cost is not counted", model);
+        }
+        let free = price.is_some_and(|p| p.prompt.is_zero() && p.completion.is_zero());
+        let unmetered = usage.is_none() && price.is_some() && !free;
+        if unmetered {
+            log::warn!("{} reported no token usage; its cost is not counted", model);
+        }
+        let usage = usage.unwrap_or_default();
+        Self {
+            requests: 1,
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            cost: price.map(|p| p.cost(&usage)).unwrap_or_default(),
+            unpriced_requests: u32::from(price.is_none()),
+            unmetered_requests: u32::from(unmetered),
+        }
+    }
+
+    pub fn add(&mut self, other: &UsageReport) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost += other.cost;
+        self.unpriced_requests += other.unpriced_requests;
+        self.unmetered_requests += other.unmetered_requests;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests == 0
+    }
+}
+
+impl fmt::Display for UsageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} request(s), {} prompt + {} completion tokens, ${}",
+            self.requests,
+            self.prompt_tokens,
+            self.completion_tokens,
+            self.cost.round_dp(6).normalize()
+        )?;
+        if self.unpriced_requests > 0 {
+            write!(f, " ({} unpriced)", self.unpriced_requests)?;
+        }
+        if self.unmetered_requests > 0 {
+            write!(f, " ({} without usage)", self.unmetered_requests)?;
+        }
+        Ok(())
+    }
+}
+
+/// Running total of what an analyzer has spent, with an optional ceiling.
+#[derive(Debug, Default)]
+pub struct CostLedger {
+    prices: PriceTable,
+    budget: Option<Decimal>,
+    total: Mutex<UsageReport>,
+}
+
+impl CostLedger {
+    pub fn with_prices(mut self, prices: PriceTable) -> Self {
+        self.prices = prices;
+        self
+    }
+
+    /// Refuses further requests once `budget` USD has been spent, any
+    /// request to a model without a price, and every request after one
+    /// whose response reported no token usage. The budget is checked before
+    /// each request, and requests already in flight when the ceiling is
+    /// reached still complete: with N documents analysed concurrently the
+    /// total can end up N requests above it.
+    pub fn with_budget(mut self, budget: Decimal) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Fails before a request to `model` with `BudgetExceeded` once the
+    /// budget is used up, with `UnpricedModel` when a budget is set and
+    /// the model's cost cannot be counted, or with `UnmeteredRequests` once
+    /// a request's cost went uncounted for lack of usage.
+    pub fn check_budget(&self, model: &str) -> Result<()> {
+        let Some(budget) = self.budget else {
+            return Ok(());
+        };
+        if self.prices.price(model).is_none() {
+            return Err(AnalyzerError::UnpricedModel {
+                model: model.to_string(),
+            });
+        }
+        let total = self.total.lock().unwrap();
+        if total.unmetered_requests > 0 {
+            return Err(AnalyzerError::UnmeteredRequests {
+                requests: total.unmetered_requests,
+            });
+        }
+        let spent = total.cost;
+        if spent >= budget {
+            return Err(AnalyzerError::BudgetExceeded { spent, budget });
+        }
+        Ok(())
+    }
+
+    /// Adds a finished request to the total and returns its own report.
+    pub fn record(&self, model: &str, usage: Option<Usage>) -> UsageReport {
+        let report = UsageReport::for_request(model, usage, &self.prices);
+        self.total.lock().unwrap().add(&report);
+        report
+    }
+
+    pub fn total(&self) -> UsageReport {
+        self.total.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: u64, completion_tokens: u64) -> Option<Usage> {
+        Some(Usage {
+            prompt_tokens,
+            completion_tokens,
+        })
+    }
+
+    #[test]
+    fn test_price_lookup_and_cost() {
+        let prices = PriceTable::default();
+        let report = UsageReport::for_request("openai/gpt-4o-mini", usage(1000, 500), &prices);
+        // 1000 * 0.15 / 1M + 500 * 0.60 / 1M
+        assert_eq!(report.cost, Decimal::new(45, 5));
+
+        let free = UsageReport::for_request(
+            "meta-llama/llama-3.2-3b-instruct:free",
+            usage(1000, 500),
+            &prices,
+        );
+        assert_eq!(free.cost, Decimal::ZERO);
+        assert_eq!(free.unpriced_requests, 0);
+
+        let unknown = UsageReport::for_request("mystery", usage(10, 10), &prices);
+        assert_eq!(unknown.unpriced_requests, 1);
+        assert_eq!(
+            unknown.to_string(),
+            "1 request(s), 10 prompt + 10 completion tokens, $0 (1 unpriced)"
+        );
+    }
+
+    #[test]
+    fn test_load_overrides_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prices.toml");
+        std::fs::write(
+            &path,
+            "[models.\"gpt-4o-mini\"]\nprompt = 1\ncompletion = 2.5\n\n[models.local]\nprompt = 0\ncompletion = 0\n",
+        )
+        .unwrap();
+
+        let prices = PriceTable::load(&path).unwrap();
+
+        assert_eq!(
+            prices.price("gpt-4o-mini"),
+            Some(ModelPrice::per_million(Decimal::ONE, Decimal::new(25, 1)))
+        );
+        assert!(prices.price("local").is_some());
+        assert!(prices.price("gpt-4o").is_some());
+    }
+
+    #[test]
+    fn test_budget_ceiling() {
+        let ledger = CostLedger::default().with_budget(Decimal::new(1, 3));
+
+        ledger.record("gpt-4o", usage(100, 50));
+        assert!(ledger.check_budget("gpt-4o").is_ok());
+        // An unpriced model would spend money the ledger cannot count.
+        assert!(matches!(
+            ledger.check_budget("mystery"),
+            Err(AnalyzerError::UnpricedModel { .. })
+        ));
+        assert!(CostLedger::default().check_budget("mystery").is_ok());
+        ledger.record("gpt-4o", usage(100, 50));
+
+        assert!(matches!(
+            ledger.check_budget("gpt-4o"),
+            Err(AnalyzerError::BudgetExceeded { .. })
+        ));
+        assert_eq!(ledger.total().requests, 2);
+    }
+
+    #[test]
+    fn test_missing_usage_is_not_free() {
+        let ledger = CostLedger::default().with_budget(Decimal::ONE);
+
+        // A free model costs nothing with or without usage.
+        ledger.record("meta-llama/llama-3.2-3b-instruct:free", None);
+        assert!(ledger.check_budget("gpt-4o").is_ok());
+
+        let report = ledger.record("gpt-4o", None);
+        assert_eq!(report.unmetered_requests, 1);
+        assert_eq!(
+            report.to_string(),
+            "1 request(s), 0 prompt + 0 completion tokens, $0 (1 without usage)"
+        );
+        assert!(matches!(
+            ledger.check_budget("gpt-4o"),
+            Err(AnalyzerError::UnmeteredRequests { requests: 1 })
+        ));
+        // Without a budget the request is only reported.
+        let unlimited = CostLedger::default();
+        unlimited.record("gpt-4o", None);
+        assert!(unlimited.check_budget("gpt-4o").is_ok());
+    }
+}
@@ -0,0 +1,336 @@
+use crate::document_types::FinancialDocument;
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::sync::LazyLock;
+
+const TEXT_FORMATS: &[&str] = &[
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%B %d, %Y",
+    "%B %d %Y",
+    "%b %d, %Y",
+    "%b %d %Y",
+    "%d %B %Y",
+    "%d %B, %Y",
+    "%d %b %Y",
+    "%d %b, %Y",
+    "%d.%m.%Y",
+];
+
+static ORDINAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(\d{1,2})(st|nd|rd|th)\b").unwrap());
+static NUMERIC: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\d{1,2})[/-](\d{1,2})[/-](\d{2}|\d{4})$").unwrap());
+static SAME_MONTH_RANGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^([A-Za-z]+)\.? (\d{1,2})\s*[-–—]\s*(\d{1,2}),? (\d{4})$").unwrap()
+});
+static RANGE_SEPARATOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\s+(?:to|through|until)\s+|\s*[–—]\s*|\s+-\s+").unwrap());
+static YEAR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(\d{4})$").unwrap());
+static YEAR_MONTH: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\d{4})-(\d{2})$").unwrap());
+
+/// A parsed date. `alternative` holds the other reading of an ambiguous
+/// numeric date such as `03/04/2024`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParsedDate {
+    pub date: NaiveDate,
+    pub alternative: Option<NaiveDate>,
+}
+
+impl ParsedDate {
+    pub fn is_ambiguous(&self) -> bool {
+        self.alternative.is_some()
+    }
+}
+
+/// An inclusive date range, written as an ISO-8601 interval
+/// (`2024-01-01/2024-01-31`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl DateRange {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end
+    }
+
+    fn month(year: i32, month: u32) -> Option<Self> {
+        let start = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let next = if month == 12 {
+            NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+        } else {
+            NaiveDate::from_ymd_opt(year, month + 1, 1)?
+        };
+        Some(DateRange {
+            start,
+            end: next.pred_opt()?,
+        })
+    }
+}
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.start, self.end)
+    }
+}
+
+fn clean(input: &str) -> String {
+    let text = ORDINAL.replace_all(input.trim(), "$1");
+    text.replace('.', ". ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace(" ,", ",")
+        .replace(". ", " ")
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// Parses the date formats seen in financial documents. Numeric dates where
+/// both readings are valid are read US-style (month first) and flagged with
+/// the day-first alternative.
+pub fn parse_date(input: &str) -> Option<ParsedDate> {
+    let text = clean(input);
+
+    if let Some(captures) = NUMERIC.captures(&text) {
+        let first: u32 = captures[1].parse().ok()?;
+        let second: u32 = captures[2].parse().ok()?;
+        let mut year: i32 = captures[3].parse().ok()?;
+        if captures[3].len() == 2 {
+            year += 2000;
+        }
+
+        let month_first = NaiveDate::from_ymd_opt(year, first, second);
+        let day_first = NaiveDate::from_ymd_opt(year, second, first);
+        return match (month_first, day_first) {
+            (Some(date), Some(alternative)) if date != alternative => Some(ParsedDate {
+                date,
+                alternative: Some(alternative),
+            }),
+            (Some(date), _) | (None, Some(date)) => Some(ParsedDate {
+                date,
+                alternative: None,
+            }),
+            (None, None) => None,
+        };
+    }
+
+    // "15.01.2024" is day-first by convention, so it is not ambiguous.
+    let dotted = input.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(dotted, "%d.%m.%Y") {
+        return Some(ParsedDate {
+            date,
+            alternative: None,
+        });
+    }
+
+    TEXT_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(&text, format).ok())
+        .map(|date| ParsedDate {
+            date,
+            alternative: None,
+        })
+}
+
+/// Parses statement periods such as `Jan 1-31, 2024`,
+/// `January 1 - February 29, 2024`, `2024-01-01 to 2024-01-31` or a whole
+/// month like `January 2024`.
+pub fn parse_period(input: &str) -> Option<DateRange> {
+    // Already normalized: "2024-01-01/2024-01-31"
+    if let Some((start, end)) = input.trim().split_once('/') {
+        if let (Ok(start), Ok(end)) = (
+            NaiveDate::parse_from_str(start, "%Y-%m-%d"),
+            NaiveDate::parse_from_str(end, "%Y-%m-%d"),
+        ) {
+            return ordered(start, end);
+        }
+    }
+
+    let text = clean(input);
+
+    if let Some(captures) = SAME_MONTH_RANGE.captures(&text) {
+        let start = parse_date(&format!(
+            "{} {}, {}",
+            &captures[1], &captures[2], &captures[4]
+        ))?;
+        let end = parse_date(&format!(
+            "{} {}, {}",
+            &captures[1], &captures[3], &captures[4]
+        ))?;
+        return ordered(start.date, end.date);
+    }
+
+    let parts: Vec<&str> = RANGE_SEPARATOR.splitn(&text, 2).collect();
+    if let [left, right] = parts.as_slice() {
+        let end = parse_date(right)?;
+        // "January 1 - February 29, 2024": borrow the year from the end.
+        let start = parse_date(left).or_else(|| {
+            let year = YEAR.captures(right)?;
+            parse_date(&format!("{}, {}", left.trim_end_matches(','), &year[1]))
+        })?;
+        return ordered(start.date, end.date);
+    }
+
+    if let Some(captures) = YEAR_MONTH.captures(&text) {
+        return DateRange::month(captures[1].parse().ok()?, captures[2].parse().ok()?);
+    }
+    let (month, year) = text.trim_end_matches(',').rsplit_once(' ')?;
+    let first_day = format!("{} 1 {}", month.trim_end_matches(','), year);
+    ["%B %d %Y", "%b %d %Y"].iter().find_map(|format| {
+        let date = NaiveDate::parse_from_str(&first_day, format).ok()?;
+        DateRange::month(date.year(), date.month())
+    })
+}
+
+fn ordered(start: NaiveDate, end: NaiveDate) -> Option<DateRange> {
+    (start <= end).then_some(DateRange { start, end })
+}
+
+fn is_date_key(key: &str) -> bool {
+    key == "date" || key.ends_with("_date") || key.starts_with("date_")
+}
+
+fn is_period_key(key: &str) -> bool {
+    key.contains("period")
+}
+
+/// Rewrites date and period fields in `extracted_data` to ISO-8601, fills
+/// `metadata.document_date` and `metadata.period`, and adds a validation
+/// error for every ambiguous date.
+pub fn normalize_dates(document: &mut FinancialDocument) {
+    let mut keys: Vec<String> = document.extracted_data.keys().cloned().collect();
+    keys.sort();
+
+    for key in keys {
+        let value = document.extracted_data[&key].clone();
+
+        if is_period_key(&key) {
+            if let Some(range) = parse_period(&value) {
+                document.extracted_data.insert(key, range.to_string());
+                document.metadata.period.get_or_insert(range);
+            }
+        } else if is_date_key(&key) {
+            let Some(parsed) = parse_date(&value) else {
+                continue;
+            };
+            if let Some(alternative) = parsed.alternative {
+                document.validation_errors.push(format!(
+                    "Ambiguous date in {}: {} (read as {}, could be {})",
+                    key, value, parsed.date, alternative
+                ));
+            }
+            document
+                .extracted_data
+                .insert(key.clone(), parsed.date.to_string());
+            if key == "date" || key == "document_date" {
+                document.metadata.document_date.get_or_insert(parsed.date);
+            }
+        }
+    }
+}
+
+/// Deserializes a date in any format `parse_date` understands; unparseable
+/// values become `None` rather than failing the whole document.
+pub fn deserialize_lenient_date<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(value
+        .as_deref()
+        .and_then(parse_date)
+        .map(|parsed| parsed.date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_parse_date_formats() {
+        let cases = [
+            ("January 15, 2024", ymd(2024, 1, 15)),
+            ("2024-01-20", ymd(2024, 1, 20)),
+            ("Feb. 14th, 2024", ymd(2024, 2, 14)),
+            ("14 February 2024", ymd(2024, 2, 14)),
+            ("01/31/2024", ymd(2024, 1, 31)),
+            ("31/01/2024", ymd(2024, 1, 31)),
+            ("15.01.2024", ymd(2024, 1, 15)),
+        ];
+        for (input, expected) in cases {
+            let parsed = parse_date(input).unwrap_or_else(|| panic!("{}", input));
+            assert_eq!(parsed.date, expected, "{}", input);
+            assert!(!parsed.is_ambiguous(), "{}", input);
+        }
+        assert!(parse_date("next Tuesday").is_none());
+    }
+
+    #[test]
+    fn test_ambiguous_numeric_date() {
+        let parsed = parse_date("03/04/2024").unwrap();
+        assert_eq!(parsed.date, ymd(2024, 3, 4));
+        assert_eq!(parsed.alternative, Some(ymd(2024, 4, 3)));
+    }
+
+    #[test]
+    fn test_parse_period_formats() {
+        let january = DateRange {
+            start: ymd(2024, 1, 1),
+            end: ymd(2024, 1, 31),
+        };
+        assert_eq!(parse_period("Jan 1-31, 2024"), Some(january));
+        assert_eq!(parse_period("2024-01-01 to 2024-01-31"), Some(january));
+        assert_eq!(parse_period("January 2024"), Some(january));
+        assert_eq!(parse_period("2024-01"), Some(january));
+        assert_eq!(
+            parse_period("January 1 - February 29, 2024"),
+            Some(DateRange {
+                start: ymd(2024, 1, 1),
+                end: ymd(2024, 2, 29),
+            })
+        );
+        assert_eq!(january.to_string(), "2024-01-01/2024-01-31");
+        assert_eq!(parse_period(&january.to_string()), Some(january));
+    }
+
+    #[test]
+    fn test_normalize_dates_rewrites_fields() {
+        let legacy = crate::LegacyFinancialDocument {
+            document_type: "Bank Statement".to_string(),
+            confidence: 0.9,
+            extracted_data: [
+                ("date", "03/04/2024"),
+                ("period", "Jan 1-31, 2024"),
+                ("due_date", "Feb 14th, 2024"),
+            ]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+            validation_errors: vec![],
+            suggested_categories: vec![],
+            document_insights: vec![],
+        };
+
+        let document = FinancialDocument::from(legacy);
+
+        assert_eq!(document.extracted_data["date"], "2024-03-04");
+        assert_eq!(document.extracted_data["due_date"], "2024-02-14");
+        assert_eq!(document.extracted_data["period"], "2024-01-01/2024-01-31");
+        assert_eq!(document.metadata.document_date, Some(ymd(2024, 3, 4)));
+        assert_eq!(document.metadata.period.unwrap().end, ymd(2024, 1, 31));
+        assert_eq!(
+            document.validation_errors,
+            vec!["Ambiguous date in date: 03/04/2024 (read as 2024-03-04, could be 2024-04-03)"]
+        );
+    }
+}
@@ -1,19 +1,55 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use crate::cost::UsageReport;
+use crate::dates::{deserialize_lenient_date, normalize_dates, DateRange};
+use crate::fields::DocumentFields;
+use crate::money::{Currency, Money};
+use crate::schema::schema_from_json;
+use crate::transactions::reconcile;
+use chrono::NaiveDate;
+use regex::Regex;
+use rust_decimal::Decimal;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::LazyLock;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct FinancialDocument {
     pub document_type: DocumentType,
     pub confidence: f32,
+    /// Raw extracted values, as the model or rules stated them; `fields`
+    /// holds them typed.
     pub extracted_data: HashMap<String, String>,
     pub validation_errors: Vec<String>,
     pub suggested_categories: Vec<String>,
     pub tax_implications: Vec<String>,
     pub risk_assessment: RiskLevel,
     pub metadata: DocumentMetadata,
+    #[serde(default)]
+    pub document_insights: Vec<String>,
+    /// Prompt template that produced this analysis, e.g. `analysis@1`.
+    /// Set by the analyzer, so it is not part of the schema sent to models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub prompt_version: Option<String>,
+    /// Tokens and cost spent producing this analysis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub usage: Option<UsageReport>,
+    /// `extracted_data` as the typed fields of the document type, such as
+    /// `InvoiceFields::due_date`. Kept in step by `normalize`; derived, so
+    /// it is neither asked of models nor read back from JSON.
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub fields: DocumentFields,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Deserializes leniently from model output: see `DocumentType::from_label`
+/// and `RiskLevel::from_label`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
 pub enum DocumentType {
     Invoice,
     Receipt,
@@ -23,10 +59,12 @@ pub enum DocumentType {
     Bill,
     PaymentConfirmation,
     Payroll,
+    /// A label that matches none of the known types, kept verbatim.
+    Other(String),
     Unknown,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RiskLevel {
     Low,
     Medium,
@@ -34,28 +72,97 @@ pub enum RiskLevel {
     Critical,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct DocumentMetadata {
-    pub document_date: Option<String>,
-    pub total_amount: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_lenient_date")]
+    #[schemars(with = "Option<String>")]
+    pub document_date: Option<NaiveDate>,
+    /// Statement or pay period, for documents that cover a date range.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period: Option<DateRange>,
+    pub total_amount: Option<Money>,
     pub currency: Option<String>,
     pub parties: Vec<Party>,
     pub line_items: Vec<LineItem>,
+    /// Statement lines, for bank statements.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transactions: Vec<Transaction>,
+    /// Number of pages of the source PDF.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(skip)]
+    pub page_count: Option<u32>,
+    /// The source page each value was found on, keyed by its
+    /// `extracted_data` key, `line_items[i]` or `transactions[i]`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schemars(skip)]
+    pub source_pages: BTreeMap<String, u32>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Party {
     pub role: String, // "payer", "payee", "employee", "employer"
     pub name: String,
     pub identifier: Option<String>, // SSN, EIN, Account number
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct LineItem {
     pub description: String,
-    pub quantity: Option<f64>,
-    pub unit_price: Option<f64>,
-    pub amount: f64,
+    #[schemars(schema_with = "crate::schema::decimal_schema")]
+    pub quantity: Option<Decimal>,
+    pub unit_price: Option<Money>,
+    pub amount: Money,
+    /// VAT or sales tax rate in percent, as stated for the line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::schema::decimal_schema")]
+    pub tax_rate: Option<Decimal>,
+    /// UNCL5305 VAT category code (`S`, `Z`, `E`, `AE`, ...), as stated for
+    /// the line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tax_category: Option<String>,
+}
+
+/// One statement line. Exactly one of `debit` and `credit` is normally set;
+/// `balance` is the running balance after the line, when the statement
+/// shows it.
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema, PartialEq)]
+pub struct Transaction {
+    #[serde(default, deserialize_with = "deserialize_lenient_date")]
+    #[schemars(with = "Option<String>")]
+    pub date: Option<NaiveDate>,
+    pub description: String,
+    #[serde(default)]
+    pub debit: Option<Money>,
+    #[serde(default)]
+    pub credit: Option<Money>,
+    #[serde(default)]
+    pub balance: Option<Money>,
+    /// The bank's or the payer's reference for the payment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+}
+
+impl Transaction {
+    /// Credit minus debit; `None` if they are in different currencies.
+    pub fn net(&self) -> Option<Money> {
+        let zero = Money::zero(Currency::unknown());
+        self.credit
+            .as_ref()
+            .unwrap_or(&zero)
+            .checked_sub(self.debit.as_ref().unwrap_or(&zero))
+    }
+}
+
+/// Shape returned by the original OpenRouter prompt: a free-form document type
+/// and AI insights, but no risk assessment or structured metadata.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LegacyFinancialDocument {
+    pub document_type: String,
+    pub confidence: f32,
+    pub extracted_data: HashMap<String, String>,
+    pub validation_errors: Vec<String>,
+    pub suggested_categories: Vec<String>,
+    pub document_insights: Vec<String>, // AI insights
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -67,15 +174,389 @@ pub struct ValidationResult {
     pub overall_score: f32,
 }
 
+// Synonyms checked in order against the normalized label, so "payment
+// receipt" is a receipt and "tax invoice" an invoice.
+const DOCUMENT_TYPE_SYNONYMS: &[(&[&str], DocumentType)] = &[
+    (&["invoice"], DocumentType::Invoice),
+    (&["receipt", "sales slip"], DocumentType::Receipt),
+    (
+        &["bank", "account statement", "statement of account"],
+        DocumentType::BankStatement,
+    ),
+    (&["contract", "agreement"], DocumentType::Contract),
+    (
+        &[
+            "payslip", "pay slip", "pay stub", "paystub", "payroll", "salary",
+        ],
+        DocumentType::Payroll,
+    ),
+    (
+        &["payment", "remittance"],
+        DocumentType::PaymentConfirmation,
+    ),
+    (&["bill", "utility"], DocumentType::Bill),
+];
+
+const TAX_FORM_LABELS: &[&str] = &["tax form", "tax return", "tax statement"];
+
+static TAX_FORM_CODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(w-?\d{1,2}|10(?:99|98|40)(?:-[a-z]+)?)\b").expect("valid form pattern")
+});
+
+impl DocumentType {
+    /// Maps a free-form label such as "Bank Statement", "BankStatement",
+    /// "Tax Form W-2" or "1099-misc". Labels that match no known type are
+    /// kept as `Other`.
+    pub fn from_label(label: &str) -> Self {
+        let label = label.trim();
+        let normalized = normalize_label(label);
+
+        if matches!(normalized.as_str(), "" | "unknown" | "n/a" | "none") {
+            return DocumentType::Unknown;
+        }
+
+        if let Some(code) = TAX_FORM_CODE.captures(label) {
+            let code = code[1].to_uppercase();
+            // "W2" -> "W-2"
+            let code = match code.strip_prefix('W') {
+                Some(number) if !number.starts_with('-') => format!("W-{}", number),
+                _ => code,
+            };
+            return DocumentType::TaxForm(code);
+        }
+        if let Some((_, document_type)) = DOCUMENT_TYPE_SYNONYMS
+            .iter()
+            .find(|(synonyms, _)| synonyms.iter().any(|s| normalized.contains(s)))
+        {
+            return document_type.clone();
+        }
+        // "Tax Form" with no code, or a form named rather than numbered,
+        // such as "Tax Return". "Tax Invoice" was caught above.
+        if TAX_FORM_LABELS.iter().any(|l| normalized.contains(l)) {
+            return DocumentType::TaxForm(match normalized.strip_prefix("tax form") {
+                Some(rest) => rest.trim().to_uppercase(),
+                None => label.to_string(),
+            });
+        }
+
+        if normalized.contains("statement") {
+            DocumentType::BankStatement
+        } else {
+            DocumentType::Other(label.to_string())
+        }
+    }
+}
+
+impl RiskLevel {
+    /// Reads "low", "HIGH RISK", "moderate" or a numeric score. A hedged
+    /// answer such as "Low|Medium" resolves to the higher level.
+    pub fn from_label(label: &str) -> Option<Self> {
+        if let Ok(score) = label.trim().parse::<f64>() {
+            return RiskLevel::from_score(score);
+        }
+        normalize_label(label)
+            .replace(" or ", "|")
+            .replace(" to ", "|")
+            .split(['|', '/', ','])
+            .filter_map(|part| {
+                let part = part.trim();
+                if ["critical", "severe", "extreme"]
+                    .iter()
+                    .any(|w| part.contains(w))
+                {
+                    Some(RiskLevel::Critical)
+                } else if ["high", "elevated"].iter().any(|w| part.contains(w)) {
+                    Some(RiskLevel::High)
+                } else if ["medium", "moderate"].iter().any(|w| part.contains(w)) {
+                    Some(RiskLevel::Medium)
+                } else if ["low", "minimal", "negligible", "none"]
+                    .iter()
+                    .any(|w| part.contains(w))
+                {
+                    Some(RiskLevel::Low)
+                } else {
+                    None
+                }
+            })
+            .max()
+    }
+
+    /// Maps a score from 0 (no risk) to 1 (certain) onto the four levels, in
+    /// quarters. Scores outside that range, such as a ten-point or
+    /// percentage scale, are rejected rather than guessed at: no single
+    /// reading of 2 or 40 is right for every model.
+    pub fn from_score(score: f64) -> Option<Self> {
+        if !(0.0..=1.0).contains(&score) {
+            return None;
+        }
+        Some(if score < 0.25 {
+            RiskLevel::Low
+        } else if score < 0.5 {
+            RiskLevel::Medium
+        } else if score < 0.75 {
+            RiskLevel::High
+        } else {
+            RiskLevel::Critical
+        })
+    }
+}
+
+// "BankStatement" / "bank_statement" / "Bank-Statement" -> "bank statement"
+fn normalize_label(label: &str) -> String {
+    let mut normalized = String::with_capacity(label.len() + 4);
+    let mut previous = ' ';
+    for c in label.trim().chars() {
+        if c.is_uppercase() && previous.is_lowercase() {
+            normalized.push(' ');
+        }
+        normalized.extend(c.to_lowercase());
+        previous = c;
+    }
+    normalized.replace('_', " ")
+}
+
+/// Accepts a label in any form `from_label` understands, or the tagged form
+/// produced by `Serialize`, e.g. `{"TaxForm": "W-2"}`.
+impl<'de> Deserialize<'de> for DocumentType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Label(String),
+            Tagged(HashMap<String, String>),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Label(label) => DocumentType::from_label(&label),
+            Repr::Tagged(tagged) => {
+                let (tag, value) = tagged
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| de::Error::custom("empty document type"))?;
+                match tag.as_str() {
+                    "Other" => DocumentType::from_label(&value),
+                    "TaxForm" => DocumentType::TaxForm(value),
+                    _ => DocumentType::from_label(&format!("{} {}", tag, value)),
+                }
+            }
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for RiskLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Score(f64),
+            Label(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Score(score) => RiskLevel::from_score(score)
+                .ok_or_else(|| de::Error::custom(format!("risk score out of range: {}", score))),
+            Repr::Label(label) => RiskLevel::from_label(&label)
+                .ok_or_else(|| de::Error::custom(format!("unknown risk level: {}", label))),
+        }
+    }
+}
+
+impl JsonSchema for DocumentType {
+    fn schema_name() -> String {
+        "DocumentType".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        schema_from_json(json!({
+            "description": "Invoice, Receipt, BankStatement, Contract, Bill, PaymentConfirmation, Payroll or Unknown; tax forms as {\"TaxForm\": \"W-2\"}",
+            "anyOf": [
+                {"type": "string"},
+                {
+                    "type": "object",
+                    "minProperties": 1,
+                    "maxProperties": 1,
+                    "additionalProperties": {"type": "string"}
+                }
+            ]
+        }))
+    }
+}
+
+impl JsonSchema for RiskLevel {
+    fn schema_name() -> String {
+        "RiskLevel".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        schema_from_json(json!({
+            "description": "Low, Medium, High or Critical, or a score from 0 to 1",
+            "anyOf": [
+                {"type": "string"},
+                {"type": "number", "minimum": 0, "maximum": 1}
+            ]
+        }))
+    }
+}
+
+impl DocumentMetadata {
+    pub fn currency(&self) -> Option<Currency> {
+        self.currency.as_deref().and_then(Currency::new)
+    }
+
+    /// Exact sum of all line item amounts; `None` when there are no line
+    /// items or they mix currencies.
+    pub fn line_items_total(&self) -> Option<Money> {
+        if self.line_items.is_empty() {
+            return None;
+        }
+        Money::sum(self.line_items.iter().map(|item| &item.amount))
+    }
+}
+
+impl From<LegacyFinancialDocument> for FinancialDocument {
+    fn from(legacy: LegacyFinancialDocument) -> Self {
+        let data = &legacy.extracted_data;
+
+        let total_amount = ["total_amount", "total"]
+            .iter()
+            .find_map(|key| data.get(*key))
+            .and_then(|value| Money::parse(value).ok());
+
+        // Statements have no total, so their balances can also tell us the
+        // currency.
+        let currency = data.get("currency").cloned().or_else(|| {
+            [
+                "total_amount",
+                "total",
+                "ending_balance",
+                "beginning_balance",
+            ]
+            .iter()
+            .filter_map(|key| Money::parse(data.get(*key)?).ok())
+            .find(|money| !money.currency.is_unknown())
+            .map(|money| money.currency.to_string())
+        });
+
+        let mut parties = Vec::new();
+        for (key, role) in [
+            ("vendor", "payee"),
+            ("store", "payee"),
+            ("client", "payer"),
+            ("employer", "employer"),
+            ("employee", "employee"),
+        ] {
+            if let Some(name) = data.get(key) {
+                let identifier = match role {
+                    "employer" => data.get("employer_ein").cloned(),
+                    _ => None,
+                };
+                parties.push(Party {
+                    role: role.to_string(),
+                    name: name.clone(),
+                    identifier,
+                });
+            }
+        }
+
+        let metadata = DocumentMetadata {
+            document_date: None,
+            period: None,
+            total_amount,
+            currency,
+            parties,
+            line_items: Vec::new(),
+            transactions: Vec::new(),
+            page_count: None,
+            source_pages: BTreeMap::new(),
+        };
+
+        let mut document = FinancialDocument {
+            document_type: DocumentType::from_label(&legacy.document_type),
+            confidence: legacy.confidence,
+            extracted_data: legacy.extracted_data,
+            validation_errors: legacy.validation_errors,
+            suggested_categories: legacy.suggested_categories,
+            tax_implications: Vec::new(),
+            // The legacy prompt never asked for a risk assessment.
+            risk_assessment: RiskLevel::Low,
+            metadata,
+            document_insights: legacy.document_insights,
+            prompt_version: None,
+            usage: None,
+            fields: DocumentFields::default(),
+        };
+        document.normalize();
+        document
+    }
+}
+
 impl FinancialDocument {
+    /// Parses an amount stored in `extracted_data`, such as `"$2,750.00"`.
+    /// Amounts without a currency take the document's currency.
+    pub fn amount(&self, key: &str) -> Option<Money> {
+        let money = Money::parse(self.extracted_data.get(key)?).ok()?;
+        Some(match self.metadata.currency() {
+            Some(currency) => money.with_default_currency(&currency),
+            None => money,
+        })
+    }
+
+    /// Brings model or rule output into canonical form: currencies resolved,
+    /// dates rewritten as ISO-8601, statement balances reconciled and the
+    /// typed fields read.
+    pub fn normalize(&mut self) {
+        self.resolve_currencies();
+        normalize_dates(self);
+        self.refresh_fields();
+        reconcile(self);
+    }
+
+    /// Gives amounts that were stated without a currency the document's
+    /// currency, so they can be summed and compared.
+    pub fn resolve_currencies(&mut self) {
+        let Some(currency) = self.metadata.currency() else {
+            return;
+        };
+        let metadata = &mut self.metadata;
+        if let Some(total) = metadata.total_amount.take() {
+            metadata.total_amount = Some(total.with_default_currency(&currency));
+        }
+        for item in &mut metadata.line_items {
+            item.amount = item.amount.clone().with_default_currency(&currency);
+            if let Some(price) = item.unit_price.take() {
+                item.unit_price = Some(price.with_default_currency(&currency));
+            }
+        }
+        for transaction in &mut metadata.transactions {
+            for amount in [
+                &mut transaction.debit,
+                &mut transaction.credit,
+                &mut transaction.balance,
+            ] {
+                if let Some(money) = amount.take() {
+                    *amount = Some(money.with_default_currency(&currency));
+                }
+            }
+        }
+    }
+
     pub fn pretty_print(&self) {
         println!("📋 Document Type: {:?}", self.document_type);
         println!("🎯 Confidence: {:.1}%", self.confidence * 100.0);
         println!("⚠️  Risk Level: {:?}", self.risk_assessment);
+        if let Some(version) = &self.prompt_version {
+            println!("📝 Prompt: {}", version);
+        }
+        if let Some(usage) = &self.usage {
+            println!("💵 Usage: {}", usage);
+        }
 
         println!("\n💰 Extracted Data:");
         for (key, value) in &self.extracted_data {
-            println!("   • {}: {}", key, value);
+            match self.metadata.source_pages.get(key) {
+                Some(page) => println!("   • {}: {} (p. {})", key, value, page),
+                None => println!("   • {}: {}", key, value),
+            }
         }
 
         if !self.validation_errors.is_empty() {
@@ -99,16 +580,29 @@ impl FinancialDocument {
             }
         }
 
+        if !self.document_insights.is_empty() {
+            println!("\n💡 Insights:");
+            for insight in &self.document_insights {
+                println!("   • {}", insight);
+            }
+        }
+
         println!("\n📅 Metadata:");
         if let Some(date) = &self.metadata.document_date {
             println!("   • Date: {}", date);
         }
-        if let Some(amount) = self.metadata.total_amount {
-            println!("   • Total Amount: ${:.2}", amount);
+        if let Some(period) = &self.metadata.period {
+            println!("   • Period: {} to {}", period.start, period.end);
+        }
+        if let Some(amount) = &self.metadata.total_amount {
+            println!("   • Total Amount: {}", amount);
         }
         if let Some(currency) = &self.metadata.currency {
             println!("   • Currency: {}", currency);
         }
+        if let Some(pages) = self.metadata.page_count {
+            println!("   • Pages: {}", pages);
+        }
 
         if !self.metadata.parties.is_empty() {
             println!("   • Parties:");
@@ -120,13 +614,56 @@ impl FinancialDocument {
         if !self.metadata.line_items.is_empty() {
             println!("   • Line Items:");
             for item in &self.metadata.line_items {
-                println!("     - {}: ${:.2}", item.description, item.amount);
+                println!("     - {}: {}", item.description, item.amount);
+            }
+        }
+
+        if !self.metadata.transactions.is_empty() {
+            println!("   • Transactions:");
+            for transaction in &self.metadata.transactions {
+                let date = transaction
+                    .date
+                    .map(|date| date.to_string())
+                    .unwrap_or_default();
+                let movement = match (&transaction.credit, &transaction.debit) {
+                    (Some(credit), _) => format!("+{}", credit),
+                    (None, Some(debit)) => format!("-{}", debit),
+                    (None, None) => String::new(),
+                };
+                let reference = transaction
+                    .reference
+                    .as_ref()
+                    .map(|reference| format!(" [{}]", reference))
+                    .unwrap_or_default();
+                println!(
+                    "     - {} {}: {}{}",
+                    date, transaction.description, movement, reference
+                );
             }
         }
     }
 }
 
 impl ValidationResult {
+    /// Combines two assessments of the same document: findings are merged
+    /// and the stricter verdict and score win.
+    pub fn merge(mut self, other: ValidationResult) -> Self {
+        for (findings, extra) in [
+            (&mut self.missing_fields, other.missing_fields),
+            (&mut self.data_quality_issues, other.data_quality_issues),
+            (&mut self.compliance_issues, other.compliance_issues),
+        ] {
+            for finding in extra {
+                if !findings.contains(&finding) {
+                    findings.push(finding);
+                }
+            }
+        }
+        self.is_valid &= other.is_valid;
+        self.overall_score = self.overall_score.min(other.overall_score);
+        self
+    }
+
     pub fn pretty_print(&self) {
         println!("\n🔍 Validation Results:");
         println!("   Overall Score: {:.1}%", self.overall_score * 100.0);
@@ -153,4 +690,158 @@ impl ValidationResult {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_type_from_label() {
+        assert_eq!(
+            DocumentType::from_label("Bank Statement"),
+            DocumentType::BankStatement
+        );
+        assert_eq!(DocumentType::from_label("invoice"), DocumentType::Invoice);
+        assert_eq!(
+            DocumentType::from_label("Tax Form W-2"),
+            DocumentType::TaxForm("W-2".to_string())
+        );
+        assert_eq!(
+            DocumentType::from_label("Memo"),
+            DocumentType::Other("Memo".to_string())
+        );
+        assert_eq!(DocumentType::from_label("unknown"), DocumentType::Unknown);
+        assert_eq!(
+            DocumentType::from_label("Tax Invoice"),
+            DocumentType::Invoice
+        );
+        assert_eq!(
+            DocumentType::from_label("Tax Receipt"),
+            DocumentType::Receipt
+        );
+        assert_eq!(
+            DocumentType::from_label("Tax Return"),
+            DocumentType::TaxForm("Tax Return".to_string())
+        );
+        assert_eq!(
+            DocumentType::from_label("Property Tax"),
+            DocumentType::Other("Property Tax".to_string())
+        );
+    }
+
+    #[test]
+    fn test_document_type_deserializes_leniently() {
+        let cases = [
+            (r#""Bank Statement""#, DocumentType::BankStatement),
+            (r#""BankStatement""#, DocumentType::BankStatement),
+            (r#""INVOICE""#, DocumentType::Invoice),
+            (r#""pay stub""#, DocumentType::Payroll),
+            (
+                r#""Tax Form W-2""#,
+                DocumentType::TaxForm("W-2".to_string()),
+            ),
+            (r#""TaxForm""#, DocumentType::TaxForm(String::new())),
+            (r#""W2""#, DocumentType::TaxForm("W-2".to_string())),
+            (
+                r#""form 1099-misc""#,
+                DocumentType::TaxForm("1099-MISC".to_string()),
+            ),
+            (r#""Form 1040""#, DocumentType::TaxForm("1040".to_string())),
+            (
+                r#"{"TaxForm": "W-2"}"#,
+                DocumentType::TaxForm("W-2".to_string()),
+            ),
+            (r#""Lease""#, DocumentType::Other("Lease".to_string())),
+        ];
+        for (json, expected) in cases {
+            let parsed: DocumentType = serde_json::from_str(json).unwrap();
+            assert_eq!(parsed, expected, "{}", json);
+
+            let round_trip = serde_json::to_string(&parsed).unwrap();
+            assert_eq!(
+                serde_json::from_str::<DocumentType>(&round_trip).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_risk_level_deserializes_leniently() {
+        let cases = [
+            (r#""low""#, RiskLevel::Low),
+            (r#""HIGH RISK""#, RiskLevel::High),
+            (r#""Moderate""#, RiskLevel::Medium),
+            (r#""Low|Medium""#, RiskLevel::Medium),
+            (r#""0.8""#, RiskLevel::Critical),
+            ("0.1", RiskLevel::Low),
+        ];
+        for (json, expected) in cases {
+            let parsed: RiskLevel = serde_json::from_str(json).unwrap();
+            assert_eq!(parsed, expected, "{}", json);
+        }
+        assert!(serde_json::from_str::<RiskLevel>(r#""purple""#).is_err());
+        assert!(serde_json::from_str::<RiskLevel>("6").is_err());
+    }
+
+    #[test]
+    fn test_risk_level_from_score_boundaries() {
+        let cases = [
+            (-0.01, None),
+            (0.0, Some(RiskLevel::Low)),
+            (0.2499, Some(RiskLevel::Low)),
+            (0.25, Some(RiskLevel::Medium)),
+            (0.4999, Some(RiskLevel::Medium)),
+            (0.5, Some(RiskLevel::High)),
+            (0.7499, Some(RiskLevel::High)),
+            (0.75, Some(RiskLevel::Critical)),
+            (1.0, Some(RiskLevel::Critical)),
+            (1.01, None),
+            (10.0, None),
+            (f64::NAN, None),
+        ];
+        for (score, expected) in cases {
+            assert_eq!(RiskLevel::from_score(score), expected, "{}", score);
+        }
+    }
+
+    #[test]
+    fn test_legacy_conversion() {
+        let legacy = LegacyFinancialDocument {
+            document_type: "Invoice".to_string(),
+            confidence: 0.96,
+            extracted_data: [
+                ("date", "January 15, 2024"),
+                ("total_amount", "$2,750.00"),
+                ("vendor", "Tech Solutions Inc."),
+                ("client", "ABC Corporation"),
+            ]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+            validation_errors: vec![],
+            suggested_categories: vec!["Technology".to_string()],
+            document_insights: vec!["Payment due soon".to_string()],
+        };
+
+        let document = FinancialDocument::from(legacy);
+
+        assert_eq!(document.document_type, DocumentType::Invoice);
+        assert_eq!(
+            document.metadata.total_amount,
+            Some(Money::parse("2750.00 USD").unwrap())
+        );
+        assert_eq!(
+            document.amount("total_amount").unwrap().to_string(),
+            "$2,750.00"
+        );
+        assert_eq!(document.metadata.currency.as_deref(), Some("USD"));
+        assert_eq!(
+            document.metadata.document_date,
+            NaiveDate::from_ymd_opt(2024, 1, 15)
+        );
+        assert_eq!(document.extracted_data["date"], "2024-01-15");
+        assert_eq!(document.metadata.parties.len(), 2);
+        assert_eq!(document.document_insights, vec!["Payment due soon"]);
+    }
+}
@@ -0,0 +1,401 @@
+use super::{AllowanceCharge, Endpoint, Invoice, InvoiceLine, InvoiceParty, TaxSubtotal};
+use crate::error::{AnalyzerError, Result};
+use crate::money::Currency;
+use crate::xml::{child, children, text};
+use chrono::NaiveDate;
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+/// Reads a UN/CEFACT Cross Industry Invoice, the XML of Factur-X and
+/// ZUGFeRD. Any profile can be read; the basic ones have no lines.
+pub(super) fn parse(xml: &str) -> Result<Invoice> {
+    let document = Document::parse(xml)
+        .map_err(|e| AnalyzerError::EInvoice(format!("invalid CII XML: {}", e)))?;
+    let root = document.root_element();
+    if !root.has_tag_name("CrossIndustryInvoice") {
+        return Err(AnalyzerError::EInvoice(format!(
+            "{} is not a Cross Industry Invoice",
+            root.tag_name().name()
+        )));
+    }
+    let transaction = child(root, &["SupplyChainTradeTransaction"]).ok_or_else(|| {
+        AnalyzerError::EInvoice("the invoice has no SupplyChainTradeTransaction".to_string())
+    })?;
+    let agreement = child(transaction, &["ApplicableHeaderTradeAgreement"]);
+    let settlement = child(transaction, &["ApplicableHeaderTradeSettlement"]);
+    let summation =
+        settlement.and_then(|s| child(s, &["SpecifiedTradeSettlementHeaderMonetarySummation"]));
+    let currency = settlement
+        .and_then(|s| text(s, &["InvoiceCurrencyCode"]))
+        .and_then(|code| Currency::new(&code));
+
+    let mut lines = Vec::new();
+    for line in children(transaction, "IncludedSupplyChainTradeLineItem") {
+        let tax = child(
+            line,
+            &["SpecifiedLineTradeSettlement", "ApplicableTradeTax"],
+        );
+        lines.push(InvoiceLine {
+            description: text(line, &["SpecifiedTradeProduct", "Name"]).unwrap_or_default(),
+            quantity: amount(line, &["SpecifiedLineTradeDelivery", "BilledQuantity"])?,
+            unit_price: amount(
+                line,
+                &[
+                    "SpecifiedLineTradeAgreement",
+                    "NetPriceProductTradePrice",
+                    "ChargeAmount",
+                ],
+            )?,
+            amount: amount(
+                line,
+                &[
+                    "SpecifiedLineTradeSettlement",
+                    "SpecifiedTradeSettlementLineMonetarySummation",
+                    "LineTotalAmount",
+                ],
+            )?
+            .unwrap_or_default(),
+            tax_category: tax.and_then(|tax| text(tax, &["CategoryCode"])),
+            tax_percent: match tax {
+                Some(tax) => amount(tax, &["RateApplicablePercent"])?,
+                None => None,
+            },
+        });
+    }
+
+    let mut taxes = Vec::new();
+    for tax in settlement
+        .into_iter()
+        .flat_map(|s| children(s, "ApplicableTradeTax"))
+    {
+        taxes.push(TaxSubtotal {
+            category: text(tax, &["CategoryCode"]).unwrap_or_default(),
+            percent: amount(tax, &["RateApplicablePercent"])?,
+            taxable: amount(tax, &["BasisAmount"])?.unwrap_or_default(),
+            tax: amount(tax, &["CalculatedAmount"])?.unwrap_or_default(),
+        });
+    }
+
+    let mut allowance_charges = Vec::new();
+    for adjustment in settlement
+        .into_iter()
+        .flat_map(|s| children(s, "SpecifiedTradeAllowanceCharge"))
+    {
+        let tax = child(adjustment, &["CategoryTradeTax"]);
+        allowance_charges.push(AllowanceCharge {
+            charge: text(adjustment, &["ChargeIndicator", "Indicator"]).as_deref() == Some("true"),
+            reason: text(adjustment, &["Reason"]),
+            amount: amount(adjustment, &["ActualAmount"])?.unwrap_or_default(),
+            tax_category: tax.and_then(|tax| text(tax, &["CategoryCode"])),
+            tax_percent: match tax {
+                Some(tax) => amount(tax, &["RateApplicablePercent"])?,
+                None => None,
+            },
+        });
+    }
+
+    let terms = settlement.and_then(|s| child(s, &["SpecifiedTradePaymentTerms"]));
+    let total = |tag: &'static str| match summation {
+        Some(summation) => total_amount(summation, tag, currency.as_ref()),
+        None => Ok(None),
+    };
+    let (line_total, tax_total) = (total("LineTotalAmount")?, total("TaxTotalAmount")?);
+    let (grand_total, payable) = (total("GrandTotalAmount")?, total("DuePayableAmount")?);
+    Ok(Invoice {
+        number: text(root, &["ExchangedDocument", "ID"]),
+        issue_date: date(root, &["ExchangedDocument", "IssueDateTime"]),
+        due_date: terms.and_then(|terms| date(terms, &["DueDateDateTime"])),
+        currency,
+        seller: agreement.and_then(|a| party(a, "SellerTradeParty")),
+        buyer: agreement.and_then(|a| party(a, "BuyerTradeParty")),
+        buyer_reference: agreement.and_then(|a| text(a, &["BuyerReference"])),
+        order_reference: agreement
+            .and_then(|a| text(a, &["BuyerOrderReferencedDocument", "IssuerAssignedID"])),
+        payment_terms: terms.and_then(|terms| text(terms, &["Description"])),
+        lines,
+        allowance_charges,
+        taxes,
+        line_total,
+        tax_total,
+        total: grand_total,
+        payable,
+    })
+}
+
+fn party(agreement: Node, role: &str) -> Option<InvoiceParty> {
+    let party = child(agreement, &[role])?;
+    // The VAT number is the registration with scheme VA; FC is the tax number.
+    let vat_id = children(party, "SpecifiedTaxRegistration")
+        .filter_map(|registration| child(registration, &["ID"]))
+        .find(|id| id.attribute("schemeID") == Some("VA"))
+        .and_then(|id| id.text())
+        .map(|id| id.trim().to_string());
+    let endpoint = child(party, &["URIUniversalCommunication", "URIID"]).and_then(|id| {
+        Some(Endpoint {
+            scheme: id.attribute("schemeID")?.to_string(),
+            id: id.text()?.trim().to_string(),
+        })
+    });
+    Some(InvoiceParty {
+        name: text(party, &["Name"])?,
+        vat_id,
+        endpoint,
+        country: text(party, &["PostalTradeAddress", "CountryID"]),
+    })
+}
+
+/// A summation amount. The tax total may be given twice, in the invoice
+/// and in the accounting currency.
+fn total_amount(
+    summation: Node,
+    tag: &'static str,
+    currency: Option<&Currency>,
+) -> Result<Option<Decimal>> {
+    let amounts: Vec<Node> = children(summation, tag).collect();
+    let node = amounts
+        .iter()
+        .find(|node| {
+            node.attribute("currencyID")
+                .and_then(Currency::new)
+                .as_ref()
+                == currency
+        })
+        .or(amounts.first());
+    match node {
+        Some(node) => amount(*node, &[]),
+        None => Ok(None),
+    }
+}
+
+fn amount(node: Node, path: &[&str]) -> Result<Option<Decimal>> {
+    text(node, path)
+        .map(|value| {
+            Decimal::from_str(&value).map_err(|_| {
+                AnalyzerError::EInvoice(format!("{} is not a number: '{}'", path.join("/"), value))
+            })
+        })
+        .transpose()
+}
+
+/// Dates in format 102 of UNTDID 2379, `YYYYMMDD`.
+fn date(node: Node, path: &[&str]) -> Option<NaiveDate> {
+    let value = text(child(node, path)?, &["DateTimeString"])?;
+    NaiveDate::parse_from_str(&value, "%Y%m%d").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{check_ubl, import_invoice, to_ubl, xml_from_pdf, InvoiceSyntax};
+    use super::*;
+    use crate::fields::DocumentFields;
+    use crate::money::Money;
+    use pdf_extract::{Dictionary, Document as PdfDocument, Object, Stream};
+
+    const FACTUR_X: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rsm:CrossIndustryInvoice xmlns:rsm="urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100"
+    xmlns:ram="urn:un:unece:uncefact:data:standard:ReusableAggregateBusinessInformationEntity:100"
+    xmlns:udt="urn:un:unece:uncefact:data:standard:UnqualifiedDataType:100">
+  <rsm:ExchangedDocumentContext>
+    <ram:GuidelineSpecifiedDocumentContextParameter>
+      <ram:ID>urn:cen.eu:en16931:2017</ram:ID>
+    </ram:GuidelineSpecifiedDocumentContextParameter>
+  </rsm:ExchangedDocumentContext>
+  <rsm:ExchangedDocument>
+    <ram:ID>FX-2024-17</ram:ID>
+    <ram:TypeCode>380</ram:TypeCode>
+    <ram:IssueDateTime><udt:DateTimeString format="102">20240305</udt:DateTimeString></ram:IssueDateTime>
+  </rsm:ExchangedDocument>
+  <rsm:SupplyChainTradeTransaction>
+    <ram:IncludedSupplyChainTradeLineItem>
+      <ram:AssociatedDocumentLineDocument><ram:LineID>1</ram:LineID></ram:AssociatedDocumentLineDocument>
+      <ram:SpecifiedTradeProduct><ram:Name>Paper A4</ram:Name></ram:SpecifiedTradeProduct>
+      <ram:SpecifiedLineTradeAgreement>
+        <ram:NetPriceProductTradePrice><ram:ChargeAmount>4.50</ram:ChargeAmount></ram:NetPriceProductTradePrice>
+      </ram:SpecifiedLineTradeAgreement>
+      <ram:SpecifiedLineTradeDelivery><ram:BilledQuantity unitCode="C62">20</ram:BilledQuantity></ram:SpecifiedLineTradeDelivery>
+      <ram:SpecifiedLineTradeSettlement>
+        <ram:ApplicableTradeTax>
+          <ram:TypeCode>VAT</ram:TypeCode><ram:CategoryCode>S</ram:CategoryCode>
+          <ram:RateApplicablePercent>20</ram:RateApplicablePercent>
+        </ram:ApplicableTradeTax>
+        <ram:SpecifiedTradeSettlementLineMonetarySummation>
+          <ram:LineTotalAmount>90.00</ram:LineTotalAmount>
+        </ram:SpecifiedTradeSettlementLineMonetarySummation>
+      </ram:SpecifiedLineTradeSettlement>
+    </ram:IncludedSupplyChainTradeLineItem>
+    <ram:ApplicableHeaderTradeAgreement>
+      <ram:SellerTradeParty>
+        <ram:Name>Papeterie Martin SARL</ram:Name>
+        <ram:SpecifiedTaxRegistration><ram:ID schemeID="VA">FR32123456789</ram:ID></ram:SpecifiedTaxRegistration>
+      </ram:SellerTradeParty>
+      <ram:BuyerTradeParty><ram:Name>Bureau Dupont</ram:Name></ram:BuyerTradeParty>
+    </ram:ApplicableHeaderTradeAgreement>
+    <ram:ApplicableHeaderTradeDelivery/>
+    <ram:ApplicableHeaderTradeSettlement>
+      <ram:InvoiceCurrencyCode>EUR</ram:InvoiceCurrencyCode>
+      <ram:ApplicableTradeTax>
+        <ram:CalculatedAmount>18.00</ram:CalculatedAmount>
+        <ram:TypeCode>VAT</ram:TypeCode>
+        <ram:BasisAmount>90.00</ram:BasisAmount>
+        <ram:CategoryCode>S</ram:CategoryCode>
+        <ram:RateApplicablePercent>20</ram:RateApplicablePercent>
+      </ram:ApplicableTradeTax>
+      <ram:SpecifiedTradePaymentTerms>
+        <ram:Description>30 jours net</ram:Description>
+        <ram:DueDateDateTime><udt:DateTimeString format="102">20240404</udt:DateTimeString></ram:DueDateDateTime>
+      </ram:SpecifiedTradePaymentTerms>
+      <ram:SpecifiedTradeSettlementHeaderMonetarySummation>
+        <ram:LineTotalAmount>90.00</ram:LineTotalAmount>
+        <ram:TaxBasisTotalAmount>90.00</ram:TaxBasisTotalAmount>
+        <ram:TaxTotalAmount currencyID="EUR">18.00</ram:TaxTotalAmount>
+        <ram:GrandTotalAmount>108.00</ram:GrandTotalAmount>
+        <ram:TotalPrepaidAmount>50.00</ram:TotalPrepaidAmount>
+        <ram:DuePayableAmount>58.00</ram:DuePayableAmount>
+      </ram:SpecifiedTradeSettlementHeaderMonetarySummation>
+    </ram:ApplicableHeaderTradeSettlement>
+  </rsm:SupplyChainTradeTransaction>
+</rsm:CrossIndustryInvoice>"#;
+
+    /// A one-page PDF with `xml` attached as factur-x.xml.
+    fn factur_x_pdf(xml: &str) -> Vec<u8> {
+        let mut pdf = PdfDocument::with_version("1.7");
+        let pages_id = pdf.new_object_id();
+        let page_id = pdf.add_object(Dictionary::from_iter([
+            ("Type", Object::Name(b"Page".to_vec())),
+            ("Parent", Object::Reference(pages_id)),
+            (
+                "MediaBox",
+                Object::Array(vec![0.into(), 0.into(), 595.into(), 842.into()]),
+            ),
+        ]));
+        pdf.objects.insert(
+            pages_id,
+            Object::Dictionary(Dictionary::from_iter([
+                ("Type", Object::Name(b"Pages".to_vec())),
+                ("Kids", Object::Array(vec![Object::Reference(page_id)])),
+                ("Count", Object::Integer(1)),
+            ])),
+        );
+        let embedded = pdf.add_object(Stream::new(
+            Dictionary::from_iter([("Type", Object::Name(b"EmbeddedFile".to_vec()))]),
+            xml.as_bytes().to_vec(),
+        ));
+        let file_spec = pdf.add_object(Dictionary::from_iter([
+            ("Type", Object::Name(b"Filespec".to_vec())),
+            ("F", Object::string_literal("factur-x.xml")),
+            ("UF", Object::string_literal("factur-x.xml")),
+            (
+                "EF",
+                Object::Dictionary(Dictionary::from_iter([("F", Object::Reference(embedded))])),
+            ),
+        ]));
+        let names = Dictionary::from_iter([(
+            "EmbeddedFiles",
+            Object::Dictionary(Dictionary::from_iter([(
+                "Names",
+                Object::Array(vec![
+                    Object::string_literal("factur-x.xml"),
+                    Object::Reference(file_spec),
+                ]),
+            )])),
+        )]);
+        let catalog = pdf.add_object(Dictionary::from_iter([
+            ("Type", Object::Name(b"Catalog".to_vec())),
+            ("Pages", Object::Reference(pages_id)),
+            ("Names", Object::Dictionary(names)),
+        ]));
+        pdf.trailer.set("Root", catalog);
+        let mut bytes = Vec::new();
+        pdf.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_factur_x_pdf_is_read_from_its_attachment() {
+        let xml = xml_from_pdf(&factur_x_pdf(FACTUR_X)).unwrap().unwrap();
+        assert_eq!(xml, FACTUR_X);
+
+        let document = import_invoice(&xml).unwrap().unwrap();
+
+        let DocumentFields::Invoice(fields) = &document.fields else {
+            panic!("not an invoice");
+        };
+        assert_eq!(fields.invoice_number.as_deref(), Some("FX-2024-17"));
+        assert_eq!(fields.date, NaiveDate::from_ymd_opt(2024, 3, 5));
+        assert_eq!(fields.due_date, NaiveDate::from_ymd_opt(2024, 4, 4));
+        assert_eq!(fields.vendor.as_deref(), Some("Papeterie Martin SARL"));
+        assert_eq!(fields.total_amount, Some(Money::parse("€108.00").unwrap()));
+        assert_eq!(
+            document
+                .extracted_data
+                .get("amount_due")
+                .map(String::as_str),
+            Some("€58.00")
+        );
+        assert_eq!(
+            document.metadata.parties[0].identifier.as_deref(),
+            Some("FR32123456789")
+        );
+        assert_eq!(
+            document.metadata.line_items[0].quantity,
+            Some(Decimal::from(20))
+        );
+        assert_eq!(
+            document.tax_implications,
+            vec!["VAT category S at 20%: €18.00 on €90.00"]
+        );
+    }
+
+    #[test]
+    fn test_cii_invoice_with_allowances_and_two_rates() {
+        let xml = include_str!("../../fixtures/einvoice/factur-x-en16931.xml");
+        let invoice = parse(xml).unwrap();
+        assert_eq!(invoice.lines.len(), 2);
+        assert_eq!(invoice.lines[1].tax_percent, Some(Decimal::from(7)));
+        assert_eq!(invoice.allowance_charges.len(), 2);
+        assert!(invoice.allowance_charges[1].charge);
+        assert_eq!(invoice.taxes.len(), 2);
+        assert_eq!(
+            invoice.buyer_reference.as_deref(),
+            Some("04011000-12345-34")
+        );
+        assert_eq!(invoice.order_reference.as_deref(), Some("B-5512"));
+        assert_eq!(
+            invoice
+                .seller
+                .as_ref()
+                .and_then(|seller| seller.vat_id.clone()),
+            Some("DE136695976".to_string())
+        );
+
+        let document = import_invoice(xml).unwrap().unwrap();
+        let DocumentFields::Invoice(fields) = &document.fields else {
+            panic!("not an invoice");
+        };
+        assert_eq!(fields.subtotal, Some(Money::parse("€155.00").unwrap()));
+        assert_eq!(fields.total_amount, Some(Money::parse("€176.05").unwrap()));
+        assert_eq!(
+            document.tax_implications,
+            vec![
+                "VAT category S at 19%: €16.15 on €85.00",
+                "VAT category S at 7%: €4.90 on €70.00",
+            ]
+        );
+        let net: Decimal = document
+            .metadata
+            .line_items
+            .iter()
+            .map(|item| item.amount.amount)
+            .sum();
+        assert_eq!(net, Decimal::from(155));
+        let written = to_ubl(&document).unwrap();
+        assert_eq!(check_ubl(&written), Vec::<String>::new());
+        let written = InvoiceSyntax::Ubl.parse(&written).unwrap();
+        assert_eq!(written.allowance_charges.len(), 1);
+        assert!(written
+            .lines
+            .iter()
+            .all(|line| line.unit_price.unwrap() >= Decimal::ZERO));
+    }
+}
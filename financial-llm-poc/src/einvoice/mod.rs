@@ -0,0 +1,767 @@
+mod cii;
+mod ubl;
+
+pub use ubl::check_ubl;
+
+use crate::document_types::{
+    DocumentMetadata, DocumentType, FinancialDocument, LineItem, Party, RiskLevel,
+};
+use crate::error::{AnalyzerError, Result};
+use crate::fields::DocumentFields;
+use crate::ingest;
+use crate::money::{Currency, Money};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Names under which Factur-X, ZUGFeRD and XRechnung PDFs attach their XML.
+const PDF_ATTACHMENTS: [&str; 4] = [
+    "factur-x.xml",
+    "zugferd-invoice.xml",
+    "xrechnung.xml",
+    "order-x.xml",
+];
+
+/// EAS codes of the national VAT number schemes, by VAT id prefix.
+const VAT_ENDPOINT_SCHEMES: [(&str, &str); 28] = [
+    ("AT", "9914"),
+    ("BE", "9925"),
+    ("BG", "9926"),
+    ("CH", "9927"),
+    ("CY", "9928"),
+    ("CZ", "9929"),
+    ("DE", "9930"),
+    ("EE", "9931"),
+    ("EL", "9933"),
+    ("ES", "9920"),
+    ("FI", "0213"),
+    ("FR", "9957"),
+    ("GB", "9932"),
+    ("HR", "9934"),
+    ("HU", "9910"),
+    ("IE", "9935"),
+    ("IT", "0211"),
+    ("LI", "9936"),
+    ("LT", "9937"),
+    ("LU", "9938"),
+    ("LV", "9939"),
+    ("MT", "9943"),
+    ("NL", "9944"),
+    ("PL", "9945"),
+    ("PT", "9946"),
+    ("RO", "9947"),
+    ("SI", "9949"),
+    ("SK", "9950"),
+];
+
+/// An XML syntax of the European e-invoicing standard EN 16931.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvoiceSyntax {
+    /// OASIS UBL 2.1, as used by Peppol BIS Billing 3.0.
+    Ubl,
+    /// UN/CEFACT Cross Industry Invoice, as used by Factur-X and ZUGFeRD.
+    Cii,
+}
+
+impl InvoiceSyntax {
+    pub fn detect(text: &str) -> Option<Self> {
+        let head: String = text.chars().take(2048).collect();
+        if head.contains("urn:oasis:names:specification:ubl:schema:xsd:Invoice-2") {
+            Some(InvoiceSyntax::Ubl)
+        } else if head.contains("CrossIndustryInvoice") {
+            Some(InvoiceSyntax::Cii)
+        } else {
+            None
+        }
+    }
+
+    pub fn parse(self, text: &str) -> Result<Invoice> {
+        match self {
+            InvoiceSyntax::Ubl => ubl::parse(text),
+            InvoiceSyntax::Cii => cii::parse(text),
+        }
+    }
+}
+
+impl fmt::Display for InvoiceSyntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InvoiceSyntax::Ubl => "UBL",
+            InvoiceSyntax::Cii => "CII",
+        })
+    }
+}
+
+/// Reads `text` as an e-invoice if it is one. Returns `None` for anything
+/// else, which is left to a model or the rule engine.
+pub fn import_invoice(text: &str) -> Option<Result<FinancialDocument>> {
+    let syntax = InvoiceSyntax::detect(text)?;
+    Some(
+        syntax
+            .parse(text)
+            .map(|invoice| invoice.into_document(syntax)),
+    )
+}
+
+/// The XML a Factur-X or ZUGFeRD PDF carries, if `bytes` is one.
+pub fn xml_from_pdf(bytes: &[u8]) -> Result<Option<String>> {
+    let attachments = ingest::attachments(bytes)?;
+    let xml = attachments.into_iter().find_map(|(name, content)| {
+        PDF_ATTACHMENTS
+            .iter()
+            .any(|known| name.eq_ignore_ascii_case(known))
+            .then_some(content)
+    });
+    xml.map(|xml| {
+        String::from_utf8(xml)
+            .map_err(|_| AnalyzerError::EInvoice("the attached XML is not UTF-8".to_string()))
+    })
+    .transpose()
+}
+
+/// Writes an analysed invoice as a UBL 2.1 invoice and checks the result
+/// with `check_ubl`, failing with every problem found.
+pub fn to_ubl(document: &FinancialDocument) -> Result<String> {
+    let invoice = Invoice::from_document(document)?;
+    let xml = ubl::write(&invoice);
+    let problems = check_ubl(&xml);
+    if problems.is_empty() {
+        Ok(xml)
+    } else {
+        Err(AnalyzerError::EInvoice(problems.join("; ")))
+    }
+}
+
+/// An invoice in the terms of EN 16931, as read from or written to XML.
+/// Amounts are in `currency`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Invoice {
+    pub number: Option<String>,
+    pub issue_date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub currency: Option<Currency>,
+    pub seller: Option<InvoiceParty>,
+    pub buyer: Option<InvoiceParty>,
+    /// The reference the buyer asked to be quoted, such as a Leitweg-ID.
+    pub buyer_reference: Option<String>,
+    /// The buyer's purchase order number.
+    pub order_reference: Option<String>,
+    pub payment_terms: Option<String>,
+    pub lines: Vec<InvoiceLine>,
+    /// Discounts and surcharges on the invoice as a whole.
+    pub allowance_charges: Vec<AllowanceCharge>,
+    /// VAT by category and rate.
+    pub taxes: Vec<TaxSubtotal>,
+    /// Sum of the line amounts.
+    pub line_total: Option<Decimal>,
+    pub tax_total: Option<Decimal>,
+    /// Including tax.
+    pub total: Option<Decimal>,
+    /// What is left to pay after prepayments.
+    pub payable: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvoiceParty {
+    pub name: String,
+    pub vat_id: Option<String>,
+    /// Where the party receives e-invoices on the Peppol network.
+    pub endpoint: Option<Endpoint>,
+    /// ISO 3166-1 alpha-2 code of the party's address.
+    pub country: Option<String>,
+}
+
+/// A Peppol electronic address: an identifier in one of the schemes of the
+/// EAS code list, such as `0088` for GLNs or `9930` for German VAT numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub scheme: String,
+    pub id: String,
+}
+
+impl Endpoint {
+    /// Reads `scheme:id`, as in `0088:4035811991014`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (scheme, id) = value.trim().split_once(':')?;
+        (Self::is_scheme(scheme) && !id.trim().is_empty()).then(|| Endpoint {
+            scheme: scheme.to_string(),
+            id: id.trim().to_string(),
+        })
+    }
+
+    /// Whether `scheme` is an EAS code: four digits, or one of the few
+    /// letter codes such as `EM` for e-mail addresses.
+    pub fn is_scheme(scheme: &str) -> bool {
+        (scheme.len() == 4 && scheme.chars().all(|c| c.is_ascii_digit()))
+            || ["AN", "AQ", "AS", "AU", "EM"].contains(&scheme)
+    }
+
+    /// The EAS scheme registered for VAT numbers of the VAT id's country.
+    /// A party's VAT number is a valid Peppol address in those countries.
+    pub fn for_vat_id(vat_id: &str) -> Option<Self> {
+        let prefix = vat_id.get(..2)?.to_ascii_uppercase();
+        let (_, scheme) = VAT_ENDPOINT_SCHEMES
+            .iter()
+            .find(|(country, _)| *country == prefix)?;
+        Some(Endpoint {
+            scheme: scheme.to_string(),
+            id: vat_id.to_ascii_uppercase(),
+        })
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.scheme, self.id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvoiceLine {
+    pub description: String,
+    pub quantity: Option<Decimal>,
+    pub unit_price: Option<Decimal>,
+    /// Net of tax.
+    pub amount: Decimal,
+    /// UNCL5305 code of the line's VAT category and its rate.
+    pub tax_category: Option<String>,
+    pub tax_percent: Option<Decimal>,
+}
+
+/// A discount (allowance) or surcharge (charge) on the whole invoice, net
+/// of tax.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllowanceCharge {
+    pub charge: bool,
+    pub reason: Option<String>,
+    pub amount: Decimal,
+    pub tax_category: Option<String>,
+    pub tax_percent: Option<Decimal>,
+}
+
+impl AllowanceCharge {
+    /// The amount's effect on the invoice total: negative for allowances.
+    pub fn signed_amount(&self) -> Decimal {
+        if self.charge {
+            self.amount
+        } else {
+            -self.amount
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaxSubtotal {
+    /// UNCL5305 code: `S` standard rate, `Z` zero rated, `E` exempt, ...
+    pub category: String,
+    pub percent: Option<Decimal>,
+    pub taxable: Decimal,
+    pub tax: Decimal,
+}
+
+impl Invoice {
+    /// An `Invoice` document with the seller as payee, the buyer as payer
+    /// and every line as a line item. Allowances and charges on the whole
+    /// invoice become line items too, negative for allowances, so the line
+    /// items add up to the total without tax.
+    pub fn into_document(self, syntax: InvoiceSyntax) -> FinancialDocument {
+        let currency = self.currency.clone().unwrap_or_else(Currency::unknown);
+        let money = |amount: Decimal| Money::new(amount, currency.clone());
+
+        let mut extracted_data = HashMap::new();
+        let seller = self.seller.clone();
+        let buyer = self.buyer.clone();
+        let amount_due = self.payable.filter(|payable| Some(*payable) != self.total);
+        let adjustments: Decimal = self
+            .allowance_charges
+            .iter()
+            .map(AllowanceCharge::signed_amount)
+            .sum();
+        let net_total = self.line_total.map(|total| total + adjustments);
+        for (key, value) in [
+            ("invoice_number", self.number),
+            ("date", self.issue_date.map(|date| date.to_string())),
+            ("due_date", self.due_date.map(|date| date.to_string())),
+            ("buyer_reference", self.buyer_reference),
+            ("order_reference", self.order_reference),
+        ] {
+            if let Some(value) = value {
+                extracted_data.insert(key.to_string(), value);
+            }
+        }
+        for (prefix, party) in [("vendor", seller), ("client", buyer)] {
+            let Some(party) = party else {
+                continue;
+            };
+            extracted_data.insert(prefix.to_string(), party.name);
+            for (key, value) in [
+                ("vat_id", party.vat_id),
+                ("endpoint_id", party.endpoint.map(|e| e.to_string())),
+                ("country", party.country),
+            ] {
+                if let Some(value) = value {
+                    extracted_data.insert(format!("{}_{}", prefix, key), value);
+                }
+            }
+        }
+        for (key, value) in [
+            ("subtotal", net_total.map(|t| money(t).to_string())),
+            ("tax_amount", self.tax_total.map(|t| money(t).to_string())),
+            ("total_amount", self.total.map(|t| money(t).to_string())),
+            ("amount_due", amount_due.map(|t| money(t).to_string())),
+            ("payment_terms", self.payment_terms),
+        ] {
+            if let Some(value) = value {
+                extracted_data.insert(key.to_string(), value);
+            }
+        }
+
+        let parties = [("payee", &self.seller), ("payer", &self.buyer)]
+            .into_iter()
+            .filter_map(|(role, party)| {
+                let party = party.as_ref()?;
+                Some(Party {
+                    role: role.to_string(),
+                    name: party.name.clone(),
+                    identifier: party.vat_id.clone(),
+                })
+            })
+            .collect();
+        let line_count = self.lines.len();
+        let adjustment_lines = self.allowance_charges.into_iter().map(|adjustment| {
+            let kind = if adjustment.charge {
+                "Charge"
+            } else {
+                "Allowance"
+            };
+            InvoiceLine {
+                description: match &adjustment.reason {
+                    Some(reason) => format!("{}: {}", kind, reason),
+                    None => kind.to_string(),
+                },
+                quantity: None,
+                unit_price: None,
+                amount: adjustment.signed_amount(),
+                tax_category: adjustment.tax_category,
+                tax_percent: adjustment.tax_percent,
+            }
+        });
+        let line_items = self
+            .lines
+            .into_iter()
+            .chain(adjustment_lines)
+            .map(|line| LineItem {
+                description: line.description,
+                quantity: line.quantity,
+                unit_price: line.unit_price.map(money),
+                amount: money(line.amount),
+                tax_rate: line.tax_percent,
+                tax_category: line.tax_category,
+            })
+            .collect();
+        let tax_implications = self
+            .taxes
+            .iter()
+            .map(|tax| {
+                let rate = tax
+                    .percent
+                    .map(|percent| format!(" at {}%", percent.normalize()))
+                    .unwrap_or_default();
+                format!(
+                    "VAT category {}{}: {} on {}",
+                    tax.category,
+                    rate,
+                    money(tax.tax),
+                    money(tax.taxable)
+                )
+            })
+            .collect();
+
+        let mut document = FinancialDocument {
+            document_type: DocumentType::Invoice,
+            // Read, not inferred.
+            confidence: 1.0,
+            extracted_data,
+            validation_errors: Vec::new(),
+            suggested_categories: vec!["Accounts Payable".to_string()],
+            tax_implications,
+            risk_assessment: RiskLevel::Low,
+            metadata: DocumentMetadata {
+                document_date: self.issue_date,
+                period: None,
+                total_amount: self.total.or(self.payable).map(money),
+                currency: self.currency.map(|c| c.code().to_string()),
+                parties,
+                line_items,
+                transactions: Vec::new(),
+                page_count: None,
+                source_pages: BTreeMap::new(),
+            },
+            document_insights: vec![format!(
+                "Imported from a {} e-invoice with {} line(s)",
+                syntax, line_count
+            )],
+            prompt_version: None,
+            usage: None,
+            fields: DocumentFields::default(),
+        };
+        document.normalize();
+        document
+    }
+
+    /// The invoice an analysed document describes. VAT is computed per
+    /// category and rate from what the document states for each line, or
+    /// for the whole invoice; an invoice whose VAT category or rate is not
+    /// stated is refused rather than guessed. The stated total is kept as
+    /// the payable amount, so the check on the written XML catches
+    /// extractions that do not add up. A document without line items is
+    /// written with its net amount as the only line.
+    pub fn from_document(document: &FinancialDocument) -> Result<Self> {
+        let DocumentFields::Invoice(fields) = document.fields.clone() else {
+            return Err(AnalyzerError::EInvoice(format!(
+                "only invoices can be written as UBL, not {:?}",
+                document.document_type
+            )));
+        };
+        let metadata = &document.metadata;
+        let currency = metadata
+            .currency()
+            .or_else(|| fields.total_amount.as_ref().map(|t| t.currency.clone()))
+            .filter(|currency| !currency.is_unknown());
+
+        let other = &fields.other;
+        // Details beyond the name are read from keys such as `vendor_vat_id`
+        // and `client_endpoint_id`.
+        let party = |role: &str, prefix: &str, name: Option<String>| -> Result<_> {
+            let party = metadata.parties.iter().find(|party| party.role == role);
+            let Some(name) = party.map(|party| party.name.clone()).or(name) else {
+                return Ok(None);
+            };
+            let detail = |key: &str| other.get(&format!("{}_{}", prefix, key)).cloned();
+            let vat_id = detail("vat_id").or_else(|| {
+                party
+                    .and_then(|party| party.identifier.clone())
+                    .filter(|id| vat_country(id).is_some())
+            });
+            let endpoint = match detail("endpoint_id") {
+                Some(endpoint) => Some(Endpoint::parse(&endpoint).ok_or_else(|| {
+                    AnalyzerError::EInvoice(format!(
+                        "{}_endpoint_id '{}' is not of the form scheme:id",
+                        prefix, endpoint
+                    ))
+                })?),
+                None => vat_id.as_deref().and_then(Endpoint::for_vat_id),
+            };
+            let country = detail("country")
+                .map(|country| country.trim().to_ascii_uppercase())
+                .or_else(|| vat_id.as_deref().and_then(vat_country));
+            Ok(Some(InvoiceParty {
+                name,
+                vat_id,
+                endpoint,
+                country,
+            }))
+        };
+
+        let mut lines: Vec<InvoiceLine> = metadata
+            .line_items
+            .iter()
+            .map(|item| InvoiceLine {
+                description: item.description.clone(),
+                quantity: item.quantity,
+                unit_price: item.unit_price.as_ref().map(|price| price.amount),
+                amount: item.amount.amount,
+                tax_category: item.tax_category.clone(),
+                tax_percent: item.tax_rate,
+            })
+            .collect();
+        let stated_total = fields.total_amount.map(|total| total.amount);
+        let stated_tax = fields.tax_amount.as_ref().map(|tax| tax.amount);
+        if lines.is_empty() {
+            let net = fields
+                .subtotal
+                .map(|subtotal| subtotal.amount)
+                .or_else(|| Some(stated_total? - stated_tax?));
+            if let Some(net) = net {
+                lines.push(InvoiceLine {
+                    description: "Invoiced amount".to_string(),
+                    quantity: None,
+                    unit_price: None,
+                    amount: net,
+                    tax_category: None,
+                    tax_percent: None,
+                });
+            }
+        }
+
+        // Taxable amount by VAT category and rate.
+        let mut breakdown: BTreeMap<(String, Option<Decimal>), Decimal> = BTreeMap::new();
+        for (index, line) in lines.iter_mut().enumerate() {
+            let (category, percent) = tax_category(
+                line.tax_category
+                    .as_deref()
+                    .or(fields.tax_category.as_deref()),
+                line.tax_percent.or(fields.tax_rate),
+            )
+            .ok_or_else(|| {
+                AnalyzerError::EInvoice(format!(
+                    "the VAT category or rate of line {} ({}) is not stated",
+                    index + 1,
+                    line.description
+                ))
+            })?;
+            line.tax_category = Some(category.clone());
+            line.tax_percent = percent;
+            *breakdown.entry((category, percent)).or_default() += line.amount;
+        }
+        let taxes: Vec<TaxSubtotal> = breakdown
+            .into_iter()
+            .map(|((category, percent), taxable)| TaxSubtotal {
+                tax: (taxable * percent.unwrap_or_default() / Decimal::ONE_HUNDRED).round_dp(2),
+                category,
+                percent,
+                taxable,
+            })
+            .collect();
+
+        // Allowances on the whole invoice come back from `into_document` as
+        // negative lines without a quantity. A line's price must not be
+        // negative (BR-27), so they are written as allowances again.
+        let (allowances, lines): (Vec<_>, Vec<_>) = lines
+            .into_iter()
+            .partition(|line| line.quantity.is_none() && line.amount < Decimal::ZERO);
+        let (lines, allowances) = if lines.is_empty() {
+            (allowances, Vec::new())
+        } else {
+            (lines, allowances)
+        };
+        let allowance_charges: Vec<AllowanceCharge> = allowances
+            .into_iter()
+            .map(|line| AllowanceCharge {
+                charge: false,
+                reason: match line.description.as_str() {
+                    "Allowance" => None,
+                    description => Some(
+                        description
+                            .strip_prefix("Allowance: ")
+                            .unwrap_or(description)
+                            .to_string(),
+                    ),
+                },
+                amount: -line.amount,
+                tax_category: line.tax_category,
+                tax_percent: line.tax_percent,
+            })
+            .collect();
+
+        let line_total: Decimal = lines.iter().map(|line| line.amount).sum();
+        let adjustments: Decimal = allowance_charges
+            .iter()
+            .map(AllowanceCharge::signed_amount)
+            .sum();
+        let tax_total: Decimal = taxes.iter().map(|tax| tax.tax).sum();
+        // Each category's tax may be rounded the other way on the document.
+        let tolerance = Decimal::new(taxes.len() as i64, 2);
+        if let Some(stated_tax) = fields
+            .tax_amount
+            .filter(|tax| (tax.amount - tax_total).abs() > tolerance)
+        {
+            return Err(AnalyzerError::EInvoice(format!(
+                "the stated tax {} does not match the {} its VAT rates give",
+                stated_tax,
+                Money::new(tax_total, stated_tax.currency.clone())
+            )));
+        }
+        let total = line_total + adjustments + tax_total;
+
+        Ok(Invoice {
+            number: fields.invoice_number,
+            issue_date: fields.date.or(metadata.document_date),
+            due_date: fields.due_date,
+            currency,
+            seller: party("payee", "vendor", fields.vendor)?,
+            buyer: party("payer", "client", fields.client)?,
+            buyer_reference: other.get("buyer_reference").cloned(),
+            order_reference: ["order_reference", "purchase_order", "po_number"]
+                .iter()
+                .find_map(|key| other.get(*key))
+                .cloned(),
+            payment_terms: fields.payment_terms,
+            lines,
+            allowance_charges,
+            taxes,
+            line_total: Some(line_total),
+            tax_total: Some(tax_total),
+            total: Some(total),
+            payable: Some(stated_total.unwrap_or(total)),
+        })
+    }
+}
+
+/// The country a VAT id was issued by, from its prefix; Greece uses `EL`.
+fn vat_country(vat_id: &str) -> Option<String> {
+    let prefix = vat_id.trim().get(..2)?;
+    if !prefix.chars().all(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+    Some(match prefix {
+        "EL" => "GR".to_string(),
+        _ => prefix.to_string(),
+    })
+}
+
+/// The UNCL5305 category and rate of a line from what the document states.
+/// A positive rate without a category is the standard rate `S`, which
+/// covers reduced rates too; a zero rate can mean several categories, so
+/// it needs one. The categories that are always zero rated need no rate,
+/// and `O` (not subject to VAT) has none.
+fn tax_category(
+    category: Option<&str>,
+    percent: Option<Decimal>,
+) -> Option<(String, Option<Decimal>)> {
+    let category = category.map(|category| category.trim().to_uppercase());
+    match (category, percent) {
+        (Some(category), _) if category == "O" => Some((category, None)),
+        (Some(category), Some(percent)) => Some((category, Some(percent))),
+        (Some(category), None) if ["Z", "E", "AE", "K", "G"].contains(&category.as_str()) => {
+            Some((category, Some(Decimal::ZERO)))
+        }
+        (None, Some(percent)) if percent > Decimal::ZERO => Some(("S".to_string(), Some(percent))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_types::LegacyFinancialDocument;
+
+    fn extracted_invoice() -> FinancialDocument {
+        let mut document: FinancialDocument = LegacyFinancialDocument {
+            document_type: "invoice".to_string(),
+            confidence: 0.9,
+            extracted_data: [
+                ("invoice_number", "INV-2024-001"),
+                ("date", "January 15, 2024"),
+                ("due_date", "2024-02-14"),
+                ("vendor", "Tech Solutions Inc."),
+                ("vendor_vat_id", "DE811111111"),
+                ("client", "ABC Corporation"),
+                ("client_vat_id", "FR40303265045"),
+                ("po_number", "PO-7781"),
+                ("tax_amount", "€19.00"),
+                ("total_amount", "€119.00"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+            validation_errors: vec![],
+            suggested_categories: vec![],
+            document_insights: vec![],
+        }
+        .into();
+        document.metadata.line_items = vec![LineItem {
+            description: "Consulting & support".to_string(),
+            quantity: Some(Decimal::from(2)),
+            unit_price: Some(Money::parse("€50.00").unwrap()),
+            amount: Money::parse("€100.00").unwrap(),
+            tax_rate: Some(Decimal::from(19)),
+            tax_category: None,
+        }];
+        document
+    }
+
+    #[test]
+    fn test_extracted_invoice_round_trips_through_ubl() {
+        let xml = to_ubl(&extracted_invoice()).unwrap();
+        assert_eq!(InvoiceSyntax::detect(&xml), Some(InvoiceSyntax::Ubl));
+
+        let document = import_invoice(&xml).unwrap().unwrap();
+
+        let DocumentFields::Invoice(fields) = document.fields.clone() else {
+            panic!("not an invoice");
+        };
+        assert_eq!(fields.invoice_number.as_deref(), Some("INV-2024-001"));
+        assert_eq!(fields.date, NaiveDate::from_ymd_opt(2024, 1, 15));
+        assert_eq!(fields.vendor.as_deref(), Some("Tech Solutions Inc."));
+        assert_eq!(fields.total_amount, Some(Money::parse("€119.00").unwrap()));
+        assert_eq!(fields.other["vendor_endpoint_id"], "9930:DE811111111");
+        assert_eq!(fields.other["client_country"], "FR");
+        assert_eq!(fields.other["order_reference"], "PO-7781");
+        assert_eq!(
+            document.metadata.line_items[0].description,
+            "Consulting & support"
+        );
+        assert_eq!(
+            document.tax_implications,
+            vec!["VAT category S at 19%: €19.00 on €100.00"]
+        );
+        assert!(
+            document.validation_errors.is_empty(),
+            "{:?}",
+            document.validation_errors
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_extraction_is_not_written() {
+        let mut document = extracted_invoice();
+        document
+            .extracted_data
+            .insert("total_amount".to_string(), "€150.00".to_string());
+        document.extracted_data.remove("invoice_number");
+        document.refresh_fields();
+
+        let error = to_ubl(&document).unwrap_err().to_string();
+
+        assert!(error.contains("Invoice is missing ID"), "{}", error);
+        assert!(error.contains("BR-CO-16"), "{}", error);
+    }
+
+    #[test]
+    fn test_vat_comes_from_stated_rates() {
+        let mut document = extracted_invoice();
+        let mut books = document.metadata.line_items[0].clone();
+        books.description = "Handbook".to_string();
+        books.tax_rate = Some(Decimal::from(7));
+        document.metadata.line_items.push(books);
+        document
+            .extracted_data
+            .insert("tax_amount".to_string(), "€26.00".to_string());
+        document
+            .extracted_data
+            .insert("total_amount".to_string(), "€226.00".to_string());
+        document.refresh_fields();
+
+        let invoice = Invoice::from_document(&document).unwrap();
+
+        let rates: Vec<_> = invoice.taxes.iter().map(|t| (t.percent, t.tax)).collect();
+        assert_eq!(
+            rates,
+            vec![
+                (Some(Decimal::from(7)), Decimal::from(7)),
+                (Some(Decimal::from(19)), Decimal::from(19)),
+            ]
+        );
+        assert!(to_ubl(&document).is_ok());
+
+        // Neither a rate derived from the tax nor a zero-rated category is
+        // assumed.
+        for item in &mut document.metadata.line_items {
+            item.tax_rate = None;
+        }
+        let error = to_ubl(&document).unwrap_err().to_string();
+        assert!(
+            error.contains("VAT category or rate of line 1"),
+            "{}",
+            error
+        );
+        document
+            .extracted_data
+            .insert("vat_rate".to_string(), "10%".to_string());
+        document.refresh_fields();
+        let error = to_ubl(&document).unwrap_err().to_string();
+        assert!(error.contains("stated tax €26.00"), "{}", error);
+    }
+}
@@ -0,0 +1,1109 @@
+use super::{AllowanceCharge, Endpoint, Invoice, InvoiceLine, InvoiceParty, TaxSubtotal};
+use crate::error::{AnalyzerError, Result};
+use crate::money::Currency;
+use crate::xml::{child, children, text};
+use chrono::NaiveDate;
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+const INVOICE_NS: &str = "urn:oasis:names:specification:ubl:schema:xsd:Invoice-2";
+const CAC_NS: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2";
+const CBC_NS: &str = "urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2";
+/// Peppol BIS Billing 3.0, the EN 16931 profile of the Peppol network.
+const CUSTOMIZATION_ID: &str =
+    "urn:cen.eu:en16931:2017#compliant#urn:fdc:peppol.eu:2017:poacc:billing:3.0";
+const PROFILE_ID: &str = "urn:fdc:peppol.eu:2017:poacc:billing:01:1.0";
+/// Commercial invoice, in UNCL1001.
+const INVOICE_TYPE_CODE: &str = "380";
+/// "One", in UN/ECE recommendation 20, for quantities without a unit.
+const UNIT_CODE: &str = "C62";
+/// VAT category codes of UNCL5305 allowed by EN 16931.
+const TAX_CATEGORIES: [&str; 9] = ["S", "Z", "E", "AE", "K", "G", "O", "L", "M"];
+
+pub(super) fn parse(xml: &str) -> Result<Invoice> {
+    let document = Document::parse(xml)
+        .map_err(|e| AnalyzerError::EInvoice(format!("invalid UBL XML: {}", e)))?;
+    let root = document.root_element();
+    if !root.has_tag_name("Invoice") {
+        return Err(AnalyzerError::EInvoice(format!(
+            "only UBL invoices can be read, not {}",
+            root.tag_name().name()
+        )));
+    }
+
+    let currency = text(root, &["DocumentCurrencyCode"]).and_then(|c| Currency::new(&c));
+    // A second tax total is only given in the tax currency, when it differs.
+    let tax_total = children(root, "TaxTotal").find(|total| {
+        child(*total, &["TaxAmount"])
+            .and_then(|amount| amount.attribute("currencyID"))
+            .and_then(Currency::new)
+            == currency
+    });
+    let totals = child(root, &["LegalMonetaryTotal"]);
+
+    let mut taxes = Vec::new();
+    for subtotal in tax_total
+        .into_iter()
+        .flat_map(|total| children(total, "TaxSubtotal"))
+    {
+        taxes.push(TaxSubtotal {
+            category: text(subtotal, &["TaxCategory", "ID"]).unwrap_or_default(),
+            percent: amount(subtotal, &["TaxCategory", "Percent"])?,
+            taxable: amount(subtotal, &["TaxableAmount"])?.unwrap_or_default(),
+            tax: amount(subtotal, &["TaxAmount"])?.unwrap_or_default(),
+        });
+    }
+
+    let mut lines = Vec::new();
+    for line in children(root, "InvoiceLine") {
+        lines.push(InvoiceLine {
+            description: text(line, &["Item", "Name"])
+                .or_else(|| text(line, &["Item", "Description"]))
+                .unwrap_or_default(),
+            quantity: amount(line, &["InvoicedQuantity"])?,
+            unit_price: amount(line, &["Price", "PriceAmount"])?,
+            amount: amount(line, &["LineExtensionAmount"])?.unwrap_or_default(),
+            tax_category: text(line, &["Item", "ClassifiedTaxCategory", "ID"]),
+            tax_percent: amount(line, &["Item", "ClassifiedTaxCategory", "Percent"])?,
+        });
+    }
+
+    let mut allowance_charges = Vec::new();
+    for adjustment in children(root, "AllowanceCharge") {
+        allowance_charges.push(AllowanceCharge {
+            charge: text(adjustment, &["ChargeIndicator"]).as_deref() == Some("true"),
+            reason: text(adjustment, &["AllowanceChargeReason"]),
+            amount: amount(adjustment, &["Amount"])?.unwrap_or_default(),
+            tax_category: text(adjustment, &["TaxCategory", "ID"]),
+            tax_percent: amount(adjustment, &["TaxCategory", "Percent"])?,
+        });
+    }
+
+    let total = |tag: &str| match totals {
+        Some(totals) => amount(totals, &[tag]),
+        None => Ok(None),
+    };
+    Ok(Invoice {
+        number: text(root, &["ID"]),
+        issue_date: date(root, &["IssueDate"]),
+        due_date: date(root, &["DueDate"])
+            .or_else(|| date(root, &["PaymentMeans", "PaymentDueDate"])),
+        currency,
+        seller: party(root, "AccountingSupplierParty"),
+        buyer: party(root, "AccountingCustomerParty"),
+        buyer_reference: text(root, &["BuyerReference"]),
+        order_reference: text(root, &["OrderReference", "ID"]),
+        payment_terms: text(root, &["PaymentTerms", "Note"]),
+        lines,
+        allowance_charges,
+        taxes,
+        line_total: total("LineExtensionAmount")?,
+        tax_total: match tax_total {
+            Some(tax_total) => amount(tax_total, &["TaxAmount"])?,
+            None => None,
+        },
+        total: total("TaxInclusiveAmount")?,
+        payable: total("PayableAmount")?,
+    })
+}
+
+fn party(root: Node, role: &str) -> Option<InvoiceParty> {
+    let party = child(root, &[role, "Party"])?;
+    let name = text(party, &["PartyLegalEntity", "RegistrationName"])
+        .or_else(|| text(party, &["PartyName", "Name"]))?;
+    let endpoint = child(party, &["EndpointID"]).and_then(|id| {
+        Some(Endpoint {
+            scheme: id.attribute("schemeID")?.to_string(),
+            id: id.text()?.trim().to_string(),
+        })
+    });
+    Some(InvoiceParty {
+        name,
+        vat_id: text(party, &["PartyTaxScheme", "CompanyID"]),
+        endpoint,
+        country: text(party, &["PostalAddress", "Country", "IdentificationCode"]),
+    })
+}
+
+fn amount(node: Node, path: &[&str]) -> Result<Option<Decimal>> {
+    text(node, path)
+        .map(|value| {
+            Decimal::from_str(&value).map_err(|_| {
+                AnalyzerError::EInvoice(format!("{} is not a number: '{}'", path.join("/"), value))
+            })
+        })
+        .transpose()
+}
+
+fn date(node: Node, path: &[&str]) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(&text(node, path)?, "%Y-%m-%d").ok()
+}
+
+/// Writes `invoice` as a Peppol BIS Billing 3.0 invoice in UBL 2.1. Lines
+/// without a quantity are written as one unit at their amount.
+pub(super) fn write(invoice: &Invoice) -> String {
+    let currency = invoice
+        .currency
+        .as_ref()
+        .map(|currency| currency.code().to_string());
+    let mut xml = XmlWriter::new(currency.clone().unwrap_or_default());
+    xml.out
+        .push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.out.push_str(&format!(
+        "<Invoice xmlns=\"{}\" xmlns:cac=\"{}\" xmlns:cbc=\"{}\">\n",
+        INVOICE_NS, CAC_NS, CBC_NS
+    ));
+    xml.depth = 1;
+
+    xml.leaf("cbc:CustomizationID", Some(CUSTOMIZATION_ID));
+    xml.leaf("cbc:ProfileID", Some(PROFILE_ID));
+    xml.leaf("cbc:ID", invoice.number.as_deref());
+    xml.leaf(
+        "cbc:IssueDate",
+        invoice.issue_date.map(|d| d.to_string()).as_deref(),
+    );
+    xml.leaf(
+        "cbc:DueDate",
+        invoice.due_date.map(|d| d.to_string()).as_deref(),
+    );
+    xml.leaf("cbc:InvoiceTypeCode", Some(INVOICE_TYPE_CODE));
+    xml.leaf("cbc:DocumentCurrencyCode", currency.as_deref());
+    xml.leaf("cbc:BuyerReference", invoice.buyer_reference.as_deref());
+    if let Some(order) = &invoice.order_reference {
+        xml.open("cac:OrderReference");
+        xml.leaf("cbc:ID", Some(order));
+        xml.close("cac:OrderReference");
+    }
+
+    for (role, party) in [
+        ("cac:AccountingSupplierParty", &invoice.seller),
+        ("cac:AccountingCustomerParty", &invoice.buyer),
+    ] {
+        xml.open(role);
+        xml.open("cac:Party");
+        if let Some(party) = party {
+            if let Some(endpoint) = &party.endpoint {
+                xml.attributed(
+                    "cbc:EndpointID",
+                    ("schemeID", &endpoint.scheme),
+                    &endpoint.id,
+                );
+            }
+            xml.open("cac:PartyName");
+            xml.leaf("cbc:Name", Some(&party.name));
+            xml.close("cac:PartyName");
+            if let Some(country) = &party.country {
+                xml.open("cac:PostalAddress");
+                xml.open("cac:Country");
+                xml.leaf("cbc:IdentificationCode", Some(country));
+                xml.close("cac:Country");
+                xml.close("cac:PostalAddress");
+            }
+            if let Some(vat_id) = &party.vat_id {
+                xml.open("cac:PartyTaxScheme");
+                xml.leaf("cbc:CompanyID", Some(vat_id));
+                xml.tax_scheme();
+                xml.close("cac:PartyTaxScheme");
+            }
+            xml.open("cac:PartyLegalEntity");
+            xml.leaf("cbc:RegistrationName", Some(&party.name));
+            xml.close("cac:PartyLegalEntity");
+        }
+        xml.close("cac:Party");
+        xml.close(role);
+    }
+
+    if let Some(terms) = &invoice.payment_terms {
+        xml.open("cac:PaymentTerms");
+        xml.leaf("cbc:Note", Some(terms));
+        xml.close("cac:PaymentTerms");
+    }
+
+    for adjustment in &invoice.allowance_charges {
+        xml.open("cac:AllowanceCharge");
+        xml.leaf(
+            "cbc:ChargeIndicator",
+            Some(if adjustment.charge { "true" } else { "false" }),
+        );
+        xml.leaf("cbc:AllowanceChargeReason", adjustment.reason.as_deref());
+        xml.amount("cbc:Amount", Some(adjustment.amount));
+        xml.tax_category(
+            "cac:TaxCategory",
+            adjustment.tax_category.as_deref(),
+            adjustment.tax_percent,
+        );
+        xml.close("cac:AllowanceCharge");
+    }
+
+    xml.open("cac:TaxTotal");
+    xml.amount("cbc:TaxAmount", invoice.tax_total);
+    for tax in &invoice.taxes {
+        xml.open("cac:TaxSubtotal");
+        xml.amount("cbc:TaxableAmount", Some(tax.taxable));
+        xml.amount("cbc:TaxAmount", Some(tax.tax));
+        xml.tax_category("cac:TaxCategory", Some(&tax.category), tax.percent);
+        xml.close("cac:TaxSubtotal");
+    }
+    xml.close("cac:TaxTotal");
+
+    xml.open("cac:LegalMonetaryTotal");
+    let sum = |charge: bool| -> Option<Decimal> {
+        let mut amounts = invoice
+            .allowance_charges
+            .iter()
+            .filter(|adjustment| adjustment.charge == charge)
+            .map(|adjustment| adjustment.amount)
+            .peekable();
+        amounts.peek()?;
+        Some(amounts.sum())
+    };
+    let (allowances, charges) = (sum(false), sum(true));
+    let tax_exclusive = invoice
+        .line_total
+        .map(|total| total - allowances.unwrap_or_default() + charges.unwrap_or_default());
+    xml.amount("cbc:LineExtensionAmount", invoice.line_total);
+    xml.amount("cbc:TaxExclusiveAmount", tax_exclusive);
+    xml.amount("cbc:TaxInclusiveAmount", invoice.total);
+    xml.amount("cbc:AllowanceTotalAmount", allowances);
+    xml.amount("cbc:ChargeTotalAmount", charges);
+    xml.amount("cbc:PayableAmount", invoice.payable);
+    xml.close("cac:LegalMonetaryTotal");
+
+    for (index, line) in invoice.lines.iter().enumerate() {
+        let mut quantity = line.quantity.unwrap_or(Decimal::ONE);
+        let mut price = line
+            .unit_price
+            .or_else(|| line.amount.checked_div(quantity));
+        // A price must not be negative (BR-27); a credited line is a
+        // negative quantity at a positive price.
+        if let Some(negative) = price.filter(|price| *price < Decimal::ZERO) {
+            quantity = -quantity;
+            price = Some(-negative);
+        }
+        xml.open("cac:InvoiceLine");
+        xml.leaf("cbc:ID", Some(&(index + 1).to_string()));
+        xml.attributed(
+            "cbc:InvoicedQuantity",
+            ("unitCode", UNIT_CODE),
+            &quantity.normalize().to_string(),
+        );
+        xml.amount("cbc:LineExtensionAmount", Some(line.amount));
+        xml.open("cac:Item");
+        xml.leaf("cbc:Name", Some(&line.description));
+        xml.tax_category(
+            "cac:ClassifiedTaxCategory",
+            line.tax_category.as_deref(),
+            line.tax_percent,
+        );
+        xml.close("cac:Item");
+        xml.open("cac:Price");
+        xml.amount("cbc:PriceAmount", price);
+        xml.close("cac:Price");
+        xml.close("cac:InvoiceLine");
+    }
+
+    xml.out.push_str("</Invoice>\n");
+    xml.out
+}
+
+/// Indented XML output. Elements without a value are left out, for the
+/// check to report if they are required.
+struct XmlWriter {
+    out: String,
+    depth: usize,
+    currency: String,
+}
+
+impl XmlWriter {
+    fn new(currency: String) -> Self {
+        Self {
+            out: String::new(),
+            depth: 0,
+            currency,
+        }
+    }
+
+    fn indent(&mut self) {
+        self.out.push_str(&"  ".repeat(self.depth));
+    }
+
+    fn open(&mut self, tag: &str) {
+        self.indent();
+        let _ = writeln!(self.out, "<{}>", tag);
+        self.depth += 1;
+    }
+
+    fn close(&mut self, tag: &str) {
+        self.depth -= 1;
+        self.indent();
+        let _ = writeln!(self.out, "</{}>", tag);
+    }
+
+    fn leaf(&mut self, tag: &str, value: Option<&str>) {
+        if let Some(value) = value {
+            self.indent();
+            let _ = writeln!(self.out, "<{}>{}</{}>", tag, escape(value), tag);
+        }
+    }
+
+    fn attributed(&mut self, tag: &str, (name, attribute): (&str, &str), value: &str) {
+        self.indent();
+        let _ = writeln!(
+            self.out,
+            "<{} {}=\"{}\">{}</{}>",
+            tag,
+            name,
+            escape(attribute),
+            escape(value),
+            tag
+        );
+    }
+
+    fn amount(&mut self, tag: &str, amount: Option<Decimal>) {
+        if let Some(amount) = amount {
+            let currency = self.currency.clone();
+            self.attributed(tag, ("currencyID", &currency), &format_amount(amount));
+        }
+    }
+
+    fn tax_category(&mut self, tag: &str, category: Option<&str>, percent: Option<Decimal>) {
+        self.open(tag);
+        self.leaf("cbc:ID", category);
+        self.leaf(
+            "cbc:Percent",
+            percent.map(|p| p.normalize().to_string()).as_deref(),
+        );
+        self.tax_scheme();
+        self.close(tag);
+    }
+
+    fn tax_scheme(&mut self) {
+        self.open("cac:TaxScheme");
+        self.leaf("cbc:ID", Some("VAT"));
+        self.close("cac:TaxScheme");
+    }
+}
+
+/// At least two decimals, as amounts are usually shown.
+fn format_amount(amount: Decimal) -> String {
+    let amount = amount.normalize();
+    if amount.scale() < 2 {
+        format!("{:.2}", amount)
+    } else {
+        amount.to_string()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Occurs {
+    Optional,
+    Required,
+    Repeated,
+    RequiredRepeated,
+}
+
+use Occurs::*;
+
+/// The children of each aggregate `write` produces, in the order of the
+/// UBL 2.1 schema, with the elements EN 16931 and Peppol BIS Billing 3.0
+/// make mandatory required.
+fn sequence(aggregate: &str) -> Option<&'static [(&'static str, Occurs)]> {
+    const TAX_CATEGORY: &[(&str, Occurs)] = &[
+        ("ID", Required),
+        ("Percent", Optional),
+        ("TaxExemptionReasonCode", Optional),
+        ("TaxExemptionReason", Optional),
+        ("TaxScheme", Required),
+    ];
+    Some(match aggregate {
+        "Invoice" => &[
+            ("CustomizationID", Required),
+            ("ProfileID", Required),
+            ("ID", Required),
+            ("IssueDate", Required),
+            ("DueDate", Optional),
+            ("InvoiceTypeCode", Required),
+            ("Note", Repeated),
+            ("TaxPointDate", Optional),
+            ("DocumentCurrencyCode", Required),
+            ("TaxCurrencyCode", Optional),
+            ("AccountingCost", Optional),
+            ("BuyerReference", Optional),
+            ("InvoicePeriod", Optional),
+            ("OrderReference", Optional),
+            ("BillingReference", Repeated),
+            ("DespatchDocumentReference", Optional),
+            ("ReceiptDocumentReference", Optional),
+            ("OriginatorDocumentReference", Optional),
+            ("ContractDocumentReference", Optional),
+            ("AdditionalDocumentReference", Repeated),
+            ("ProjectReference", Optional),
+            ("AccountingSupplierParty", Required),
+            ("AccountingCustomerParty", Required),
+            ("PayeeParty", Optional),
+            ("TaxRepresentativeParty", Optional),
+            ("Delivery", Optional),
+            ("PaymentMeans", Repeated),
+            ("PaymentTerms", Optional),
+            ("AllowanceCharge", Repeated),
+            ("TaxTotal", RequiredRepeated),
+            ("LegalMonetaryTotal", Required),
+            ("InvoiceLine", RequiredRepeated),
+        ],
+        "AccountingSupplierParty" | "AccountingCustomerParty" => &[("Party", Required)],
+        "Party" => &[
+            ("EndpointID", Optional),
+            ("PartyIdentification", Repeated),
+            ("PartyName", Repeated),
+            ("PostalAddress", Optional),
+            ("PartyTaxScheme", Repeated),
+            ("PartyLegalEntity", Optional),
+            ("Contact", Optional),
+        ],
+        "OrderReference" => &[("ID", Required)],
+        "PartyName" => &[("Name", Required)],
+        "PostalAddress" => &[
+            ("StreetName", Optional),
+            ("AdditionalStreetName", Optional),
+            ("CityName", Optional),
+            ("PostalZone", Optional),
+            ("CountrySubentity", Optional),
+            ("AddressLine", Repeated),
+            ("Country", Required),
+        ],
+        "Country" => &[("IdentificationCode", Required)],
+        "PartyTaxScheme" => &[("CompanyID", Required), ("TaxScheme", Required)],
+        "PartyLegalEntity" => &[
+            ("RegistrationName", Required),
+            ("CompanyID", Optional),
+            ("CompanyLegalForm", Optional),
+        ],
+        "TaxScheme" => &[("ID", Required)],
+        "PaymentTerms" => &[("Note", Repeated)],
+        "AllowanceCharge" => &[
+            ("ChargeIndicator", Required),
+            ("AllowanceChargeReasonCode", Optional),
+            ("AllowanceChargeReason", Optional),
+            ("MultiplierFactorNumeric", Optional),
+            ("Amount", Required),
+            ("BaseAmount", Optional),
+            ("TaxCategory", Optional),
+        ],
+        "TaxTotal" => &[("TaxAmount", Required), ("TaxSubtotal", Repeated)],
+        "TaxSubtotal" => &[
+            ("TaxableAmount", Required),
+            ("TaxAmount", Required),
+            ("TaxCategory", Required),
+        ],
+        "TaxCategory" | "ClassifiedTaxCategory" => TAX_CATEGORY,
+        "LegalMonetaryTotal" => &[
+            ("LineExtensionAmount", Required),
+            ("TaxExclusiveAmount", Required),
+            ("TaxInclusiveAmount", Required),
+            ("AllowanceTotalAmount", Optional),
+            ("ChargeTotalAmount", Optional),
+            ("PrepaidAmount", Optional),
+            ("PayableRoundingAmount", Optional),
+            ("PayableAmount", Required),
+        ],
+        "InvoiceLine" => &[
+            ("ID", Required),
+            ("Note", Repeated),
+            ("InvoicedQuantity", Required),
+            ("LineExtensionAmount", Required),
+            ("AccountingCost", Optional),
+            ("InvoicePeriod", Optional),
+            ("OrderLineReference", Optional),
+            ("DocumentReference", Optional),
+            ("AllowanceCharge", Repeated),
+            ("Item", Required),
+            ("Price", Required),
+        ],
+        "Item" => &[
+            ("Description", Repeated),
+            ("Name", Required),
+            ("BuyersItemIdentification", Optional),
+            ("SellersItemIdentification", Optional),
+            ("StandardItemIdentification", Optional),
+            ("OriginCountry", Optional),
+            ("CommodityClassification", Repeated),
+            ("ClassifiedTaxCategory", Required),
+            ("AdditionalItemProperty", Repeated),
+        ],
+        "Price" => &[
+            ("PriceAmount", Required),
+            ("BaseQuantity", Optional),
+            ("AllowanceCharge", Optional),
+        ],
+        _ => return None,
+    })
+}
+
+/// Checks UBL invoice XML, such as the output of `to_ubl`, against the
+/// part of the UBL 2.1 schema that is written here (element order,
+/// cardinality, namespaces and value formats), against the EN 16931
+/// calculation rules and against the Peppol BIS Billing 3.0 rules on
+/// identifiers and references. It is not a replacement for the official
+/// Schematron: rules about data that is never written, such as street
+/// addresses, are not checked. Returns every problem found.
+pub fn check_ubl(xml: &str) -> Vec<String> {
+    let document = match Document::parse(xml) {
+        Ok(document) => document,
+        Err(e) => return vec![format!("not well-formed XML: {}", e)],
+    };
+    let root = document.root_element();
+    if root.tag_name().namespace() != Some(INVOICE_NS) || !root.has_tag_name("Invoice") {
+        return vec![format!(
+            "root element is {}, not a UBL Invoice",
+            root.tag_name().name()
+        )];
+    }
+
+    let currency = text(root, &["DocumentCurrencyCode"]);
+    let mut problems = Vec::new();
+    if let Some(code) = &currency {
+        if Currency::new(code).is_none() {
+            problems.push(format!(
+                "DocumentCurrencyCode '{}' is not an ISO 4217 code",
+                code
+            ));
+        }
+    }
+    check_element(root, currency.as_deref(), &mut problems);
+    check_parties(root, &mut problems);
+    check_peppol(root, &mut problems);
+    check_totals(root, &mut problems);
+    problems
+}
+
+fn check_element(node: Node, currency: Option<&str>, problems: &mut Vec<String>) {
+    let name = node.tag_name().name();
+    let Some(sequence) = sequence(name) else {
+        // Aggregates `write` never produces, such as PaymentMeans, are
+        // left to the official validation.
+        if !node.children().any(|child| child.is_element()) {
+            check_value(node, currency, problems);
+        }
+        return;
+    };
+    if name != "Invoice" && node.tag_name().namespace() != Some(CAC_NS) {
+        problems.push(format!("{} is not in the cac namespace", name));
+    }
+
+    let mut position = 0;
+    let mut counts = vec![0; sequence.len()];
+    for child in node.children().filter(Node::is_element) {
+        let child_name = child.tag_name().name();
+        match sequence.iter().position(|(tag, _)| *tag == child_name) {
+            None => problems.push(format!("unexpected element {} in {}", child_name, name)),
+            Some(index) if index < position => {
+                problems.push(format!("{} is out of order in {}", child_name, name));
+            }
+            Some(index) => {
+                position = index;
+                counts[index] += 1;
+                if counts[index] == 2 && matches!(sequence[index].1, Optional | Required) {
+                    problems.push(format!("{} has more than one {}", name, child_name));
+                }
+            }
+        }
+        check_element(child, currency, problems);
+    }
+    for ((tag, occurs), count) in sequence.iter().zip(counts) {
+        if count == 0 && matches!(occurs, Required | RequiredRepeated) {
+            problems.push(format!("{} is missing {}", name, tag));
+        }
+    }
+}
+
+/// Basic components: namespace, and the format of amounts, dates,
+/// quantities and codes.
+fn check_value(node: Node, currency: Option<&str>, problems: &mut Vec<String>) {
+    let name = node.tag_name().name();
+    if node.tag_name().namespace() != Some(CBC_NS) {
+        problems.push(format!("{} is not in the cbc namespace", name));
+    }
+    let value = node.text().unwrap_or("").trim();
+    if value.is_empty() {
+        problems.push(format!("{} is empty", name));
+        return;
+    }
+
+    if name.ends_with("Amount") {
+        match Decimal::from_str(value) {
+            Err(_) => problems.push(format!("{} is not an amount: '{}'", name, value)),
+            Ok(price) if name == "PriceAmount" && price < Decimal::ZERO => {
+                problems.push(format!("BR-27: the item price {} is negative", value));
+            }
+            Ok(_) => {}
+        }
+        match node.attribute("currencyID") {
+            None => problems.push(format!("{} has no currencyID", name)),
+            Some(code) if Some(code) != currency => problems.push(format!(
+                "{} is in {}, not in the document currency",
+                name, code
+            )),
+            Some(_) => {}
+        }
+    } else if name.ends_with("Date") {
+        if NaiveDate::parse_from_str(value, "%Y-%m-%d").is_err() {
+            problems.push(format!("{} is not a YYYY-MM-DD date: '{}'", name, value));
+        }
+    } else if name == "ChargeIndicator" {
+        if !matches!(value, "true" | "false") {
+            problems.push(format!("ChargeIndicator is not true or false: '{}'", value));
+        }
+    } else if matches!(
+        name,
+        "Percent" | "InvoicedQuantity" | "BaseQuantity" | "MultiplierFactorNumeric"
+    ) {
+        if Decimal::from_str(value).is_err() {
+            problems.push(format!("{} is not a number: '{}'", name, value));
+        }
+    } else if name == "ID" {
+        let parent = node.parent_element().map(|p| p.tag_name().name());
+        if matches!(parent, Some("TaxCategory" | "ClassifiedTaxCategory"))
+            && !TAX_CATEGORIES.contains(&value)
+        {
+            problems.push(format!("'{}' is not a VAT category code", value));
+        }
+    } else if name == "EndpointID" {
+        let scheme = node.attribute("schemeID").unwrap_or("");
+        if !Endpoint::is_scheme(scheme) {
+            problems.push(format!(
+                "EndpointID {} has no EAS schemeID, but '{}'",
+                value, scheme
+            ));
+        }
+    } else if name == "IdentificationCode" {
+        if value.len() != 2 || !value.chars().all(|c| c.is_ascii_uppercase()) {
+            problems.push(format!("'{}' is not an ISO 3166 country code", value));
+        }
+    } else if name == "CompanyID"
+        && node.parent_element().map(|p| p.tag_name().name()) == Some("PartyTaxScheme")
+        && !value
+            .get(..2)
+            .is_some_and(|p| p.chars().all(|c| c.is_ascii_uppercase()))
+    {
+        problems.push(format!(
+            "BR-CO-09: VAT identifier {} has no country prefix",
+            value
+        ));
+    }
+}
+
+/// What EN 16931 and Peppol require of the seller and the buyer.
+fn check_parties(root: Node, problems: &mut Vec<String>) {
+    for (role, label, rules) in [
+        (
+            "AccountingSupplierParty",
+            "seller",
+            ["BR-06", "BR-08", "PEPPOL-EN16931-R020"],
+        ),
+        (
+            "AccountingCustomerParty",
+            "buyer",
+            ["BR-07", "BR-10", "PEPPOL-EN16931-R010"],
+        ),
+    ] {
+        let Some(party) = child(root, &[role, "Party"]) else {
+            continue;
+        };
+        for (rule, path, missing) in [
+            (
+                rules[0],
+                &["PartyLegalEntity", "RegistrationName"][..],
+                "name",
+            ),
+            (rules[1], &["PostalAddress"][..], "postal address"),
+            (rules[2], &["EndpointID"][..], "electronic address"),
+        ] {
+            if child(party, path).is_none() {
+                problems.push(format!("{}: the {} has no {}", rule, label, missing));
+            }
+        }
+    }
+}
+
+/// The Peppol rules on profile, references and payment information that
+/// the schema table cannot express.
+fn check_peppol(root: Node, problems: &mut Vec<String>) {
+    for (tag, expected, rule) in [
+        ("CustomizationID", CUSTOMIZATION_ID, "PEPPOL-EN16931-R004"),
+        ("ProfileID", PROFILE_ID, "PEPPOL-EN16931-R007"),
+    ] {
+        if let Some(value) = text(root, &[tag]).filter(|value| value != expected) {
+            problems.push(format!("{}: {} is {}, not {}", rule, tag, value, expected));
+        }
+    }
+    if text(root, &["BuyerReference"]).is_none() && text(root, &["OrderReference", "ID"]).is_none()
+    {
+        problems.push(
+            "PEPPOL-EN16931-R003: neither a buyer reference nor an order reference is given"
+                .to_string(),
+        );
+    }
+    let payable = text(root, &["LegalMonetaryTotal", "PayableAmount"])
+        .and_then(|amount| Decimal::from_str(&amount).ok())
+        .unwrap_or_default();
+    if payable > Decimal::ZERO
+        && text(root, &["DueDate"]).is_none()
+        && text(root, &["PaymentTerms", "Note"]).is_none()
+    {
+        problems.push(
+            "BR-CO-25: an amount is due, but neither a due date nor payment terms are given"
+                .to_string(),
+        );
+    }
+}
+
+fn compare(
+    problems: &mut Vec<String>,
+    rule: &str,
+    label: &str,
+    stated: Option<Decimal>,
+    expected: Decimal,
+) {
+    if let Some(stated) = stated.filter(|stated| *stated != expected) {
+        problems.push(format!(
+            "{}: {} is {}, but should be {}",
+            rule,
+            label,
+            format_amount(stated),
+            format_amount(expected)
+        ));
+    }
+}
+
+/// The EN 16931 rules tying the totals to the lines and the VAT breakdown.
+/// Amounts that do not parse were reported already and count as zero.
+fn check_totals(root: Node, problems: &mut Vec<String>) {
+    let value = |node: Option<Node>, path: &[&str]| -> Option<Decimal> {
+        Decimal::from_str(&text(node?, path)?).ok()
+    };
+    let totals = child(root, &["LegalMonetaryTotal"]);
+    let total = |tag: &str| value(totals, &[tag]);
+
+    let lines: Vec<Node> = children(root, "InvoiceLine").collect();
+    let line_sum: Decimal = lines
+        .iter()
+        .filter_map(|line| value(Some(*line), &["LineExtensionAmount"]))
+        .sum();
+    compare(
+        problems,
+        "BR-CO-10",
+        "the sum of line amounts",
+        total("LineExtensionAmount"),
+        line_sum,
+    );
+
+    // Allowances and charges on the invoice as a whole.
+    let adjustments: Vec<(bool, Node)> = children(root, "AllowanceCharge")
+        .map(|node| {
+            (
+                text(node, &["ChargeIndicator"]).as_deref() == Some("true"),
+                node,
+            )
+        })
+        .collect();
+    for (charge, tag, rule, label) in [
+        (
+            false,
+            "AllowanceTotalAmount",
+            "BR-CO-11",
+            "the sum of allowances",
+        ),
+        (true, "ChargeTotalAmount", "BR-CO-12", "the sum of charges"),
+    ] {
+        let amounts: Vec<Decimal> = adjustments
+            .iter()
+            .filter(|(is_charge, _)| *is_charge == charge)
+            .filter_map(|(_, node)| value(Some(*node), &["Amount"]))
+            .collect();
+        let sum = amounts.iter().sum();
+        match total(tag) {
+            None if !amounts.is_empty() => problems.push(format!(
+                "{}: {} is {}, but LegalMonetaryTotal has no {}",
+                rule,
+                label,
+                format_amount(sum),
+                tag
+            )),
+            stated => compare(problems, rule, label, stated, sum),
+        }
+    }
+
+    let line_total = total("LineExtensionAmount").unwrap_or(line_sum);
+    let allowances = total("AllowanceTotalAmount").unwrap_or_default();
+    let charges = total("ChargeTotalAmount").unwrap_or_default();
+    let tax_exclusive = line_total - allowances + charges;
+    compare(
+        problems,
+        "BR-CO-13",
+        "the total without VAT",
+        total("TaxExclusiveAmount"),
+        tax_exclusive,
+    );
+
+    let tax_total_node = child(root, &["TaxTotal"]);
+    let subtotals: Vec<Node> = tax_total_node
+        .into_iter()
+        .flat_map(|total| children(total, "TaxSubtotal"))
+        .collect();
+    let subtotal_sum: Decimal = subtotals
+        .iter()
+        .filter_map(|subtotal| value(Some(*subtotal), &["TaxAmount"]))
+        .sum();
+    let tax_total = value(tax_total_node, &["TaxAmount"]);
+    compare(
+        problems,
+        "BR-CO-14",
+        "the VAT total",
+        tax_total,
+        subtotal_sum,
+    );
+
+    let tax_inclusive = tax_exclusive + tax_total.unwrap_or(subtotal_sum);
+    compare(
+        problems,
+        "BR-CO-15",
+        "the total with VAT",
+        total("TaxInclusiveAmount"),
+        tax_inclusive,
+    );
+    let payable = total("TaxInclusiveAmount").unwrap_or(tax_inclusive)
+        - total("PrepaidAmount").unwrap_or_default()
+        + total("PayableRoundingAmount").unwrap_or_default();
+    compare(
+        problems,
+        "BR-CO-16",
+        "the amount due",
+        total("PayableAmount"),
+        payable,
+    );
+
+    // Each VAT category and rate covers the lines, allowances and charges
+    // that carry it.
+    let mut taxable: BTreeMap<(String, Option<Decimal>), Decimal> = BTreeMap::new();
+    let mut add = |category: Option<Node>, amount: Decimal| {
+        let key = (
+            category.and_then(|c| text(c, &["ID"])).unwrap_or_default(),
+            value(category, &["Percent"]).map(|p| p.normalize()),
+        );
+        *taxable.entry(key).or_default() += amount;
+    };
+    for line in &lines {
+        add(
+            child(*line, &["Item", "ClassifiedTaxCategory"]),
+            value(Some(*line), &["LineExtensionAmount"]).unwrap_or_default(),
+        );
+    }
+    for (charge, node) in &adjustments {
+        if child(*node, &["TaxCategory"]).is_none() {
+            let (rule, kind) = if *charge {
+                ("BR-37", "charge")
+            } else {
+                ("BR-32", "allowance")
+            };
+            problems.push(format!("{}: an invoice {} has no VAT category", rule, kind));
+        }
+        let amount = value(Some(*node), &["Amount"]).unwrap_or_default();
+        add(
+            child(*node, &["TaxCategory"]),
+            if *charge { amount } else { -amount },
+        );
+    }
+    for subtotal in subtotals {
+        let category = child(subtotal, &["TaxCategory"]);
+        let id = category.and_then(|c| text(c, &["ID"])).unwrap_or_default();
+        let percent = value(category, &["Percent"]).map(|p| p.normalize());
+        let basis = value(Some(subtotal), &["TaxableAmount"]);
+        let label = format!("the taxable amount of VAT category {}", id);
+        let lines_total = taxable.remove(&(id.clone(), percent)).unwrap_or_default();
+        compare(
+            problems,
+            &format!("BR-{}-08", id),
+            &label,
+            basis,
+            lines_total,
+        );
+
+        if let (Some(basis), Some(percent), Some(tax)) =
+            (basis, percent, value(Some(subtotal), &["TaxAmount"]))
+        {
+            let expected = (basis * percent / Decimal::ONE_HUNDRED).round_dp(2);
+            if (tax - expected).abs() > Decimal::new(1, 2) {
+                problems.push(format!(
+                    "BR-CO-17: the VAT of category {} is {}, but {}% of {} is {}",
+                    id,
+                    format_amount(tax),
+                    percent,
+                    format_amount(basis),
+                    format_amount(expected)
+                ));
+            }
+        }
+    }
+    for ((id, percent), _) in taxable {
+        let rate = percent
+            .map(|percent| format!(" at {}%", percent))
+            .unwrap_or_default();
+        problems.push(format!(
+            "BR-CO-18: lines in VAT category {}{} have no VAT breakdown",
+            id, rate
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{to_ubl, InvoiceSyntax};
+    use super::*;
+
+    #[test]
+    fn test_check_ubl_reports_schema_and_calculation_errors() {
+        let invoice = Invoice {
+            number: Some("INV-1".to_string()),
+            issue_date: NaiveDate::from_ymd_opt(2024, 1, 15),
+            currency: Currency::new("EUR"),
+            seller: Some(InvoiceParty {
+                name: "Seller GmbH".to_string(),
+                vat_id: Some("DE123456789".to_string()),
+                endpoint: Endpoint::for_vat_id("DE123456789"),
+                country: Some("DE".to_string()),
+            }),
+            buyer: Some(InvoiceParty {
+                name: "Buyer SARL".to_string(),
+                vat_id: None,
+                endpoint: Endpoint::parse("0009:12345678200077"),
+                country: Some("FR".to_string()),
+            }),
+            buyer_reference: Some("Dept. 42".to_string()),
+            payment_terms: Some("30 days net".to_string()),
+            lines: vec![InvoiceLine {
+                description: "Widget".to_string(),
+                quantity: Some(Decimal::from(4)),
+                unit_price: Some(Decimal::new(25, 0)),
+                amount: Decimal::new(100, 0),
+                tax_category: Some("S".to_string()),
+                tax_percent: Some(Decimal::new(19, 0)),
+            }],
+            taxes: vec![TaxSubtotal {
+                category: "S".to_string(),
+                percent: Some(Decimal::new(19, 0)),
+                taxable: Decimal::new(100, 0),
+                tax: Decimal::new(19, 0),
+            }],
+            line_total: Some(Decimal::new(100, 0)),
+            tax_total: Some(Decimal::new(19, 0)),
+            total: Some(Decimal::new(119, 0)),
+            payable: Some(Decimal::new(119, 0)),
+            ..Invoice::default()
+        };
+        let xml = write(&invoice);
+        assert_eq!(check_ubl(&xml), Vec::<String>::new());
+        assert_eq!(parse(&xml).unwrap(), invoice);
+
+        let broken = xml
+            .replace("  <cbc:InvoiceTypeCode>380</cbc:InvoiceTypeCode>\n", "")
+            .replace("  <cbc:BuyerReference>Dept. 42</cbc:BuyerReference>\n", "")
+            .replace(
+                "<cbc:EndpointID schemeID=\"0009\">12345678200077</cbc:EndpointID>",
+                "",
+            )
+            .replace(
+                "<cbc:IssueDate>2024-01-15</cbc:IssueDate>",
+                "<cbc:IssueDate>15.01.2024</cbc:IssueDate>",
+            )
+            .replace(
+                "<cbc:TaxAmount currencyID=\"EUR\">19.00</cbc:TaxAmount>\n    <cac:TaxSubtotal>",
+                "<cbc:TaxAmount currencyID=\"EUR\">20.00</cbc:TaxAmount>\n    <cac:TaxSubtotal>",
+            );
+
+        assert_eq!(
+            check_ubl(&broken),
+            vec![
+                "IssueDate is not a YYYY-MM-DD date: '15.01.2024'",
+                "Invoice is missing InvoiceTypeCode",
+                "PEPPOL-EN16931-R010: the buyer has no electronic address",
+                "PEPPOL-EN16931-R003: neither a buyer reference nor an order reference is given",
+                "BR-CO-14: the VAT total is 20.00, but should be 19.00",
+                "BR-CO-15: the total with VAT is 119.00, but should be 120.00",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_peppol_invoice_with_allowances_and_several_rates() {
+        let xml = include_str!("../../fixtures/einvoice/peppol-allowances.xml");
+        assert_eq!(check_ubl(xml), Vec::<String>::new());
+
+        let invoice = parse(xml).unwrap();
+        assert_eq!(invoice.lines.len(), 3);
+        assert_eq!(invoice.lines[2].tax_category.as_deref(), Some("E"));
+        assert_eq!(invoice.allowance_charges.len(), 2);
+        assert_eq!(
+            invoice.allowance_charges[0].signed_amount(),
+            Decimal::new(-100, 0)
+        );
+        assert_eq!(invoice.taxes.len(), 3);
+        assert_eq!(invoice.buyer_reference.as_deref(), Some("FIN-0042"));
+        assert_eq!(invoice.order_reference.as_deref(), Some("PO-2024-118"));
+        assert_eq!(
+            invoice
+                .buyer
+                .as_ref()
+                .and_then(|buyer| buyer.endpoint.clone()),
+            Endpoint::parse("0208:0844044609")
+        );
+        assert_eq!(invoice.total, Some(Decimal::new(166150, 2)));
+        assert_eq!(invoice.payable, Some(Decimal::new(1500, 0)));
+
+        let document = invoice.into_document(InvoiceSyntax::Ubl);
+        assert_eq!(document.metadata.line_items.len(), 5);
+        let net: Decimal = document
+            .metadata
+            .line_items
+            .iter()
+            .map(|item| item.amount.amount)
+            .sum();
+        assert_eq!(net, Decimal::new(1450, 0));
+        let written = to_ubl(&document).unwrap();
+        assert_eq!(check_ubl(&written), Vec::<String>::new());
+        // The volume discount is written as an allowance again, not as a
+        // line with a negative price.
+        let written = parse(&written).unwrap();
+        assert_eq!(written.allowance_charges.len(), 1);
+        assert!(written
+            .lines
+            .iter()
+            .all(|line| line.unit_price.unwrap() >= Decimal::ZERO));
+        assert_eq!(written.total, Some(Decimal::new(166150, 2)));
+
+        let negative = xml.replace(
+            "<cbc:PriceAmount currencyID=\"EUR\">20.00",
+            "<cbc:PriceAmount currencyID=\"EUR\">-20.00",
+        );
+        assert!(check_ubl(&negative)
+            .iter()
+            .any(|problem| problem.starts_with("BR-27")));
+
+        let broken = xml.replace(
+            "<cbc:TaxExclusiveAmount currencyID=\"EUR\">1450.00",
+            "<cbc:TaxExclusiveAmount currencyID=\"EUR\">1500.00",
+        );
+        assert!(check_ubl(&broken)
+            .iter()
+            .any(|problem| problem.starts_with("BR-CO-13")));
+    }
+}
@@ -0,0 +1,116 @@
+use rust_decimal::Decimal;
+use std::time::Duration;
+
+/// Everything that can go wrong between sending a document to a model and
+/// getting a typed result back. Variants that involve model output keep the
+/// raw text so callers can log the offending payload.
+#[derive(Debug, thiserror::Error)]
+pub enum AnalyzerError {
+    #[error("transport error: {0}")]
+    Transport(#[from] reqwest::Error),
+
+    #[error("provider error{}: {message}", code.map(|c| format!(" ({})", c)).unwrap_or_default())]
+    Provider {
+        message: String,
+        error_type: Option<String>,
+        code: Option<u16>,
+    },
+
+    #[error("rate limited: {message}")]
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+
+    #[error("model returned an empty completion")]
+    EmptyCompletion,
+
+    #[error("model output is not valid JSON: {source}")]
+    MalformedJson {
+        raw: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("model output does not match the expected schema: {}", errors.join("; "))]
+    SchemaMismatch { raw: String, errors: Vec<String> },
+
+    #[error("no models configured")]
+    NoModels,
+
+    #[error("prompt template error: {0}")]
+    Template(String),
+
+    #[error("budget of ${budget} exhausted (spent ${spent})")]
+    BudgetExceeded { spent: Decimal, budget: Decimal },
+
+    #[error("no price for {model}, so its cost cannot be held to the budget")]
+    UnpricedModel { model: String },
+
+    #[error("{requests} request(s) reported no token usage, so the budget cannot be held")]
+    UnmeteredRequests { requests: u32 },
+
+    #[error("cannot read PDF: {0}")]
+    Pdf(String),
+
+    #[error("cannot import statement: {0}")]
+    Import(String),
+
+    #[error("invalid e-invoice: {0}")]
+    EInvoice(String),
+}
+
+impl AnalyzerError {
+    /// Classifies a failed parse of `raw` as malformed JSON or a schema mismatch.
+    pub fn from_parse(raw: &str, source: serde_json::Error) -> Self {
+        let raw = raw.to_string();
+        if source.is_data() {
+            AnalyzerError::SchemaMismatch {
+                raw,
+                errors: vec![source.to_string()],
+            }
+        } else {
+            AnalyzerError::MalformedJson { raw, source }
+        }
+    }
+
+    /// Rate limits, server errors and network failures are worth retrying;
+    /// bad requests, authentication failures and bad model output are not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AnalyzerError::Transport(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            AnalyzerError::RateLimited { .. } => true,
+            AnalyzerError::Provider { code, .. } => code.is_some_and(|c| c == 408 || c >= 500),
+            _ => false,
+        }
+    }
+
+    /// The model output that caused the error, if any.
+    pub fn raw_output(&self) -> Option<&str> {
+        match self {
+            AnalyzerError::MalformedJson { raw, .. }
+            | AnalyzerError::SchemaMismatch { raw, .. } => Some(raw),
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T, E = AnalyzerError> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_parse_distinguishes_syntax_and_schema() {
+        let syntax = serde_json::from_str::<serde_json::Value>("{oops").unwrap_err();
+        let error = AnalyzerError::from_parse("{oops", syntax);
+        assert!(matches!(error, AnalyzerError::MalformedJson { .. }));
+        assert_eq!(error.raw_output(), Some("{oops"));
+
+        let schema = serde_json::from_str::<Vec<u8>>(r#"{"a": 1}"#).unwrap_err();
+        let error = AnalyzerError::from_parse(r#"{"a": 1}"#, schema);
+        assert!(matches!(error, AnalyzerError::SchemaMismatch { .. }));
+        assert!(!error.is_retryable());
+    }
+}
@@ -0,0 +1,511 @@
+use crate::dates::parse_date;
+use crate::document_types::{DocumentType, FinancialDocument};
+use crate::financial_analyzer::FinancialAnalyzer;
+use crate::money::Money;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The gold standard for one corpus document. The listed fields are scored,
+/// and any other field the model extracts counts as unexpected against its
+/// precision, so a label should list every field the document has.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExpectedDocument {
+    pub document_type: DocumentType,
+    #[serde(default)]
+    pub extracted_data: BTreeMap<String, String>,
+}
+
+/// A corpus document and its expected extraction.
+#[derive(Debug, Clone)]
+pub struct EvalCase {
+    pub name: String,
+    pub text: String,
+    pub expected: ExpectedDocument,
+}
+
+/// Loads `<name>.txt` documents from `dir`, each labelled by a sibling
+/// `<name>.expected.json`.
+pub fn load_corpus(dir: &Path) -> std::io::Result<Vec<EvalCase>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "txt"));
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let name = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            let gold = path.with_file_name(format!("{}.expected.json", name));
+            let expected = serde_json::from_slice(&std::fs::read(&gold)?).map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{}: {}", gold.display(), e),
+                )
+            })?;
+            Ok(EvalCase {
+                name,
+                text: std::fs::read_to_string(&path)?,
+                expected,
+            })
+        })
+        .collect()
+}
+
+/// How an extracted value compares with the expected one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldOutcome {
+    /// Equal after trimming and ignoring case and repeated whitespace.
+    Exact,
+    /// The same date, or an amount within the tolerance.
+    Close,
+    Mismatch,
+    Missing,
+    /// Extracted, but not in the label: a hallucinated or extra field.
+    Unexpected,
+}
+
+/// Counts of outcomes for one field, or for all fields of a group.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FieldScore {
+    pub expected: usize,
+    pub exact: usize,
+    pub close: usize,
+    pub mismatched: usize,
+    pub missing: usize,
+    /// Values returned for fields the label does not list.
+    pub unexpected: usize,
+}
+
+impl FieldScore {
+    fn record(&mut self, outcome: FieldOutcome) {
+        if outcome != FieldOutcome::Unexpected {
+            self.expected += 1;
+        }
+        match outcome {
+            FieldOutcome::Exact => self.exact += 1,
+            FieldOutcome::Close => self.close += 1,
+            FieldOutcome::Mismatch => self.mismatched += 1,
+            FieldOutcome::Missing => self.missing += 1,
+            FieldOutcome::Unexpected => self.unexpected += 1,
+        }
+    }
+
+    fn add(&mut self, other: &FieldScore) {
+        self.expected += other.expected;
+        self.exact += other.exact;
+        self.close += other.close;
+        self.mismatched += other.mismatched;
+        self.missing += other.missing;
+        self.unexpected += other.unexpected;
+    }
+
+    pub fn correct(&self) -> usize {
+        self.exact + self.close
+    }
+
+    pub fn exact_match_rate(&self) -> f64 {
+        ratio(self.exact, self.expected)
+    }
+
+    /// Exact matches plus values within the numeric tolerance.
+    pub fn tolerant_match_rate(&self) -> f64 {
+        ratio(self.correct(), self.expected)
+    }
+
+    /// Share of the values the model returned that were correct, counting
+    /// values for fields the label does not list as wrong.
+    pub fn precision(&self) -> f64 {
+        ratio(
+            self.correct(),
+            self.correct() + self.mismatched + self.unexpected,
+        )
+    }
+
+    /// Share of the expected values the model returned correctly.
+    pub fn recall(&self) -> f64 {
+        ratio(self.correct(), self.expected)
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+/// The scored result of one model on one document.
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub model: String,
+    pub name: String,
+    pub document_type: String,
+    pub error: Option<String>,
+    pub fields: BTreeMap<String, FieldOutcome>,
+}
+
+/// Runs analyzers over a labelled corpus and scores their extractions.
+pub struct Evaluator {
+    tolerance: Decimal,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self {
+            tolerance: Decimal::new(1, 2),
+        }
+    }
+
+    /// Largest difference at which two amounts still count as a match.
+    pub fn with_tolerance(mut self, tolerance: Decimal) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Analyzes every case with `analyzer`, reported under `model`. A failed
+    /// analysis scores every expected field as missing.
+    pub async fn evaluate(
+        &self,
+        model: &str,
+        analyzer: &FinancialAnalyzer,
+        cases: &[EvalCase],
+    ) -> Vec<CaseResult> {
+        let mut results = Vec::with_capacity(cases.len());
+        for case in cases {
+            log::info!("Evaluating {} on {}", model, case.name);
+            let analysis = analyzer.analyze_document(&case.text).await;
+            let (fields, error) = match &analysis {
+                Ok(document) => (self.score(&case.expected, Some(document)), None),
+                Err(e) => (self.score(&case.expected, None), Some(e.to_string())),
+            };
+            results.push(CaseResult {
+                model: model.to_string(),
+                name: case.name.clone(),
+                document_type: type_label(&case.expected.document_type),
+                error,
+                fields,
+            });
+        }
+        results
+    }
+
+    /// Compares `actual` with the gold standard, field by field. The document
+    /// type is scored as a field of its own, and every non-empty extracted
+    /// field the label does not list is `Unexpected`.
+    pub fn score(
+        &self,
+        expected: &ExpectedDocument,
+        actual: Option<&FinancialDocument>,
+    ) -> BTreeMap<String, FieldOutcome> {
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "document_type".to_string(),
+            match actual {
+                None => FieldOutcome::Missing,
+                Some(document) if document.document_type == expected.document_type => {
+                    FieldOutcome::Exact
+                }
+                Some(_) => FieldOutcome::Mismatch,
+            },
+        );
+        for (key, value) in &expected.extracted_data {
+            let extracted = actual.and_then(|document| document.extracted_data.get(key));
+            fields.insert(key.clone(), self.compare(value, extracted));
+        }
+        for (key, value) in actual.iter().flat_map(|document| &document.extracted_data) {
+            if !value.trim().is_empty() && !expected.extracted_data.contains_key(key) {
+                fields.insert(key.clone(), FieldOutcome::Unexpected);
+            }
+        }
+        fields
+    }
+
+    fn compare(&self, expected: &str, actual: Option<&String>) -> FieldOutcome {
+        let Some(actual) = actual else {
+            return FieldOutcome::Missing;
+        };
+        if normalize(expected) == normalize(actual) {
+            return FieldOutcome::Exact;
+        }
+        if let (Some(expected), Some(actual)) = (parse_date(expected), parse_date(actual)) {
+            if expected.date == actual.date {
+                return FieldOutcome::Close;
+            }
+            return FieldOutcome::Mismatch;
+        }
+        if let (Ok(expected), Ok(actual)) = (Money::parse(expected), Money::parse(actual)) {
+            let within = expected
+                .checked_sub(&actual)
+                .is_some_and(|difference| difference.amount.abs() <= self.tolerance);
+            if within {
+                return FieldOutcome::Close;
+            }
+        }
+        FieldOutcome::Mismatch
+    }
+}
+
+fn normalize(value: &str) -> String {
+    value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn type_label(document_type: &DocumentType) -> String {
+    match document_type {
+        DocumentType::TaxForm(form) if !form.is_empty() => format!("TaxForm {}", form),
+        DocumentType::Other(label) => label.clone(),
+        other => format!("{:?}", other)
+            .split('(')
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+/// Scores for one model on one document type (`*` for all types).
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupReport {
+    pub model: String,
+    pub document_type: String,
+    pub documents: usize,
+    pub failures: usize,
+    pub total: FieldScore,
+    pub fields: BTreeMap<String, FieldScore>,
+}
+
+/// Per model and document type comparison of a set of evaluation runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalReport {
+    pub groups: Vec<GroupReport>,
+    pub cases: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    pub fn new(cases: Vec<CaseResult>) -> Self {
+        let mut groups: Vec<GroupReport> = Vec::new();
+        for case in &cases {
+            for document_type in [case.document_type.as_str(), "*"] {
+                let index = match groups
+                    .iter()
+                    .position(|g| g.model == case.model && g.document_type == document_type)
+                {
+                    Some(index) => index,
+                    None => {
+                        groups.push(GroupReport {
+                            model: case.model.clone(),
+                            document_type: document_type.to_string(),
+                            documents: 0,
+                            failures: 0,
+                            total: FieldScore::default(),
+                            fields: BTreeMap::new(),
+                        });
+                        groups.len() - 1
+                    }
+                };
+                let group = &mut groups[index];
+                group.documents += 1;
+                group.failures += usize::from(case.error.is_some());
+                for (field, outcome) in &case.fields {
+                    let mut score = FieldScore::default();
+                    score.record(*outcome);
+                    group.total.add(&score);
+                    group.fields.entry(field.clone()).or_default().add(&score);
+                }
+            }
+        }
+        groups.sort_by(|a, b| {
+            (&a.model, a.document_type == "*", &a.document_type).cmp(&(
+                &b.model,
+                b.document_type == "*",
+                &b.document_type,
+            ))
+        });
+        Self { groups, cases }
+    }
+
+    pub fn group(&self, model: &str, document_type: &str) -> Option<&GroupReport> {
+        self.groups
+            .iter()
+            .find(|g| g.model == model && g.document_type == document_type)
+    }
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<40} {:<16} {:>4} {:>4} {:>7} {:>7} {:>7} {:>7}",
+            "Model", "Type", "Docs", "Fail", "Exact", "±Tol", "Prec", "Recall"
+        )?;
+        for group in &self.groups {
+            writeln!(
+                f,
+                "{:<40} {:<16} {:>4} {:>4} {:>6.1}% {:>6.1}% {:>6.1}% {:>6.1}%",
+                group.model,
+                if group.document_type == "*" {
+                    "all"
+                } else {
+                    &group.document_type
+                },
+                group.documents,
+                group.failures,
+                group.total.exact_match_rate() * 100.0,
+                group.total.tolerant_match_rate() * 100.0,
+                group.total.precision() * 100.0,
+                group.total.recall() * 100.0,
+            )?;
+        }
+
+        for group in self.groups.iter().filter(|g| g.document_type == "*") {
+            writeln!(f, "\n{} by field:", group.model)?;
+            for (field, score) in &group.fields {
+                writeln!(
+                    f,
+                    "  {:<28} {:>2}/{:<2} exact, {:>2} close, {:>2} wrong, {:>2} missing, {:>2} unexpected",
+                    field,
+                    score.exact,
+                    score.expected,
+                    score.close,
+                    score.mismatched,
+                    score.missing,
+                    score.unexpected
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::financial_analyzer::AnalysisPrompt;
+    use crate::llm_provider::{RecordedProvider, Recording};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn case() -> EvalCase {
+        EvalCase {
+            name: "invoice".to_string(),
+            text: "INVOICE #INV-7\nDate: 01/15/2024\nTotal: $2,750.00".to_string(),
+            expected: serde_json::from_value(json!({
+                "document_type": "Invoice",
+                "extracted_data": {
+                    "invoice_number": "INV-7",
+                    "date": "2024-01-15",
+                    "total_amount": "2750.00",
+                    "vendor": "Tech Solutions Inc."
+                }
+            }))
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_score_distinguishes_exact_close_and_wrong() {
+        let mut document = crate::RuleBasedExtractor::new().extract(&case().text);
+        document.extracted_data = [
+            ("invoice_number", "inv-7"),
+            ("date", "January 15, 2024"),
+            ("total_amount", "$2,750.004"),
+            ("po_number", "PO-99"),
+            ("notes", " "),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let fields = Evaluator::new().score(&case().expected, Some(&document));
+
+        assert_eq!(fields["document_type"], FieldOutcome::Exact);
+        assert_eq!(fields["invoice_number"], FieldOutcome::Exact);
+        assert_eq!(fields["date"], FieldOutcome::Close);
+        assert_eq!(fields["total_amount"], FieldOutcome::Close);
+        assert_eq!(fields["vendor"], FieldOutcome::Missing);
+        // A field the label does not have was made up; a blank one was not.
+        assert_eq!(fields["po_number"], FieldOutcome::Unexpected);
+        assert!(!fields.contains_key("notes"));
+
+        let mut score = FieldScore::default();
+        for outcome in fields.values() {
+            score.record(*outcome);
+        }
+        assert_eq!(score.expected, 5);
+        assert_eq!(score.recall(), 0.8);
+        assert_eq!(score.precision(), 0.8);
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_compares_models_from_recordings() {
+        let provider = Arc::new(RecordedProvider::new(vec![
+            Recording {
+                model: "good".to_string(),
+                matches: "INV-7".to_string(),
+                response: json!({
+                    "document_type": "invoice",
+                    "confidence": 0.9,
+                    "extracted_data": {
+                        "invoice_number": "INV-7", "date": "2024-01-15",
+                        "total_amount": "2750", "vendor": "Tech Solutions Inc."
+                    },
+                    "validation_errors": [], "suggested_categories": [], "document_insights": []
+                }),
+                usage: None,
+            },
+            Recording {
+                model: "weak".to_string(),
+                matches: "INV-7".to_string(),
+                response: json!({
+                    "document_type": "receipt",
+                    "confidence": 0.4,
+                    "extracted_data": {"invoice_number": "INV-1", "total_amount": "2750"},
+                    "validation_errors": [], "suggested_categories": [], "document_insights": []
+                }),
+                usage: None,
+            },
+        ]));
+
+        let evaluator = Evaluator::new();
+        let mut results = Vec::new();
+        for model in ["good", "weak", "absent"] {
+            let analyzer = FinancialAnalyzer::with_provider(provider.clone())
+                .with_model(model)
+                .with_prompt(AnalysisPrompt::Smart);
+            results.extend(evaluator.evaluate(model, &analyzer, &[case()]).await);
+        }
+        let report = EvalReport::new(results);
+
+        let good = report.group("good", "Invoice").unwrap();
+        assert_eq!(good.total.recall(), 1.0);
+        assert_eq!(good.total.exact_match_rate(), 0.8);
+
+        let weak = report.group("weak", "*").unwrap();
+        assert_eq!(weak.total.correct(), 1);
+        assert_eq!(weak.total.precision(), 1.0 / 3.0);
+        assert_eq!(weak.total.recall(), 0.2);
+
+        let absent = report.group("absent", "*").unwrap();
+        assert_eq!(absent.failures, 1);
+        assert_eq!(absent.total.missing, 5);
+        assert!(report.to_string().contains("good"));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::dates::{parse_date, parse_period, DateRange};
+use crate::document_types::{DocumentType, FinancialDocument};
+use crate::money::{Currency, Money};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// `extracted_data` read into typed fields, chosen by the document type.
+/// Values that do not parse, and keys no struct knows, stay in `other` as
+/// strings, so no extracted data is lost.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind")]
+pub enum DocumentFields {
+    Invoice(InvoiceFields),
+    Receipt(ReceiptFields),
+    BankStatement(BankStatementFields),
+    W2(W2Fields),
+    Payroll(PayrollFields),
+    Other(BTreeMap<String, String>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct InvoiceFields {
+    pub invoice_number: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub vendor: Option<String>,
+    pub client: Option<String>,
+    pub subtotal: Option<Money>,
+    pub tax_amount: Option<Money>,
+    /// VAT rate in percent, when one rate applies to the whole invoice.
+    pub tax_rate: Option<Decimal>,
+    /// UNCL5305 VAT category code, when one applies to the whole invoice.
+    pub tax_category: Option<String>,
+    pub total_amount: Option<Money>,
+    pub payment_terms: Option<String>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReceiptFields {
+    pub receipt_number: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub store: Option<String>,
+    pub subtotal: Option<Money>,
+    pub tax_amount: Option<Money>,
+    pub total_amount: Option<Money>,
+    pub payment_method: Option<String>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BankStatementFields {
+    pub bank_name: Option<String>,
+    pub account_number: Option<String>,
+    pub account_holder: Option<String>,
+    pub period: Option<DateRange>,
+    pub beginning_balance: Option<Money>,
+    pub ending_balance: Option<Money>,
+    pub total_deposits: Option<Money>,
+    pub total_withdrawals: Option<Money>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct W2Fields {
+    pub year: Option<i32>,
+    pub employee: Option<String>,
+    pub employer: Option<String>,
+    pub employer_ein: Option<String>,
+    pub wages: Option<Money>,
+    pub federal_tax_withheld: Option<Money>,
+    pub social_security_wages: Option<Money>,
+    pub medicare_wages: Option<Money>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PayrollFields {
+    pub employee: Option<String>,
+    pub employer: Option<String>,
+    pub pay_date: Option<NaiveDate>,
+    pub pay_period: Option<DateRange>,
+    pub gross_pay: Option<Money>,
+    pub deductions: Option<Money>,
+    pub net_pay: Option<Money>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, String>,
+}
+
+impl DocumentFields {
+    /// Parses `data` into the field struct for `document_type`. Amounts
+    /// without a currency take `currency`.
+    pub fn from_data(
+        document_type: &DocumentType,
+        data: &HashMap<String, String>,
+        currency: Option<Currency>,
+    ) -> Self {
+        let mut reader = FieldReader {
+            remaining: data.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            currency,
+        };
+
+        match document_type {
+            DocumentType::Invoice => DocumentFields::Invoice(InvoiceFields {
+                invoice_number: reader.text(&["invoice_number", "invoice_no"]),
+                date: reader.date(&["date", "invoice_date"]),
+                due_date: reader.date(&["due_date"]),
+                vendor: reader.text(&["vendor", "seller", "from"]),
+                client: reader.text(&["client", "customer", "bill_to", "to"]),
+                subtotal: reader.money(&["subtotal"]),
+                tax_amount: reader.money(&["tax_amount", "tax", "vat", "sales_tax"]),
+                tax_rate: reader.percent(&["tax_rate", "vat_rate"]),
+                tax_category: reader.text(&["tax_category", "vat_category"]),
+                total_amount: reader.money(&["total_amount", "total", "total_due", "amount_due"]),
+                payment_terms: reader.text(&["payment_terms", "terms"]),
+                other: reader.finish(),
+            }),
+            DocumentType::Receipt => DocumentFields::Receipt(ReceiptFields {
+                receipt_number: reader.text(&["receipt_number", "receipt_no"]),
+                date: reader.date(&["date"]),
+                store: reader.text(&["store", "merchant", "vendor"]),
+                subtotal: reader.money(&["subtotal"]),
+                tax_amount: reader.money(&["tax_amount", "tax", "vat", "sales_tax"]),
+                total_amount: reader.money(&["total_amount", "total"]),
+                payment_method: reader.text(&["payment_method"]),
+                other: reader.finish(),
+            }),
+            DocumentType::BankStatement => DocumentFields::BankStatement(BankStatementFields {
+                bank_name: reader.text(&["bank_name", "bank"]),
+                account_number: reader.text(&["account_number", "account"]),
+                account_holder: reader.text(&["account_holder", "customer"]),
+                period: reader.period(&["period", "statement_period"]),
+                beginning_balance: reader.money(&["beginning_balance", "opening_balance"]),
+                ending_balance: reader.money(&["ending_balance", "closing_balance"]),
+                total_deposits: reader.money(&["total_deposits", "deposits"]),
+                total_withdrawals: reader.money(&["total_withdrawals", "withdrawals"]),
+                other: reader.finish(),
+            }),
+            DocumentType::TaxForm(form) if form.is_empty() || form == "W-2" => {
+                DocumentFields::W2(W2Fields {
+                    year: reader.year(&["year", "tax_year"]),
+                    employee: reader.text(&["employee", "employee_name"]),
+                    employer: reader.text(&["employer", "employer_name"]),
+                    employer_ein: reader.text(&["employer_ein", "ein"]),
+                    wages: reader.money(&["wages", "wages_tips_other_compensation"]),
+                    federal_tax_withheld: reader
+                        .money(&["federal_tax_withheld", "federal_income_tax_withheld"]),
+                    social_security_wages: reader.money(&["social_security_wages"]),
+                    medicare_wages: reader.money(&["medicare_wages"]),
+                    other: reader.finish(),
+                })
+            }
+            DocumentType::Payroll => DocumentFields::Payroll(PayrollFields {
+                employee: reader.text(&["employee", "employee_name"]),
+                employer: reader.text(&["employer", "employer_name"]),
+                pay_date: reader.date(&["pay_date", "date"]),
+                pay_period: reader.period(&["pay_period", "period"]),
+                gross_pay: reader.money(&["gross_pay", "gross"]),
+                deductions: reader.money(&["deductions", "total_deductions"]),
+                net_pay: reader.money(&["net_pay", "net"]),
+                other: reader.finish(),
+            }),
+            _ => DocumentFields::Other(reader.finish()),
+        }
+    }
+
+    /// Keys that were not mapped onto a typed field.
+    pub fn other(&self) -> &BTreeMap<String, String> {
+        match self {
+            DocumentFields::Invoice(fields) => &fields.other,
+            DocumentFields::Receipt(fields) => &fields.other,
+            DocumentFields::BankStatement(fields) => &fields.other,
+            DocumentFields::W2(fields) => &fields.other,
+            DocumentFields::Payroll(fields) => &fields.other,
+            DocumentFields::Other(other) => other,
+        }
+    }
+}
+
+impl Default for DocumentFields {
+    fn default() -> Self {
+        DocumentFields::Other(BTreeMap::new())
+    }
+}
+
+impl FinancialDocument {
+    /// Re-reads `fields` from `extracted_data`. `normalize` does this; call
+    /// it after changing the data, the document type or the currency by
+    /// hand.
+    pub fn refresh_fields(&mut self) {
+        self.fields = DocumentFields::from_data(
+            &self.document_type,
+            &self.extracted_data,
+            self.metadata.currency(),
+        );
+    }
+}
+
+// Takes the first alias whose value parses; anything left over becomes the
+// fallback map.
+struct FieldReader {
+    remaining: BTreeMap<String, String>,
+    currency: Option<Currency>,
+}
+
+impl FieldReader {
+    fn take<T>(&mut self, keys: &[&str], parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+        let (key, value) = keys.iter().find_map(|key| {
+            let value = parse(self.remaining.get(*key)?)?;
+            Some((*key, value))
+        })?;
+        self.remaining.remove(key);
+        Some(value)
+    }
+
+    fn text(&mut self, keys: &[&str]) -> Option<String> {
+        self.take(keys, |value| {
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        })
+    }
+
+    fn money(&mut self, keys: &[&str]) -> Option<Money> {
+        let currency = self.currency.clone();
+        self.take(keys, |value| {
+            let money = Money::parse(value).ok()?;
+            Some(match &currency {
+                Some(currency) => money.with_default_currency(currency),
+                None => money,
+            })
+        })
+    }
+
+    fn date(&mut self, keys: &[&str]) -> Option<NaiveDate> {
+        self.take(keys, |value| parse_date(value).map(|parsed| parsed.date))
+    }
+
+    /// "19%", "19 %" or "19".
+    fn percent(&mut self, keys: &[&str]) -> Option<Decimal> {
+        self.take(keys, |value| {
+            Decimal::from_str(value.trim().trim_end_matches('%').trim()).ok()
+        })
+    }
+
+    fn period(&mut self, keys: &[&str]) -> Option<DateRange> {
+        self.take(keys, parse_period)
+    }
+
+    fn year(&mut self, keys: &[&str]) -> Option<i32> {
+        self.take(keys, |value| {
+            value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|year| (1900..=2100).contains(year))
+        })
+    }
+
+    fn finish(self) -> BTreeMap<String, String> {
+        self.remaining
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_invoice_fields_are_typed_and_lossless() {
+        let data = data(&[
+            ("invoice_number", "INV-2024-001"),
+            ("due_date", "2024-02-14"),
+            ("tax", "250.00"),
+            ("total", "$2,750.00"),
+            ("date", "sometime in January"),
+            ("po_number", "PO-7"),
+        ]);
+
+        let DocumentFields::Invoice(invoice) =
+            DocumentFields::from_data(&DocumentType::Invoice, &data, Currency::new("USD"))
+        else {
+            panic!("expected invoice fields");
+        };
+
+        assert_eq!(invoice.invoice_number.as_deref(), Some("INV-2024-001"));
+        assert_eq!(invoice.due_date, NaiveDate::from_ymd_opt(2024, 2, 14));
+        assert_eq!(invoice.tax_amount, Some(Money::parse("USD 250").unwrap()));
+        assert_eq!(
+            invoice.total_amount,
+            Some(Money::parse("USD 2750").unwrap())
+        );
+        // Unparseable and unknown values are kept verbatim.
+        assert_eq!(invoice.date, None);
+        assert_eq!(
+            invoice.other,
+            BTreeMap::from([
+                ("date".to_string(), "sometime in January".to_string()),
+                ("po_number".to_string(), "PO-7".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_fields_follow_document_type() {
+        let data = data(&[("year", "2023"), ("wages", "$85,000.00")]);
+
+        let w2 = DocumentFields::from_data(&DocumentType::TaxForm("W-2".to_string()), &data, None);
+        let DocumentFields::W2(w2) = w2 else {
+            panic!("expected W-2 fields");
+        };
+        assert_eq!(w2.year, Some(2023));
+        assert!(w2.other.is_empty());
+
+        let other = DocumentFields::from_data(&DocumentType::Contract, &data, None);
+        assert_eq!(other.other().len(), 2);
+    }
+
+    #[test]
+    fn test_document_carries_its_fields() {
+        let legacy = crate::LegacyFinancialDocument {
+            document_type: "Invoice".to_string(),
+            confidence: 0.9,
+            extracted_data: data(&[("due_date", "Feb 14th, 2024"), ("total", "$99.00")]),
+            validation_errors: vec![],
+            suggested_categories: vec![],
+            document_insights: vec![],
+        };
+
+        // `normalize` reads the fields after rewriting the dates.
+        let mut document = FinancialDocument::from(legacy);
+        let DocumentFields::Invoice(invoice) = &document.fields else {
+            panic!("expected invoice fields");
+        };
+        assert_eq!(invoice.due_date, NaiveDate::from_ymd_opt(2024, 2, 14));
+
+        document
+            .extracted_data
+            .insert("due_date".to_string(), "2024-03-01".to_string());
+        document.refresh_fields();
+        let DocumentFields::Invoice(invoice) = &document.fields else {
+            panic!("expected invoice fields");
+        };
+        assert_eq!(invoice.due_date, NaiveDate::from_ymd_opt(2024, 3, 1));
+    }
+}
@@ -1,244 +1,918 @@
-use crate::document_types::{FinancialDocument, ValidationResult};
-use anyhow::Result;
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize)]
-struct LLMRequest {
-    model: String,
-    messages: Vec<Message>,
-    temperature: f32,
-    max_tokens: u32,
-    response_format: Option<ResponseFormat>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Message {
-    role: String,
-    content: String,
-}
-
-#[derive(Debug, Serialize)]
-struct ResponseFormat {
-    r#type: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct LLMResponse {
-    choices: Vec<Choice>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Choice {
-    message: Message,
+use crate::batch::{run_batch, BatchOptions, BatchProgress};
+use crate::cache::ResponseCache;
+use crate::cost::{CostLedger, PriceTable, UsageReport};
+use crate::document_types::{
+    FinancialDocument, LegacyFinancialDocument, LineItem, Party, Transaction, ValidationResult,
+};
+use crate::error::{AnalyzerError, Result};
+use crate::fields::DocumentFields;
+use crate::ingest::PagedText;
+use crate::llm_provider::{Completion, LLMRequest, LlmProvider, OpenAiProvider, ResponseFormat};
+use crate::prompts::{self, PromptLibrary, PromptTemplate};
+use crate::redaction::{Redactions, Redactor};
+use crate::rule_extractor::RuleBasedExtractor;
+use crate::schema;
+use crate::streaming::StreamProgress;
+use crate::validator::DocumentValidator;
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::sync::Arc;
+
+// Structured outputs (`json_schema`) need gpt-4o-mini or newer.
+const DEFAULT_MODEL: &str = "gpt-4o-mini"; // or "gpt-4o" for better accuracy
+
+/// Which analysis prompt to send. `Smart` is the shorter, type-adaptive prompt
+/// that works better with small free models; its legacy-shaped answers are
+/// converted into the canonical `FinancialDocument`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnalysisPrompt {
+    Standard,
+    Smart,
 }
 
 pub struct FinancialAnalyzer {
-    client: Client,
-    api_key: String,
+    provider: Arc<dyn LlmProvider>,
+    models: Vec<String>,
+    prompt: AnalysisPrompt,
+    prompts: PromptLibrary,
+    rule_fallback: Option<RuleBasedExtractor>,
+    validator: DocumentValidator,
+    llm_validation: bool,
+    redactor: Option<Redactor>,
+    cache: Option<ResponseCache>,
+    ledger: CostLedger,
+    on_stream_progress: Option<Arc<StreamProgressFn>>,
 }
 
+type StreamProgressFn = dyn Fn(StreamProgress) + Send + Sync;
+
 impl FinancialAnalyzer {
     pub fn new(api_key: String) -> Self {
+        Self::with_provider(Arc::new(OpenAiProvider::new(api_key)))
+    }
+
+    pub fn with_provider(provider: Arc<dyn LlmProvider>) -> Self {
         Self {
-            client: Client::new(),
-            api_key,
+            provider,
+            models: vec![DEFAULT_MODEL.to_string()],
+            prompt: AnalysisPrompt::Standard,
+            prompts: PromptLibrary::builtin(),
+            rule_fallback: None,
+            validator: DocumentValidator::new(),
+            llm_validation: false,
+            redactor: Some(Redactor::new()),
+            cache: None,
+            ledger: CostLedger::default(),
+            on_stream_progress: None,
         }
     }
 
+    pub fn with_model(self, model: impl Into<String>) -> Self {
+        self.with_models([model])
+    }
+
+    /// Models are tried in order until one returns a parseable analysis.
+    pub fn with_models<I, S>(mut self, models: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.models = models.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_prompt(mut self, prompt: AnalysisPrompt) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Renders prompts from `prompts` instead of the built-in templates.
+    pub fn with_prompts(mut self, prompts: PromptLibrary) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    /// Falls back to the offline `RuleBasedExtractor` when every model fails.
+    pub fn with_rule_fallback(mut self) -> Self {
+        self.rule_fallback = Some(RuleBasedExtractor::new());
+        self
+    }
+
+    pub fn with_validator(mut self, validator: DocumentValidator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Also asks the model to review each document during validation and
+    /// merges its findings into the local result.
+    pub fn with_llm_validation(mut self) -> Self {
+        self.llm_validation = true;
+        self
+    }
+
+    /// Sends documents to the model verbatim. By default SSNs, EINs, IBANs,
+    /// card and account numbers and email addresses are replaced with
+    /// placeholders before sending and restored in the answer.
+    pub fn without_redaction(mut self) -> Self {
+        self.redactor = None;
+        self
+    }
+
+    /// Reuses completions stored in `cache` instead of calling the model
+    /// again for a prompt it has already answered.
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Prices used to turn token counts into cost.
+    pub fn with_price_table(mut self, prices: PriceTable) -> Self {
+        self.ledger = std::mem::take(&mut self.ledger).with_prices(prices);
+        self
+    }
+
+    /// Stops sending requests once `budget` USD has been spent; further
+    /// calls fail with `AnalyzerError::BudgetExceeded`. Models missing from
+    /// the price table are refused with `AnalyzerError::UnpricedModel`, and
+    /// once a response reports no token usage, further calls fail with
+    /// `AnalyzerError::UnmeteredRequests`.
+    pub fn with_budget(mut self, budget: Decimal) -> Self {
+        self.ledger = std::mem::take(&mut self.ledger).with_budget(budget);
+        self
+    }
+
+    /// Streams completions and reports their progress to `on_progress`.
+    /// When a stream is cut off, the fields received before the
+    /// interruption are kept, sections that never arrived are left empty and
+    /// the document is flagged as incomplete.
+    pub fn with_streaming(
+        mut self,
+        on_progress: impl Fn(StreamProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_stream_progress = Some(Arc::new(on_progress));
+        self
+    }
+
+    /// Tokens and cost of every request this analyzer has sent.
+    pub fn usage(&self) -> UsageReport {
+        self.ledger.total()
+    }
+
+    pub fn provider_name(&self) -> &str {
+        self.provider.name()
+    }
+
     pub async fn analyze_document(&self, text: &str) -> Result<FinancialDocument> {
-        let prompt = self.build_analysis_prompt(text);
+        let template = self.prompts.get(match self.prompt {
+            AnalysisPrompt::Standard => prompts::ANALYSIS,
+            AnalysisPrompt::Smart => prompts::SMART_ANALYSIS,
+        })?;
+        let messages = template.render(&[("document", text)])?;
+
+        let mut last_error = AnalyzerError::NoModels;
+        let mut usage = UsageReport::default();
+
+        for model in &self.models {
+            log::info!("Trying model: {}", model);
+
+            let request = LLMRequest {
+                model: model.clone(),
+                messages: messages.clone(),
+                temperature: 0.1,
+                max_tokens: 2000,
+                response_format: self.analysis_response_format(),
+            };
+
+            let result = match self.call_llm(request, template, &mut usage).await {
+                Ok(completion) if completion.truncated => {
+                    parse_truncated_analysis(&completion.content).map(|mut document| {
+                        document
+                            .validation_errors
+                            .push(TRUNCATED_OUTPUT.to_string());
+                        document
+                    })
+                }
+                Ok(completion) => parse_analysis(&completion.content),
+                // Another model would cost money too, and falling back
+                // would hide that processing stopped.
+                Err(e @ AnalyzerError::BudgetExceeded { .. }) => return Err(e),
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(mut analysis) => {
+                    analysis.prompt_version = Some(template.version_id());
+                    analysis.usage = Some(usage);
+                    // Post-process the analysis for better insights
+                    enhance_analysis(&mut analysis);
+                    log::info!("Successfully analyzed with {}", model);
+                    return Ok(analysis);
+                }
+                Err(e) => {
+                    log::warn!("Model {} failed: {}", model, e);
+                    last_error = e;
+                }
+            }
+        }
 
-        let request = LLMRequest {
-            model: "gpt-3.5-turbo".to_string(), // or "gpt-4" for better accuracy
-            messages: vec![
-                Message {
-                    role: "system".to_string(),
-                    content: r#"You are a financial document analysis expert.
-                    Analyze financial documents and extract structured data.
-                    Always respond with valid JSON in the specified format.
-                    Be accurate and thorough in your analysis."#
-                        .to_string(),
-                },
-                Message {
-                    role: "user".to_string(),
-                    content: prompt,
-                },
-            ],
-            temperature: 0.1,
-            max_tokens: 2000,
-            response_format: Some(ResponseFormat {
-                r#type: "json_object".to_string(),
-            }),
-        };
+        match &self.rule_fallback {
+            Some(extractor) => {
+                log::warn!(
+                    "All models failed ({}), using rule-based extraction",
+                    last_error
+                );
+                let mut document = extractor.extract(text);
+                document.usage = (!usage.is_empty()).then_some(usage);
+                Ok(document)
+            }
+            None => Err(last_error),
+        }
+    }
 
-        let response = self.call_llm(request).await?;
-        let analysis: FinancialDocument = serde_json::from_str(&response)
-            .map_err(|e| anyhow::anyhow!("Failed to parse LLM response: {}", e))?;
+    /// Analyzes a document read page by page, such as a PDF, and records the
+    /// page each value was found on.
+    pub async fn analyze_pages(&self, text: &PagedText) -> Result<FinancialDocument> {
+        let mut document = self.analyze_document(&text.text()).await?;
+        text.annotate(&mut document);
+        Ok(document)
+    }
 
-        Ok(analysis)
+    /// Analyzes many documents with bounded concurrency. Results keep the
+    /// input order and a failed document does not abort the rest of the batch.
+    pub async fn analyze_batch<S: AsRef<str>>(
+        &self,
+        documents: &[S],
+        options: &BatchOptions,
+        on_progress: impl Fn(BatchProgress),
+    ) -> Vec<Result<FinancialDocument>> {
+        run_batch(
+            documents.iter().collect(),
+            options,
+            |_, text| self.analyze_document(text.as_ref()),
+            on_progress,
+        )
+        .await
     }
 
+    /// Checks the document locally and, when enabled, merges in the model's
+    /// second opinion.
     pub async fn validate_document(
         &self,
         document: &FinancialDocument,
     ) -> Result<ValidationResult> {
-        let prompt = self.build_validation_prompt(document);
+        let local = self.validator.validate(document);
+        if !self.llm_validation {
+            return Ok(local);
+        }
+        let second_opinion = self.llm_validate_document(document).await?;
+        Ok(local.merge(second_opinion))
+    }
+
+    async fn llm_validate_document(
+        &self,
+        document: &FinancialDocument,
+    ) -> Result<ValidationResult> {
+        let template = self.prompts.get(prompts::VALIDATION)?;
+        let analysis = serde_json::to_string_pretty(document).unwrap();
 
         let request = LLMRequest {
-            model: "gpt-3.5-turbo".to_string(),
-            messages: vec![
-                Message {
-                    role: "system".to_string(),
-                    content: r#"You are a financial document validation expert.
-                    Validate financial documents for completeness, accuracy, and compliance.
-                    Return JSON with validation results."#
-                        .to_string(),
-                },
-                Message {
-                    role: "user".to_string(),
-                    content: prompt,
-                },
-            ],
+            model: self.primary_model(),
+            messages: template.render(&[("analysis", &analysis)])?,
             temperature: 0.1,
             max_tokens: 1000,
-            response_format: Some(ResponseFormat {
-                r#type: "json_object".to_string(),
-            }),
+            response_format: self.response_format(),
         };
 
-        let response = self.call_llm(request).await?;
-        let validation: ValidationResult = serde_json::from_str(&response)
-            .map_err(|e| anyhow::anyhow!("Failed to parse validation response: {}", e))?;
-
-        Ok(validation)
+        let response = self
+            .call_llm(request, template, &mut UsageReport::default())
+            .await?
+            .content;
+        parse_json(&response)
     }
 
     pub async fn convert_to_json(&self, text: &str) -> Result<serde_json::Value> {
-        let prompt = format!(
-            r#"Convert this financial document into structured JSON format.
-            Extract all relevant fields and maintain data relationships.
-
-            DOCUMENT:
-            {}
-
-            Return a clean JSON object with all extracted data."#,
-            text
-        );
+        let template = self.prompts.get(prompts::CONVERSION)?;
 
         let request = LLMRequest {
-            model: "gpt-3.5-turbo".to_string(),
-            messages: vec![
-                Message {
-                    role: "system".to_string(),
-                    content: "Convert financial documents to structured JSON format.".to_string(),
-                },
-                Message {
-                    role: "user".to_string(),
-                    content: prompt,
-                },
-            ],
+            model: self.primary_model(),
+            messages: template.render(&[("document", text)])?,
             temperature: 0.1,
             max_tokens: 2000,
-            response_format: Some(ResponseFormat {
-                r#type: "json_object".to_string(),
-            }),
+            response_format: self.response_format(),
         };
 
-        let response = self.call_llm(request).await?;
-        let json_data: serde_json::Value = serde_json::from_str(&response)
-            .map_err(|e| anyhow::anyhow!("Failed to parse JSON conversion: {}", e))?;
-
-        Ok(json_data)
-    }
-
-    fn build_analysis_prompt(&self, text: &str) -> String {
-        format!(
-            r#"
-            Analyze this financial document and extract structured information.
-
-            DOCUMENT TEXT:
-            {}
-
-            Return JSON in this exact format:
-            {{
-                "document_type": "Invoice|Receipt|BankStatement|TaxForm|Contract|Bill|PaymentConfirmation|Payroll|Unknown",
-                "confidence": 0.95,
-                "extracted_data": {{
-                    "date": "YYYY-MM-DD",
-                    "total_amount": "123.45",
-                    "vendor": "Company Name",
-                    "tax_amount": "12.34",
-                    "currency": "USD",
-                    "document_number": "INV-001",
-                    "payment_terms": "Net 30"
-                }},
-                "validation_errors": ["Missing invoice number", "Invalid date format"],
-                "suggested_categories": ["Office Supplies", "Tax Deductible"],
-                "tax_implications": ["VAT applicable", "Business expense"],
-                "risk_assessment": "Low|Medium|High|Critical",
-                "metadata": {{
-                    "document_date": "2024-01-15",
-                    "total_amount": 4860.0,
-                    "currency": "USD",
-                    "parties": [
-                        {{"role": "payer", "name": "ABC Corporation"}},
-                        {{"role": "payee", "name": "Tech Solutions Inc."}}
-                    ],
-                    "line_items": [
-                        {{"description": "Software License", "quantity": 2, "unit_price": 1500.0, "amount": 3000.0}},
-                        {{"description": "Technical Support", "quantity": 10, "unit_price": 100.0, "amount": 1000.0}}
-                    ]
-                }}
-            }}
-
-            Be thorough and accurate in your analysis.
-            "#,
-            text
-        )
+        let response = self
+            .call_llm(request, template, &mut UsageReport::default())
+            .await?
+            .content;
+        parse_json(&response)
     }
 
-    fn build_validation_prompt(&self, document: &FinancialDocument) -> String {
-        let doc_json = serde_json::to_string_pretty(document).unwrap();
-
-        format!(
-            r#"
-            Validate this financial document analysis for completeness and compliance.
-
-            DOCUMENT ANALYSIS:
-            {}
-
-            Check for:
-            1. Missing required fields based on document type
-            2. Data consistency issues
-            3. Compliance with financial regulations
-            4. Risk factors
-
-            Return JSON:
-            {{
-                "is_valid": true|false,
-                "missing_fields": ["field1", "field2"],
-                "data_quality_issues": ["issue1", "issue2"],
-                "compliance_issues": ["compliance1", "compliance2"],
-                "overall_score": 0.95
-            }}
-            "#,
-            doc_json
-        )
+    fn primary_model(&self) -> String {
+        self.models
+            .first()
+            .cloned()
+            .unwrap_or_else(|| DEFAULT_MODEL.to_string())
     }
 
-    async fn call_llm(&self, request: LLMRequest) -> Result<String> {
-        let response: LLMResponse = self
-            .client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?
-            .json()
-            .await?;
+    fn response_format(&self) -> Option<ResponseFormat> {
+        self.provider
+            .supports_response_format()
+            .then(ResponseFormat::json_object)
+    }
 
-        Ok(response.choices[0].message.content.clone())
+    // The smart prompt asks for the legacy shape, so only the standard prompt
+    // can be held to the `FinancialDocument` schema.
+    fn analysis_response_format(&self) -> Option<ResponseFormat> {
+        if self.prompt == AnalysisPrompt::Standard && self.provider.supports_json_schema() {
+            Some(ResponseFormat::json_schema(
+                "financial_document",
+                schema::financial_document_schema().clone(),
+            ))
+        } else {
+            self.response_format()
+        }
     }
-}
\ No newline at end of file
+
+    // Every prompt goes through here, so this is the one place personal data
+    // is swapped out. Placeholders are restored in the raw completion, which
+    // covers every field the answer is parsed into. The cache is keyed on the
+    // redacted prompt and the template version and stores the redacted
+    // completion, so no personal data is written to disk. Requests sent are
+    // added to `usage` and the analyzer's ledger; cache hits are free. A
+    // completion recovered from a cut-off stream is never cached.
+    async fn call_llm(
+        &self,
+        mut request: LLMRequest,
+        template: &PromptTemplate,
+        usage: &mut UsageReport,
+    ) -> Result<Completion> {
+        let mut redactions = Redactions::default();
+        if let Some(redactor) = &self.redactor {
+            for message in &mut request.messages {
+                message.content = redactor.redact(&message.content, &mut redactions);
+            }
+            if !redactions.is_empty() {
+                log::info!("Redacted {} value(s) before sending", redactions.len());
+            }
+        }
+
+        let key = self
+            .cache
+            .as_ref()
+            .map(|_| ResponseCache::key(&request, &template.version_id()));
+        let cached = self
+            .cache
+            .as_ref()
+            .zip(key.as_deref())
+            .and_then(|(cache, key)| cache.get(key));
+
+        let mut completion = match cached {
+            Some(response) => Completion::new(response),
+            None => {
+                self.ledger.check_budget(&request.model)?;
+                let completion = match &self.on_stream_progress {
+                    Some(on_progress) => {
+                        self.provider
+                            .complete_streaming(&request, on_progress.as_ref())
+                            .await?
+                    }
+                    None => self.provider.complete(&request).await?,
+                };
+                usage.add(&self.ledger.record(&request.model, completion.usage));
+                if let (Some(cache), Some(key), false) = (&self.cache, &key, completion.truncated) {
+                    cache.put(key, &request.model, &completion.content);
+                }
+                completion
+            }
+        };
+        completion.content = redactions.restore(&completion.content);
+        Ok(completion)
+    }
+}
+
+const TRUNCATED_OUTPUT: &str =
+    "Model output was cut off; only the fields received before the interruption were kept";
+
+/// Strips the reasoning block and markdown fences some models wrap around
+/// their JSON.
+fn clean_json_response(response: &str) -> &str {
+    response
+        .rsplit_once("</think>")
+        .map_or(response, |(_, answer)| answer)
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim()
+}
+
+fn extract_json_from_text(text: &str) -> Option<&str> {
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    (end > start).then(|| &text[start..=end])
+}
+
+/// Parses a JSON answer, looking past any prose around it for backends that
+/// ignore `response_format`.
+fn parse_json<T: DeserializeOwned>(response: &str) -> Result<T> {
+    let clean_json = clean_json_response(response);
+    serde_json::from_str(clean_json).or_else(|e| {
+        extract_json_from_text(clean_json)
+            .and_then(|json| serde_json::from_str(json).ok())
+            .ok_or_else(|| AnalyzerError::from_parse(response, e))
+    })
+}
+
+/// Accepts either the canonical `FinancialDocument` shape or the legacy
+/// `document_insights` shape, optionally surrounded by prose. Canonical
+/// answers are checked against the generated JSON Schema first, so a bad
+/// field is reported by path. A schema mismatch is reported in preference to
+/// a syntax error, since it means the model did produce JSON.
+fn parse_analysis(response: &str) -> Result<FinancialDocument> {
+    let clean_json = clean_json_response(response);
+    let candidates = [Some(clean_json), extract_json_from_text(clean_json)];
+
+    let mut errors = Vec::new();
+    for candidate in candidates.into_iter().flatten() {
+        let value: serde_json::Value = match serde_json::from_str(candidate) {
+            Ok(value) => value,
+            Err(e) => {
+                errors.push(AnalyzerError::from_parse(response, e));
+                continue;
+            }
+        };
+
+        let violations = match schema::validate_financial_document(&value) {
+            Ok(()) => match serde_json::from_value::<FinancialDocument>(value) {
+                Ok(mut document) => {
+                    document.normalize();
+                    return Ok(document);
+                }
+                Err(e) => {
+                    errors.push(AnalyzerError::from_parse(response, e));
+                    continue;
+                }
+            },
+            Err(violations) => violations,
+        };
+        if let Ok(legacy) = serde_json::from_value::<LegacyFinancialDocument>(value) {
+            return Ok(legacy.into());
+        }
+        errors.push(AnalyzerError::SchemaMismatch {
+            raw: response.to_string(),
+            errors: violations,
+        });
+    }
+
+    let index = errors
+        .iter()
+        .position(|e| matches!(e, AnalyzerError::SchemaMismatch { .. }))
+        .unwrap_or(0);
+    Err(errors.swap_remove(index))
+}
+
+/// Reads an answer recovered from a cut-off stream. It is complete JSON but
+/// may stop before required sections, so it is not held to the schema:
+/// missing sections are filled with empty defaults and list entries that
+/// were cut off midway are dropped, keeping every field that did arrive.
+fn parse_truncated_analysis(response: &str) -> Result<FinancialDocument> {
+    if let Ok(document) = parse_analysis(response) {
+        return Ok(document);
+    }
+    let mut value: serde_json::Value = parse_json(response)?;
+    let Some(object) = value.as_object_mut() else {
+        return parse_analysis(response);
+    };
+    for (key, default) in [
+        ("document_type", json!("Unknown")),
+        ("confidence", json!(0.0)),
+        ("extracted_data", json!({})),
+        ("validation_errors", json!([])),
+        ("suggested_categories", json!([])),
+        ("tax_implications", json!([])),
+        // A document whose risk was never assessed is not low-risk.
+        ("risk_assessment", json!("Medium")),
+        ("metadata", json!({})),
+    ] {
+        object.entry(key).or_insert(default);
+    }
+    if let Some(metadata) = object["metadata"].as_object_mut() {
+        keep_readable::<Party>(metadata, "parties");
+        keep_readable::<LineItem>(metadata, "line_items");
+        keep_readable::<Transaction>(metadata, "transactions");
+    }
+
+    let mut document: FinancialDocument =
+        serde_json::from_value(value).map_err(|e| AnalyzerError::from_parse(response, e))?;
+    document.normalize();
+    Ok(document)
+}
+
+/// Replaces the list at `key` with the entries that read as a `T`, or an
+/// empty list when it is missing.
+fn keep_readable<T: DeserializeOwned>(
+    object: &mut serde_json::Map<String, serde_json::Value>,
+    key: &str,
+) {
+    let entries = match object.remove(key) {
+        Some(serde_json::Value::Array(entries)) => entries
+            .into_iter()
+            .filter(|entry| T::deserialize(entry).is_ok())
+            .collect(),
+        _ => Vec::new(),
+    };
+    object.insert(key.to_string(), serde_json::Value::Array(entries));
+}
+
+/// Adds type-aware insights and drops validation errors that do not apply to
+/// the detected document type.
+pub fn enhance_analysis(analysis: &mut FinancialDocument) {
+    let mut insights = Vec::new();
+
+    match analysis.fields.clone() {
+        DocumentFields::BankStatement(statement) => {
+            // Remove inappropriate validation errors for bank statements
+            analysis
+                .validation_errors
+                .retain(|error| !error.contains("vendor") && !error.contains("client"));
+
+            if let Some(balance) = statement.ending_balance {
+                insights.push(format!("Ending balance: {}", balance));
+            }
+            if let Some(period) = statement.period {
+                insights.push(format!(
+                    "Statement period: {} to {}",
+                    period.start, period.end
+                ));
+            }
+        }
+        DocumentFields::Invoice(invoice) => {
+            if let Some(due_date) = invoice.due_date {
+                insights.push(format!("Payment due: {}", due_date));
+            }
+            if let Some(tax) = invoice.tax_amount {
+                insights.push(format!("Tax amount: {}", tax));
+            }
+        }
+        DocumentFields::Receipt(receipt) => {
+            if let Some(store) = receipt.store {
+                insights.push(format!("Purchase from: {}", store));
+            }
+            if let Some(payment_method) = receipt.payment_method {
+                insights.push(format!("Paid with: {}", payment_method));
+            }
+        }
+        DocumentFields::W2(w2) => {
+            if let Some(year) = w2.year {
+                insights.push(format!("Tax year: {}", year));
+            }
+            if let Some(wages) = w2.wages {
+                insights.push(format!("Wages: {}", wages));
+            }
+        }
+        DocumentFields::Payroll(payroll) => {
+            if let Some(net_pay) = payroll.net_pay {
+                insights.push(format!("Net pay: {}", net_pay));
+            }
+        }
+        DocumentFields::Other(_) => {}
+    }
+
+    // Add confidence-based insight
+    if analysis.confidence > 0.9 {
+        insights.push("High confidence analysis".to_string());
+    } else if analysis.confidence > 0.7 {
+        insights.push("Moderate confidence analysis".to_string());
+    }
+
+    analysis.document_insights = insights;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_types::DocumentType;
+    use crate::llm_provider::{Completion, MockProvider, Usage};
+
+    const INVOICE_JSON: &str = r#"{
+        "document_type": "Invoice",
+        "confidence": 0.95,
+        "extracted_data": {"invoice_number": "INV-2024-001", "total_amount": "2750.00"},
+        "validation_errors": [],
+        "suggested_categories": ["Professional Services"],
+        "tax_implications": ["Business expense"],
+        "risk_assessment": "Low",
+        "metadata": {
+            "document_date": "2024-01-15",
+            "total_amount": 2750.0,
+            "currency": "USD",
+            "parties": [{"role": "payee", "name": "Tech Solutions Inc."}],
+            "line_items": []
+        }
+    }"#;
+
+    #[tokio::test]
+    async fn test_analyze_document_with_mock() {
+        let mock = Arc::new(MockProvider::with_responses([INVOICE_JSON]));
+        let analyzer = FinancialAnalyzer::with_provider(mock.clone()).with_model("test-model");
+
+        let document = analyzer
+            .analyze_document("INVOICE #INV-2024-001")
+            .await
+            .unwrap();
+
+        assert!(matches!(document.document_type, DocumentType::Invoice));
+        assert_eq!(document.extracted_data["invoice_number"], "INV-2024-001");
+        assert_eq!(document.prompt_version.as_deref(), Some("analysis@2"));
+        assert_eq!(
+            document.metadata.total_amount,
+            Some(crate::money::Money::parse("USD 2750").unwrap())
+        );
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].model, "test-model");
+        assert!(requests[0].messages[1]
+            .content
+            .contains("INVOICE #INV-2024-001"));
+        let format = requests[0].response_format.as_ref().unwrap();
+        assert_eq!(format.r#type, "json_schema");
+        assert_eq!(
+            &format.json_schema.as_ref().unwrap().schema,
+            schema::financial_document_schema()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_and_convert_with_mock() {
+        let mock = Arc::new(MockProvider::with_responses([
+            INVOICE_JSON,
+            // Backends that ignore `response_format` wrap the JSON in prose.
+            "<think>{ reasoning }</think>\nHere it is:\n```json\n{\"invoice_number\": \"INV-2024-001\"}\n```",
+        ]));
+        let analyzer = FinancialAnalyzer::with_provider(mock.clone());
+
+        let document = analyzer.analyze_document("invoice").await.unwrap();
+        let validation = analyzer.validate_document(&document).await.unwrap();
+        let json = analyzer.convert_to_json("invoice").await.unwrap();
+
+        assert!(validation.is_valid);
+        assert_eq!(validation.overall_score, 1.0);
+        assert_eq!(json["invoice_number"], "INV-2024-001");
+        // Validation is local unless a second opinion is requested.
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pii_is_redacted_before_sending_and_restored() {
+        let mock = Arc::new(MockProvider::with_responses([r#"{
+            "document_type": "W-2", "confidence": 0.9,
+            "extracted_data": {"employer_ein": "[EIN_1]", "employee_ssn": "[SSN_1]"},
+            "validation_errors": [], "suggested_categories": [],
+            "document_insights": []
+        }"#]));
+        let analyzer = FinancialAnalyzer::with_provider(mock.clone());
+
+        let document = analyzer
+            .analyze_document("W-2\nEmployee SSN: 123-45-6789\nEmployer EIN: 12-3456789")
+            .await
+            .unwrap();
+
+        let sent = &mock.requests()[0].messages[1].content;
+        assert!(!sent.contains("123-45-6789") && !sent.contains("12-3456789"));
+        assert!(sent.contains("[SSN_1]") && sent.contains("[EIN_1]"));
+        assert_eq!(document.extracted_data["employer_ein"], "12-3456789");
+        assert_eq!(document.extracted_data["employee_ssn"], "123-45-6789");
+    }
+
+    #[tokio::test]
+    async fn test_redaction_round_trip_through_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        // The model repeats one placeholder, invents another and drops the
+        // email address entirely.
+        let mock = Arc::new(MockProvider::with_responses([r#"{
+            "document_type": "W-2", "confidence": 0.9,
+            "extracted_data": {
+                "employee_ssn": "[SSN_1]", "ssn_copy": "SSN [SSN_1] on file",
+                "spouse_ssn": "[SSN_2]"
+            },
+            "validation_errors": [], "suggested_categories": [],
+            "document_insights": []
+        }"#]));
+        let analyzer = FinancialAnalyzer::with_provider(mock.clone())
+            .with_cache(ResponseCache::new(dir.path()));
+
+        let first = analyzer
+            .analyze_document("W-2\nEmployee SSN: 123-45-6789\nEmail: ann@example.com")
+            .await
+            .unwrap();
+        assert_eq!(first.extracted_data["employee_ssn"], "123-45-6789");
+        assert_eq!(first.extracted_data["ssn_copy"], "SSN 123-45-6789 on file");
+        assert_eq!(first.extracted_data["spouse_ssn"], "[SSN_2]");
+
+        // The cache is keyed on the redacted prompt, so a document that
+        // differs only in its PII reuses the entry and gets its own values back.
+        let second = analyzer
+            .analyze_document("W-2\nEmployee SSN: 987-65-4321\nEmail: bob@example.com")
+            .await
+            .unwrap();
+        assert_eq!(mock.requests().len(), 1);
+        assert_eq!(second.extracted_data["employee_ssn"], "987-65-4321");
+        assert_eq!(second.extracted_data["ssn_copy"], "SSN 987-65-4321 on file");
+
+        let entries: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        for entry in entries {
+            let cached = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            assert!(cached.contains("[SSN_1]"));
+            assert!(!cached.contains("123-45-6789") && !cached.contains("987-65-4321"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_completion_is_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let mock = Arc::new(MockProvider::with_responses([INVOICE_JSON]));
+        let analyzer = FinancialAnalyzer::with_provider(mock.clone())
+            .with_cache(ResponseCache::new(dir.path()));
+
+        let first = analyzer.analyze_document("invoice").await.unwrap();
+        let second = analyzer.analyze_document("invoice").await.unwrap();
+
+        assert_eq!(mock.requests().len(), 1);
+        assert_eq!(first.extracted_data, second.extracted_data);
+        // A different document misses the cache.
+        assert!(analyzer.analyze_document("receipt").await.is_err());
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_recorded_and_budget_enforced() {
+        let usage = Usage {
+            prompt_tokens: 2000,
+            completion_tokens: 1000,
+        };
+        let mock = Arc::new(MockProvider::new());
+        for _ in 0..3 {
+            mock.push_completion(Completion::new(INVOICE_JSON).with_usage(usage));
+        }
+        let analyzer = FinancialAnalyzer::with_provider(mock.clone())
+            .with_model("gpt-4o-mini")
+            .with_budget(Decimal::new(1, 3))
+            .with_rule_fallback();
+
+        let document = analyzer.analyze_document("invoice 1").await.unwrap();
+        let first = document.usage.unwrap();
+        assert_eq!(first.prompt_tokens, 2000);
+        // 2000 * 0.15 / 1M + 1000 * 0.60 / 1M
+        assert_eq!(first.cost, Decimal::new(9, 4));
+
+        analyzer.analyze_document("invoice 2").await.unwrap();
+        let stopped = analyzer.analyze_document("invoice 3").await;
+
+        assert!(matches!(stopped, Err(AnalyzerError::BudgetExceeded { .. })));
+        assert_eq!(mock.requests().len(), 2);
+        assert_eq!(analyzer.usage().cost, Decimal::new(18, 4));
+    }
+
+    #[tokio::test]
+    async fn test_llm_validation_is_merged_as_second_opinion() {
+        let mock = Arc::new(MockProvider::with_responses([
+            INVOICE_JSON,
+            r#"{"is_valid": false, "missing_fields": ["due_date"], "data_quality_issues": [],
+                "compliance_issues": ["No tax id"], "overall_score": 0.6}"#,
+        ]));
+        let analyzer = FinancialAnalyzer::with_provider(mock).with_llm_validation();
+
+        let document = analyzer.analyze_document("invoice").await.unwrap();
+        let validation = analyzer.validate_document(&document).await.unwrap();
+
+        assert!(!validation.is_valid);
+        assert_eq!(validation.missing_fields, vec!["due_date"]);
+        assert_eq!(validation.compliance_issues, vec!["No tax id"]);
+        assert_eq!(validation.overall_score, 0.6);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_document_reports_typed_parse_errors() {
+        let analyzer = FinancialAnalyzer::with_provider(Arc::new(MockProvider::with_responses([
+            "not json",
+            r#"{"document_type": "Invoice", "confidence": "high"}"#,
+        ])));
+
+        let malformed = analyzer.analyze_document("invoice").await.unwrap_err();
+        assert!(matches!(malformed, AnalyzerError::MalformedJson { .. }));
+        assert_eq!(malformed.raw_output(), Some("not json"));
+
+        let mismatch = analyzer.analyze_document("invoice").await.unwrap_err();
+        let AnalyzerError::SchemaMismatch { errors, .. } = &mismatch else {
+            panic!("expected a schema mismatch, got {:?}", mismatch);
+        };
+        assert!(errors.iter().any(|e| e.starts_with("confidence: ")));
+        assert!(mismatch.raw_output().unwrap().contains("\"high\""));
+    }
+
+    #[tokio::test]
+    async fn test_model_chain_falls_back_and_accepts_legacy_shape() {
+        let mock = Arc::new(MockProvider::new());
+        mock.push_error("rate limited");
+        mock.push_response(
+            r#"```json
+            {"document_type": "Bank Statement", "confidence": 0.95,
+             "extracted_data": {"ending_balance": "$16,714.50", "vendor": ""},
+             "validation_errors": ["Missing vendor"], "suggested_categories": [],
+             "document_insights": []}
+            ```"#,
+        );
+        let analyzer = FinancialAnalyzer::with_provider(mock.clone())
+            .with_models(["primary", "backup"])
+            .with_prompt(AnalysisPrompt::Smart);
+
+        let document = analyzer.analyze_document("BANK STATEMENT").await.unwrap();
+
+        assert_eq!(document.document_type, DocumentType::BankStatement);
+        assert!(document.validation_errors.is_empty());
+        assert!(document
+            .document_insights
+            .contains(&"Ending balance: $16,714.50".to_string()));
+
+        let models: Vec<_> = mock.requests().into_iter().map(|r| r.model).collect();
+        assert_eq!(models, vec!["primary", "backup"]);
+    }
+
+    #[tokio::test]
+    async fn test_rule_fallback_when_all_models_fail() {
+        let mock = Arc::new(MockProvider::new());
+        mock.push_error("offline");
+        let analyzer = FinancialAnalyzer::with_provider(mock).with_rule_fallback();
+
+        let document = analyzer
+            .analyze_document("RECEIPT\nStore: Corner Shop\nTotal: $4.20")
+            .await
+            .unwrap();
+
+        assert_eq!(document.document_type, DocumentType::Receipt);
+        assert_eq!(document.extracted_data["store"], "Corner Shop");
+    }
+
+    #[tokio::test]
+    async fn test_cut_off_stream_yields_partial_document() {
+        let answer = r#"{"document_type": "Invoice", "confidence": 0.9,
+            "extracted_data": {"invoice_number": "INV-7", "total_amount": "$120.00"},
+            "validation_errors": [], "suggested_categories": ["Office Supplies"],
+            "tax_implications": [], "risk_assessment": "Low",
+            "metadata": {"document_date": "2024-02-01", "currency": "USD",
+                "parties": [{"role": "payee", "name": "Acme"}, {"role": "pay"#;
+        // The connection drops without a finish reason or `[DONE]`.
+        let events: String = answer
+            .as_bytes()
+            .chunks(40)
+            .map(|chunk| {
+                let content = String::from_utf8_lossy(chunk);
+                let event = json!({"choices": [{"delta": {"content": content}}]});
+                format!("data: {}\n\n", event)
+            })
+            .collect();
+        let mock = Arc::new(MockProvider::new());
+        mock.push_stream(events);
+        let progress = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = progress.clone();
+        let analyzer = FinancialAnalyzer::with_provider(mock)
+            .with_streaming(move |p| seen.lock().unwrap().push(p));
+
+        let document = analyzer.analyze_document("invoice").await.unwrap();
+
+        assert_eq!(document.document_type, DocumentType::Invoice);
+        assert_eq!(document.extracted_data["invoice_number"], "INV-7");
+        assert_eq!(document.metadata.parties.len(), 1);
+        assert!(document.metadata.line_items.is_empty());
+        assert_eq!(document.validation_errors, vec![TRUNCATED_OUTPUT]);
+        let progress = progress.lock().unwrap();
+        assert!(progress.len() > 2);
+        assert!(progress.last().unwrap().finished);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_batch_keeps_per_document_errors() {
+        let mock = Arc::new(MockProvider::with_responses([INVOICE_JSON]));
+        mock.push_error("timeout");
+        mock.push_response(INVOICE_JSON);
+        let analyzer = FinancialAnalyzer::with_provider(mock);
+
+        let progress = std::sync::Mutex::new(Vec::new());
+        let results = analyzer
+            .analyze_batch(
+                &["one", "two", "three"],
+                &BatchOptions { concurrency: 1 },
+                |p| progress.lock().unwrap().push(p.completed),
+            )
+            .await;
+
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert!(results[2].is_ok());
+        assert_eq!(progress.into_inner().unwrap(), vec![1, 2, 3]);
+    }
+}
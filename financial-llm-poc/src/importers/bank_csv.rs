@@ -0,0 +1,321 @@
+use super::{parse_amount, parse_dates, Statement, StatementFormat, StatementLine};
+use crate::error::{AnalyzerError, Result};
+use crate::money::Currency;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Lines searched for the header row, to skip the account details some
+/// banks put above it.
+const HEADER_SEARCH_LINES: usize = 10;
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+/// Which columns of a bank's CSV export hold what. Each field lists header
+/// names to look for, matched without regard to case or surrounding space;
+/// the defaults cover the usual English exports. A bank's layout can be
+/// loaded from TOML:
+///
+/// ```toml
+/// date = ["Buchungstag"]
+/// description = ["Verwendungszweck"]
+/// amount = ["Betrag"]
+/// day_first = true
+/// currency = "EUR"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CsvMapping {
+    pub date: Vec<String>,
+    pub description: Vec<String>,
+    /// A signed amount column; positive is money in.
+    pub amount: Vec<String>,
+    /// Money out and money in, for exports that split them.
+    pub debit: Vec<String>,
+    pub credit: Vec<String>,
+    /// Running balance after each line.
+    pub balance: Vec<String>,
+    /// For exports that show spending as positive amounts.
+    pub negate_amounts: bool,
+    /// Whether numeric dates are day-first; guessed from the data if unset.
+    pub day_first: Option<bool>,
+    /// Guessed from the first lines if unset.
+    pub delimiter: Option<char>,
+    /// ISO-4217 code of the account.
+    pub currency: Option<String>,
+    pub bank_name: Option<String>,
+    pub account_number: Option<String>,
+}
+
+impl Default for CsvMapping {
+    fn default() -> Self {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        Self {
+            date: names(&[
+                "date",
+                "transaction date",
+                "posting date",
+                "booking date",
+                "value date",
+            ]),
+            description: names(&[
+                "description",
+                "details",
+                "narrative",
+                "payee",
+                "memo",
+                "reference",
+                "transaction description",
+            ]),
+            amount: names(&["amount", "transaction amount"]),
+            debit: names(&[
+                "debit",
+                "withdrawal",
+                "withdrawals",
+                "money out",
+                "paid out",
+            ]),
+            credit: names(&["credit", "deposit", "deposits", "money in", "paid in"]),
+            balance: names(&["balance", "running balance"]),
+            negate_amounts: false,
+            day_first: None,
+            delimiter: None,
+            currency: None,
+            bank_name: None,
+            account_number: None,
+        }
+    }
+}
+
+impl CsvMapping {
+    /// Loads a mapping; fields left out keep their defaults.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let source = std::fs::read_to_string(path)?;
+        toml::from_str(&source).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Whether `text` has a header row with a date and an amount column.
+    pub fn recognizes(&self, text: &str) -> bool {
+        self.find_header(text).is_some()
+    }
+
+    fn find_header(&self, text: &str) -> Option<(u8, usize, Columns)> {
+        let delimiter = match self.delimiter {
+            Some(delimiter) => u8::try_from(delimiter).ok()?,
+            None => guess_delimiter(text),
+        };
+        let mut reader = reader(text, delimiter);
+        for (index, record) in reader.records().take(HEADER_SEARCH_LINES).enumerate() {
+            let Ok(record) = record else {
+                continue;
+            };
+            if let Some(columns) = self.columns(&record) {
+                return Some((delimiter, index, columns));
+            }
+        }
+        None
+    }
+
+    fn columns(&self, header: &csv::StringRecord) -> Option<Columns> {
+        let find = |names: &[String]| {
+            names.iter().find_map(|name| {
+                header
+                    .iter()
+                    .position(|cell| cell.trim().eq_ignore_ascii_case(name.trim()))
+            })
+        };
+        let columns = Columns {
+            date: find(&self.date)?,
+            description: find(&self.description),
+            amount: find(&self.amount),
+            debit: find(&self.debit),
+            credit: find(&self.credit),
+            balance: find(&self.balance),
+        };
+        (columns.amount.is_some() || columns.debit.is_some() || columns.credit.is_some())
+            .then_some(columns)
+    }
+}
+
+struct Columns {
+    date: usize,
+    description: Option<usize>,
+    amount: Option<usize>,
+    debit: Option<usize>,
+    credit: Option<usize>,
+    balance: Option<usize>,
+}
+
+fn reader(text: &str, delimiter: u8) -> csv::Reader<&[u8]> {
+    csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes())
+}
+
+/// The candidate that appears most often in the first lines.
+fn guess_delimiter(text: &str) -> u8 {
+    let head: Vec<&str> = text.lines().take(HEADER_SEARCH_LINES).collect();
+    DELIMITERS
+        .into_iter()
+        .max_by_key(|delimiter| {
+            head.iter()
+                .map(|line| line.bytes().filter(|b| b == delimiter).count())
+                .sum::<usize>()
+        })
+        .unwrap_or(b',')
+}
+
+pub(super) fn parse(text: &str, mapping: &CsvMapping) -> Result<Statement> {
+    let (delimiter, header_index, columns) = mapping.find_header(text).ok_or_else(|| {
+        AnalyzerError::Import("no header row with date and amount columns".into())
+    })?;
+
+    let mut rows = Vec::new();
+    for (index, record) in reader(text, delimiter).records().enumerate() {
+        let record = record.map_err(|e| AnalyzerError::Import(e.to_string()))?;
+        if index <= header_index {
+            continue;
+        }
+        let cell = |column: Option<usize>| column.and_then(|c| record.get(c)).unwrap_or("").trim();
+        let date = cell(Some(columns.date));
+        // Blank lines and footers such as "Total" or "Closing balance".
+        if !date.chars().any(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        rows.push((
+            index + 1,
+            date.to_string(),
+            cell(columns.description).to_string(),
+            [
+                cell(columns.amount),
+                cell(columns.debit),
+                cell(columns.credit),
+                cell(columns.balance),
+            ]
+            .map(str::to_string),
+        ));
+    }
+
+    let raw_dates: Vec<&str> = rows.iter().map(|(_, date, _, _)| date.as_str()).collect();
+    let dates = parse_dates(&raw_dates, mapping.day_first);
+
+    let mut statement = Statement::new(StatementFormat::Csv);
+    statement.bank_name = mapping.bank_name.clone();
+    statement.account_number = mapping.account_number.clone();
+    statement.currency = mapping.currency.as_deref().and_then(Currency::new);
+
+    for ((line, raw_date, description, [amount, debit, credit, balance]), date) in
+        rows.into_iter().zip(dates)
+    {
+        let date = date.ok_or_else(|| {
+            AnalyzerError::Import(format!("line {}: unreadable date '{}'", line, raw_date))
+        })?;
+        let mut read = |cell: &str| -> Result<Option<Decimal>> {
+            if cell.is_empty() {
+                return Ok(None);
+            }
+            let (amount, currency) = parse_amount(cell).ok_or_else(|| {
+                AnalyzerError::Import(format!("line {}: unreadable amount '{}'", line, cell))
+            })?;
+            if statement.currency.is_none() {
+                statement.currency = currency;
+            }
+            Ok(Some(amount))
+        };
+
+        let amount = match read(&amount)? {
+            Some(amount) if mapping.negate_amounts => -amount,
+            Some(amount) => amount,
+            None => {
+                let money_in = read(&credit)?.unwrap_or_default().abs();
+                let money_out = read(&debit)?.unwrap_or_default().abs();
+                money_in - money_out
+            }
+        };
+        let balance = read(&balance)?;
+        statement.lines.push(StatementLine {
+            date,
+            description,
+            amount,
+            balance,
+            counterparty: None,
+            reference: None,
+        });
+    }
+
+    // Many banks list the newest line first.
+    if statement.lines.first().map(|line| line.date) > statement.lines.last().map(|line| line.date)
+    {
+        statement.lines.reverse();
+    }
+    // The balance before the first line and after the last, when the export
+    // has a balance column; later running balances are then reconciled.
+    if let Some(first) = statement.lines.first() {
+        statement.opening_balance = first.balance.map(|balance| balance - first.amount);
+    }
+    statement.closing_balance = statement.lines.last().and_then(|line| line.balance);
+    Ok(statement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_csv_with_preamble_split_columns_and_newest_first() {
+        let text = "\
+Account;DE44 5001 0517 5407 3249 31
+Booking date;Details;Money out;Money in;Balance
+04.03.2024;Supermarket;12,50;;1.237,50
+01.03.2024;Salary;;1.000,00;1.250,00
+;Closing balance;;;1.237,50
+";
+        let mapping = CsvMapping {
+            currency: Some("EUR".to_string()),
+            ..CsvMapping::default()
+        };
+
+        let statement = parse(text, &mapping).unwrap();
+
+        assert_eq!(statement.lines.len(), 2);
+        assert_eq!(statement.lines[0].description, "Salary");
+        assert_eq!(statement.lines[1].amount, Decimal::new(-1250, 2));
+        assert_eq!(
+            statement.lines[1].date,
+            NaiveDate::from_ymd_opt(2024, 3, 4).unwrap()
+        );
+        assert_eq!(statement.opening_balance, Some(Decimal::new(25000, 2)));
+        assert_eq!(statement.closing_balance, Some(Decimal::new(123750, 2)));
+        assert_eq!(statement.currency, Currency::new("EUR"));
+    }
+
+    #[test]
+    fn test_custom_mapping_and_bad_rows() {
+        let mapping: CsvMapping = toml::from_str(
+            "date = [\"Buchungstag\"]\ndescription = [\"Verwendungszweck\"]\namount = [\"Betrag\"]\nnegate_amounts = true\n",
+        )
+        .unwrap();
+        let text = "Buchungstag,Verwendungszweck,Betrag\n2024-03-01,Miete,800.00\n";
+
+        assert!(!CsvMapping::default().recognizes(text));
+        let statement = parse(text, &mapping).unwrap();
+        assert_eq!(statement.lines[0].amount, Decimal::new(-80000, 2));
+
+        let error = parse(
+            "Buchungstag,Verwendungszweck,Betrag\n2024-03-01,Miete,abc\n",
+            &mapping,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "cannot import statement: line 2: unreadable amount 'abc'"
+        );
+    }
+}
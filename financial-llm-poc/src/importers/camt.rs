@@ -0,0 +1,379 @@
+use super::{describe, Counterparty, Statement, StatementFormat, StatementLine};
+use crate::dates::DateRange;
+use crate::error::{AnalyzerError, Result};
+use crate::money::Currency;
+use crate::xml::{child, children, text};
+use chrono::NaiveDate;
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+/// Balance types that open a statement: opening booked and, in place of it,
+/// previously closed booked.
+const OPENING_BALANCES: [&str; 2] = ["OPBD", "PRCD"];
+const CLOSING_BALANCE: &str = "CLBD";
+
+pub(super) fn looks_like(text: &str) -> bool {
+    let head: String = text.chars().take(2048).collect();
+    head.contains("BkToCstmrStmt") || head.contains(":xsd:camt.053")
+}
+
+/// Reads every `Stmt` of the file. They must be for the same account, and
+/// are then taken as consecutive statements.
+pub(super) fn parse(text: &str) -> Result<Statement> {
+    let document = Document::parse(text)
+        .map_err(|e| AnalyzerError::Import(format!("invalid camt.053 XML: {}", e)))?;
+    let statements: Vec<Node> = document
+        .descendants()
+        .filter(|node| node.has_tag_name("Stmt"))
+        .collect();
+    if statements.is_empty() {
+        return Err(AnalyzerError::Import(
+            "no statement (Stmt) in the camt.053 file".to_string(),
+        ));
+    }
+
+    let mut accounts: Vec<String> = statements
+        .iter()
+        .filter_map(|stmt| account_id(*stmt))
+        .collect();
+    accounts.sort();
+    accounts.dedup();
+    if accounts.len() > 1 {
+        return Err(AnalyzerError::Import(format!(
+            "the file holds statements for several accounts: {}",
+            accounts.join(", ")
+        )));
+    }
+
+    let mut statement = Statement::new(StatementFormat::Camt053);
+    for (index, stmt) in statements.into_iter().enumerate() {
+        read_statement(stmt, index == 0, &mut statement)?;
+    }
+    statement.lines.sort_by_key(|line| line.date);
+    Ok(statement)
+}
+
+fn read_statement(stmt: Node, first: bool, statement: &mut Statement) -> Result<()> {
+    let label = text(stmt, &["Id"]).unwrap_or_else(|| "without Id".to_string());
+    let account = child(stmt, &["Acct"]);
+    if first {
+        statement.account_number = account_id(stmt);
+        statement.currency = account
+            .and_then(|account| text(account, &["Ccy"]))
+            .and_then(|code| Currency::new(&code));
+        statement.account_holder = account.and_then(|account| text(account, &["Ownr", "Nm"]));
+        statement.bank_name = account.and_then(|account| {
+            let institution = child(account, &["Svcr", "FinInstnId"])?;
+            ["Nm", "BICFI", "BIC"]
+                .iter()
+                .find_map(|tag| text(institution, &[*tag]))
+        });
+    }
+
+    let from = text(stmt, &["FrToDt", "FrDtTm"]).and_then(|value| date(&value));
+    let to = text(stmt, &["FrToDt", "ToDtTm"]).and_then(|value| date(&value));
+    if let (Some(start), Some(end)) = (from, to) {
+        statement.period = Some(match statement.period {
+            Some(period) => DateRange {
+                start: period.start.min(start),
+                end: period.end.max(end),
+            },
+            None => DateRange { start, end },
+        });
+    }
+
+    let mut opening = None;
+    let mut closing = None;
+    for balance in children(stmt, "Bal") {
+        let code = text(balance, &["Tp", "CdOrPrtry", "Cd"]).unwrap_or_default();
+        let (amount, currency) = signed_amount(balance, "Amt")?;
+        if let (Some(currency), Some(account)) = (&currency, &statement.currency) {
+            if currency != account {
+                statement.issues.push(format!(
+                    "Balance {} of statement {} is in {}, not in the account currency {}",
+                    code, label, currency, account
+                ));
+            }
+        }
+        if OPENING_BALANCES.contains(&code.as_str()) {
+            opening = Some(amount);
+        } else if code == CLOSING_BALANCE {
+            closing = Some(amount);
+        }
+    }
+    if opening.is_none() {
+        statement.issues.push(format!(
+            "Statement {} has no opening booked balance (OPBD)",
+            label
+        ));
+    }
+    if closing.is_none() {
+        statement.issues.push(format!(
+            "Statement {} has no closing booked balance (CLBD)",
+            label
+        ));
+    }
+    statement.chain_balances(&label, first, opening, closing);
+
+    let mut count = 0;
+    let mut net = Decimal::ZERO;
+    for entry in children(stmt, "Ntry") {
+        // Pending and informational entries are not part of the balance.
+        let status = text(entry, &["Sts", "Cd"]).or_else(|| text(entry, &["Sts"]));
+        if status.as_deref().is_some_and(|status| status != "BOOK") {
+            continue;
+        }
+        let line = line(entry)?;
+        count += 1;
+        net += line.amount;
+        statement.lines.push(line);
+    }
+    check_summary(stmt, &label, count, net, statement)
+}
+
+/// Compares the entries with the totals the bank gives in `TxsSummry`.
+fn check_summary(
+    stmt: Node,
+    label: &str,
+    count: usize,
+    net: Decimal,
+    statement: &mut Statement,
+) -> Result<()> {
+    let Some(totals) = child(stmt, &["TxsSummry", "TtlNtries"]) else {
+        return Ok(());
+    };
+    if let Some(stated) = text(totals, &["NbOfNtries"]).and_then(|n| n.parse::<usize>().ok()) {
+        if stated != count {
+            statement.issues.push(format!(
+                "Statement {} has {} booked entries, but its summary counts {}",
+                label, count, stated
+            ));
+        }
+    }
+    // `TtlNetNtry/Amt` since version 3, `TtlNetNtryAmt` before.
+    let stated = match child(totals, &["TtlNetNtry"]) {
+        Some(total) => Some(signed_amount(total, "Amt")?.0),
+        None if child(totals, &["TtlNetNtryAmt"]).is_some() => {
+            Some(signed_amount(totals, "TtlNetNtryAmt")?.0)
+        }
+        None => None,
+    };
+    if let Some(stated) = stated.filter(|stated| *stated != net) {
+        statement.issues.push(format!(
+            "Entries of statement {} net {}, but its summary shows {}",
+            label,
+            statement.show(net),
+            statement.show(stated)
+        ));
+    }
+    Ok(())
+}
+
+fn line(entry: Node) -> Result<StatementLine> {
+    let (amount, _) = signed_amount(entry, "Amt")?;
+    let date = ["BookgDt", "ValDt"]
+        .iter()
+        .find_map(|tag| {
+            let when = child(entry, &[*tag])?;
+            date(&text(when, &["Dt"]).or_else(|| text(when, &["DtTm"]))?)
+        })
+        .ok_or_else(|| AnalyzerError::Import("entry without a booking date".to_string()))?;
+
+    // Details are only attributed to the entry when it is a single payment,
+    // not a batch booked as one amount.
+    let details: Vec<Node> = entry
+        .descendants()
+        .filter(|node| node.has_tag_name("TxDtls"))
+        .collect();
+    let details = match details.as_slice() {
+        [details] => Some(*details),
+        _ => None,
+    };
+
+    // The other side is the debtor of money in and the creditor of money out.
+    let (party, party_account) = if amount.is_sign_negative() {
+        ("Cdtr", "CdtrAcct")
+    } else {
+        ("Dbtr", "DbtrAcct")
+    };
+    let counterparty = details.and_then(|details| {
+        let parties = child(details, &["RltdPties"])?;
+        let name = child(parties, &[party])?
+            .descendants()
+            .find(|node| node.has_tag_name("Nm"))
+            .and_then(|node| node.text())
+            .map(|name| name.trim().to_string())?;
+        let account = child(parties, &[party_account]).and_then(account_number);
+        Some(Counterparty { name, account })
+    });
+
+    let remittance = details.and_then(|details| {
+        let lines: Vec<String> = child(details, &["RmtInf"])?
+            .children()
+            .filter(|node| node.has_tag_name("Ustrd"))
+            .filter_map(|node| node.text())
+            .map(|text| text.trim().to_string())
+            .collect();
+        (!lines.is_empty()).then(|| lines.join(" "))
+    });
+    let remittance = remittance
+        .or_else(|| details.and_then(|details| text(details, &["AddtlTxInf"])))
+        .or_else(|| text(entry, &["AddtlNtryInf"]));
+
+    let reference = details
+        .and_then(|details| text(details, &["Refs", "EndToEndId"]))
+        .filter(|reference| reference != "NOTPROVIDED")
+        .or_else(|| text(entry, &["AcctSvcrRef"]))
+        .or_else(|| text(entry, &["NtryRef"]));
+
+    Ok(StatementLine {
+        date,
+        description: describe(
+            counterparty.as_ref().map(|party| party.name.clone()),
+            remittance,
+        ),
+        amount,
+        balance: None,
+        counterparty,
+        reference,
+    })
+}
+
+/// The amount in the `tag` child of `node`, negative when the sibling
+/// `CdtDbtInd` is `DBIT`, and the currency it is given in.
+fn signed_amount(node: Node, tag: &str) -> Result<(Decimal, Option<Currency>)> {
+    let amount = child(node, &[tag]).ok_or_else(|| {
+        AnalyzerError::Import(format!("{} without an amount", node.tag_name().name()))
+    })?;
+    let value = amount.text().unwrap_or("").trim();
+    let value = Decimal::from_str(value)
+        .map_err(|_| AnalyzerError::Import(format!("unreadable amount '{}'", value)))?;
+    let currency = amount.attribute("Ccy").and_then(Currency::new);
+    Ok(match text(node, &["CdtDbtInd"]).as_deref() {
+        Some("DBIT") => (-value, currency),
+        _ => (value, currency),
+    })
+}
+
+fn account_id(stmt: Node) -> Option<String> {
+    account_number(child(stmt, &["Acct"])?)
+}
+
+fn account_number(account: Node) -> Option<String> {
+    text(account, &["Id", "IBAN"]).or_else(|| text(account, &["Id", "Othr", "Id"]))
+}
+
+/// ISO dates, with or without a time.
+fn date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.08">
+  <BkToCstmrStmt>
+    <GrpHdr><MsgId>MSG-1</MsgId><CreDtTm>2024-04-01T06:00:00</CreDtTm></GrpHdr>
+    <Stmt>
+      <Id>STMT-2024-03</Id>
+      <FrToDt><FrDtTm>2024-03-01T00:00:00</FrDtTm><ToDtTm>2024-03-31T23:59:59</ToDtTm></FrToDt>
+      <Acct>
+        <Id><IBAN>DE89370400440532013000</IBAN></Id>
+        <Ccy>EUR</Ccy>
+        <Ownr><Nm>Muster GmbH</Nm></Ownr>
+        <Svcr><FinInstnId><BICFI>COBADEFFXXX</BICFI></FinInstnId></Svcr>
+      </Acct>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>OPBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">1000.00</Amt><CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><Dt>2024-03-01</Dt></Dt>
+      </Bal>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>CLBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">1450.00</Amt><CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><Dt>2024-03-31</Dt></Dt>
+      </Bal>
+      <TxsSummry>
+        <TtlNtries><NbOfNtries>2</NbOfNtries><TtlNetNtry><Amt>450.00</Amt><CdtDbtInd>CRDT</CdtDbtInd></TtlNetNtry></TtlNtries>
+      </TxsSummry>
+      <Ntry>
+        <Amt Ccy="EUR">250.00</Amt><CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+        <BookgDt><Dt>2024-03-12</Dt></BookgDt>
+        <AcctSvcrRef>BANKREF-2</AcctSvcrRef>
+        <NtryDtls><TxDtls>
+          <Refs><EndToEndId>NOTPROVIDED</EndToEndId></Refs>
+          <RltdPties>
+            <Cdtr><Pty><Nm>Stadtwerke</Nm></Pty></Cdtr>
+            <CdtrAcct><Id><IBAN>DE02120300000000202051</IBAN></Id></CdtrAcct>
+          </RltdPties>
+          <RmtInf><Ustrd>Strom März</Ustrd></RmtInf>
+        </TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">700.00</Amt><CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts><Cd>BOOK</Cd></Sts>
+        <BookgDt><Dt>2024-03-05</Dt></BookgDt>
+        <NtryDtls><TxDtls>
+          <Refs><EndToEndId>INV-4711</EndToEndId></Refs>
+          <RltdPties><Dbtr><Pty><Nm>Kunde AG</Nm></Pty></Dbtr></RltdPties>
+          <RmtInf><Ustrd>Rechnung 4711</Ustrd></RmtInf>
+        </TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">99.00</Amt><CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts><Cd>PDNG</Cd></Sts>
+        <BookgDt><Dt>2024-03-31</Dt></BookgDt>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+
+    #[test]
+    fn test_camt053_statement() {
+        assert!(looks_like(STATEMENT));
+
+        let statement = parse(STATEMENT).unwrap();
+
+        assert!(statement.issues.is_empty(), "{:?}", statement.issues);
+        assert_eq!(
+            statement.account_number.as_deref(),
+            Some("DE89370400440532013000")
+        );
+        assert_eq!(statement.account_holder.as_deref(), Some("Muster GmbH"));
+        assert_eq!(statement.bank_name.as_deref(), Some("COBADEFFXXX"));
+        assert_eq!(statement.opening_balance, Some(Decimal::new(100000, 2)));
+        assert_eq!(statement.closing_balance, Some(Decimal::new(145000, 2)));
+        assert_eq!(statement.lines.len(), 2);
+
+        let payment = &statement.lines[1];
+        assert_eq!(payment.amount, Decimal::new(-25000, 2));
+        assert_eq!(payment.description, "Stadtwerke (Strom März)");
+        assert_eq!(payment.reference.as_deref(), Some("BANKREF-2"));
+        assert_eq!(
+            payment.counterparty.as_ref().unwrap().account.as_deref(),
+            Some("DE02120300000000202051")
+        );
+        assert_eq!(statement.lines[0].reference.as_deref(), Some("INV-4711"));
+    }
+
+    #[test]
+    fn test_camt053_balance_tags_are_checked() {
+        let text = STATEMENT
+            .replace("<NbOfNtries>2</NbOfNtries>", "<NbOfNtries>3</NbOfNtries>")
+            .replace("<Cd>OPBD</Cd>", "<Cd>ITBD</Cd>");
+
+        let statement = parse(&text).unwrap();
+
+        assert_eq!(
+            statement.issues,
+            vec![
+                "Statement STMT-2024-03 has no opening booked balance (OPBD)",
+                "Statement STMT-2024-03 has 2 booked entries, but its summary counts 3",
+            ]
+        );
+    }
+}
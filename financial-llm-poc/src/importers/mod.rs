@@ -0,0 +1,409 @@
+mod bank_csv;
+mod camt;
+mod mt940;
+mod ofx;
+mod qif;
+
+pub use bank_csv::CsvMapping;
+
+use crate::dates::{parse_date, DateRange};
+use crate::document_types::{
+    DocumentMetadata, DocumentType, FinancialDocument, Party, RiskLevel, Transaction,
+};
+use crate::error::Result;
+use crate::fields::DocumentFields;
+use crate::money::{Currency, Money};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// A structured bank export that is read directly instead of by a model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementFormat {
+    Csv,
+    /// OFX 1.x (SGML) or 2.x (XML), including Quicken's QFX.
+    Ofx,
+    Qif,
+    /// ISO 20022 bank-to-customer statement (camt.053) XML.
+    Camt053,
+    /// SWIFT MT940 customer statement message.
+    Mt940,
+}
+
+impl StatementFormat {
+    /// Recognises an export from its content. CSV is only recognised when
+    /// `mapping` finds a date and an amount column in its header row.
+    pub fn detect(text: &str, mapping: &CsvMapping) -> Option<Self> {
+        if ofx::looks_like(text) {
+            Some(StatementFormat::Ofx)
+        } else if camt::looks_like(text) {
+            Some(StatementFormat::Camt053)
+        } else if mt940::looks_like(text) {
+            Some(StatementFormat::Mt940)
+        } else if qif::looks_like(text) {
+            Some(StatementFormat::Qif)
+        } else if mapping.recognizes(text) {
+            Some(StatementFormat::Csv)
+        } else {
+            None
+        }
+    }
+
+    pub fn parse(self, text: &str, mapping: &CsvMapping) -> Result<Statement> {
+        match self {
+            StatementFormat::Csv => bank_csv::parse(text, mapping),
+            StatementFormat::Ofx => ofx::parse(text),
+            StatementFormat::Qif => qif::parse(text),
+            StatementFormat::Camt053 => camt::parse(text),
+            StatementFormat::Mt940 => mt940::parse(text),
+        }
+    }
+}
+
+impl fmt::Display for StatementFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StatementFormat::Csv => "CSV",
+            StatementFormat::Ofx => "OFX",
+            StatementFormat::Qif => "QIF",
+            StatementFormat::Camt053 => "camt.053",
+            StatementFormat::Mt940 => "MT940",
+        })
+    }
+}
+
+/// Reads `text` as a bank export if it is one. Returns `None` for anything
+/// else, which is left to a model or the rule engine.
+pub fn import_statement(text: &str, mapping: &CsvMapping) -> Option<Result<FinancialDocument>> {
+    let format = StatementFormat::detect(text, mapping)?;
+    Some(format.parse(text, mapping).map(Statement::into_document))
+}
+
+/// A bank statement as read from an export, in a single currency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub format: StatementFormat,
+    pub bank_name: Option<String>,
+    pub account_number: Option<String>,
+    pub account_holder: Option<String>,
+    pub currency: Option<Currency>,
+    /// Taken from the transaction dates when the export does not state it.
+    pub period: Option<DateRange>,
+    pub opening_balance: Option<Decimal>,
+    pub closing_balance: Option<Decimal>,
+    /// In date order.
+    pub lines: Vec<StatementLine>,
+    /// Problems found while reading, such as balance tags that disagree.
+    /// They become validation errors of the document.
+    pub issues: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementLine {
+    pub date: NaiveDate,
+    pub description: String,
+    /// Positive for money in, negative for money out.
+    pub amount: Decimal,
+    /// Running balance after the line, when the export has one.
+    pub balance: Option<Decimal>,
+    /// Who paid or was paid, when the export names them.
+    pub counterparty: Option<Counterparty>,
+    pub reference: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterparty {
+    pub name: String,
+    /// IBAN or other account number.
+    pub account: Option<String>,
+}
+
+impl Statement {
+    pub fn new(format: StatementFormat) -> Self {
+        Self {
+            format,
+            bank_name: None,
+            account_number: None,
+            account_holder: None,
+            currency: None,
+            period: None,
+            opening_balance: None,
+            closing_balance: None,
+            lines: Vec::new(),
+            issues: Vec::new(),
+        }
+    }
+
+    /// Adds the balances of one of several consecutive statements of the
+    /// account, such as the pages of an MT940 message, after checking that
+    /// it opens where the one before it closed. `label` names it in issues.
+    fn chain_balances(
+        &mut self,
+        label: &str,
+        first: bool,
+        opening: Option<Decimal>,
+        closing: Option<Decimal>,
+    ) {
+        if first {
+            self.opening_balance = opening;
+        } else if let (Some(opening), Some(previous)) = (opening, self.closing_balance) {
+            if opening != previous {
+                self.issues.push(format!(
+                    "Statement {} opens at {}, but the statement before it closed at {}",
+                    label,
+                    self.show(opening),
+                    self.show(previous)
+                ));
+            }
+        }
+        self.closing_balance = closing;
+    }
+
+    fn show(&self, amount: Decimal) -> String {
+        let currency = self.currency.clone().unwrap_or_else(Currency::unknown);
+        Money::new(amount, currency).to_string()
+    }
+
+    /// A `BankStatement` with every line as a transaction. Totals are summed
+    /// from the lines, and the balances are reconciled like any other
+    /// statement. The account holder, the bank and every counterparty are
+    /// listed as parties.
+    pub fn into_document(self) -> FinancialDocument {
+        let currency = self.currency.clone().unwrap_or_else(Currency::unknown);
+        let money = |amount: Decimal| Money::new(amount, currency.clone());
+
+        let period = self.period.or_else(|| {
+            let start = self.lines.iter().map(|line| line.date).min()?;
+            let end = self.lines.iter().map(|line| line.date).max()?;
+            Some(DateRange { start, end })
+        });
+        let deposits: Decimal = self
+            .lines
+            .iter()
+            .map(|line| line.amount)
+            .filter(|amount| amount.is_sign_positive())
+            .sum();
+        let withdrawals: Decimal = self
+            .lines
+            .iter()
+            .map(|line| line.amount)
+            .filter(|amount| amount.is_sign_negative())
+            .sum();
+
+        let mut parties = Vec::new();
+        let mut add_party = |role: &str, name: &str, identifier: Option<&String>| {
+            let party = Party {
+                role: role.to_string(),
+                name: name.to_string(),
+                identifier: identifier.cloned(),
+            };
+            let known = parties.iter().any(|p: &Party| {
+                p.role == party.role && p.name == party.name && p.identifier == party.identifier
+            });
+            if !known {
+                parties.push(party);
+            }
+        };
+        if let Some(holder) = &self.account_holder {
+            add_party("account_holder", holder, self.account_number.as_ref());
+        }
+        if let Some(bank) = &self.bank_name {
+            add_party("bank", bank, None);
+        }
+        for line in &self.lines {
+            if let Some(counterparty) = &line.counterparty {
+                let role = if line.amount.is_sign_negative() {
+                    "payee"
+                } else {
+                    "payer"
+                };
+                add_party(role, &counterparty.name, counterparty.account.as_ref());
+            }
+        }
+
+        let mut extracted_data = HashMap::new();
+        for (key, value) in [
+            ("bank_name", self.bank_name),
+            ("account_number", self.account_number),
+            ("account_holder", self.account_holder),
+            ("period", period.map(|period| period.to_string())),
+            (
+                "beginning_balance",
+                self.opening_balance.map(|b| money(b).to_string()),
+            ),
+            (
+                "ending_balance",
+                self.closing_balance.map(|b| money(b).to_string()),
+            ),
+            ("total_deposits", Some(money(deposits).to_string())),
+            ("total_withdrawals", Some(money(-withdrawals).to_string())),
+        ] {
+            if let Some(value) = value {
+                extracted_data.insert(key.to_string(), value);
+            }
+        }
+
+        let line_count = self.lines.len();
+        let transactions = self
+            .lines
+            .into_iter()
+            .map(|line| Transaction {
+                date: Some(line.date),
+                description: line.description,
+                debit: line.amount.is_sign_negative().then(|| money(-line.amount)),
+                credit: line.amount.is_sign_positive().then(|| money(line.amount)),
+                balance: line.balance.map(money),
+                reference: line.reference,
+            })
+            .collect();
+
+        let mut document = FinancialDocument {
+            document_type: DocumentType::BankStatement,
+            // Read, not inferred.
+            confidence: 1.0,
+            extracted_data,
+            validation_errors: self.issues,
+            suggested_categories: vec!["Banking".to_string(), "Financial Records".to_string()],
+            tax_implications: Vec::new(),
+            risk_assessment: RiskLevel::Low,
+            metadata: DocumentMetadata {
+                document_date: None,
+                period,
+                total_amount: None,
+                currency: self.currency.map(|c| c.code().to_string()),
+                parties,
+                line_items: Vec::new(),
+                transactions,
+                page_count: None,
+                source_pages: BTreeMap::new(),
+            },
+            document_insights: vec![format!(
+                "Imported from {} with {} transaction(s)",
+                self.format, line_count
+            )],
+            prompt_version: None,
+            usage: None,
+            fields: DocumentFields::default(),
+        };
+        document.normalize();
+        document
+    }
+}
+
+/// Parses a column of dates. Numeric dates such as `03/04/2024` are read
+/// day-first when `day_first` says so or, when it is unset, when another
+/// date in the column only makes sense day-first.
+fn parse_dates(raw: &[&str], day_first: Option<bool>) -> Vec<Option<NaiveDate>> {
+    let day_first = day_first.unwrap_or_else(|| {
+        raw.iter().any(|date| {
+            date.split(['/', '-', '.'])
+                .next()
+                .filter(|first| first.len() <= 2)
+                .and_then(|first| first.parse::<u32>().ok())
+                .is_some_and(|first| first > 12)
+        })
+    });
+    raw.iter()
+        .map(|date| {
+            let parsed = parse_date(date)?;
+            Some(match parsed.alternative {
+                Some(alternative) if day_first => alternative,
+                _ => parsed.date,
+            })
+        })
+        .collect()
+}
+
+/// A line's description from the payee and memo of an export, keeping the
+/// memo when it adds something.
+fn describe(name: Option<String>, memo: Option<String>) -> String {
+    match (name, memo) {
+        (Some(name), Some(memo)) if !memo.is_empty() && memo != name => {
+            format!("{} ({})", name, memo)
+        }
+        (Some(name), _) => name,
+        (None, memo) => memo.unwrap_or_default(),
+    }
+}
+
+/// Reads an amount cell: `1,234.56`, `-12.00`, `(45.00)`, `€1.234,56` or
+/// `12.50 CR`. Returns the signed amount and the currency if one is shown.
+fn parse_amount(text: &str) -> Option<(Decimal, Option<Currency>)> {
+    let text = text.trim();
+    let (text, sign) = match (text.strip_suffix("CR"), text.strip_suffix("DR")) {
+        (Some(rest), _) => (rest, Decimal::ONE),
+        (_, Some(rest)) => (rest, Decimal::NEGATIVE_ONE),
+        _ => (text, Decimal::ONE),
+    };
+    let money = Money::parse(text.trim()).ok()?;
+    let currency = (!money.currency.is_unknown()).then_some(money.currency);
+    Some((money.amount * sign, currency))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statement_becomes_reconciled_bank_statement() {
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+        let mut statement = Statement::new(StatementFormat::Csv);
+        statement.currency = Currency::new("EUR");
+        statement.opening_balance = Some(Decimal::new(10000, 2));
+        statement.closing_balance = Some(Decimal::new(14000, 2));
+        statement.lines = vec![
+            StatementLine {
+                date: date(1),
+                description: "Salary".to_string(),
+                amount: Decimal::new(5000, 2),
+                balance: Some(Decimal::new(15000, 2)),
+                counterparty: None,
+                reference: None,
+            },
+            StatementLine {
+                date: date(4),
+                description: "Groceries".to_string(),
+                amount: Decimal::new(-1000, 2),
+                balance: Some(Decimal::new(14000, 2)),
+                counterparty: None,
+                reference: None,
+            },
+        ];
+
+        let document = statement.into_document();
+
+        assert_eq!(document.document_type, DocumentType::BankStatement);
+        assert!(
+            document.validation_errors.is_empty(),
+            "{:?}",
+            document.validation_errors
+        );
+        let DocumentFields::BankStatement(fields) = &document.fields else {
+            panic!("not a bank statement");
+        };
+        assert_eq!(
+            fields.period,
+            Some(DateRange {
+                start: date(1),
+                end: date(4)
+            })
+        );
+        assert_eq!(
+            fields.total_withdrawals.as_ref().unwrap().amount,
+            Decimal::new(1000, 2)
+        );
+        let groceries = &document.metadata.transactions[1];
+        assert_eq!(groceries.debit.as_ref().unwrap().currency.code(), "EUR");
+        assert_eq!(groceries.credit, None);
+    }
+
+    #[test]
+    fn test_parse_dates_guesses_day_first_from_the_column() {
+        let dates = parse_dates(&["03/04/2024", "25/04/2024"], None);
+        assert_eq!(dates[0], NaiveDate::from_ymd_opt(2024, 4, 3));
+
+        let dates = parse_dates(&["03/04/2024", "04/25/2024"], None);
+        assert_eq!(dates[0], NaiveDate::from_ymd_opt(2024, 3, 4));
+    }
+}
@@ -0,0 +1,405 @@
+use super::{describe, Counterparty, Statement, StatementFormat, StatementLine};
+use crate::error::{AnalyzerError, Result};
+use crate::money::Currency;
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+static FIELD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^:(\d{2}[A-Z]?):(.*)$").unwrap());
+
+/// `:60F:`, `:62F:` and their intermediate `M` forms: credit or debit mark,
+/// date, currency and amount.
+static BALANCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([CD])(\d{6})([A-Z]{3})(\d[\d,]*)$").unwrap());
+
+/// `:61:`: value date, optional entry date, mark (`RC`/`RD` reverse a
+/// credit or debit), optional funds code, amount, transaction type, the
+/// customer's and the bank's reference, and supplementary details on the
+/// next line. The customer's reference may hold single slashes; `//` starts
+/// the bank's.
+static ENTRY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(\d{6})(\d{4})?(RC|RD|C|D)([A-Z])?(\d[\d,]*)([NSF][A-Z0-9]{3})((?:[^/\n]|/[^/\n])*)(?://([^\n]*))?(?:\n(.*))?$",
+    )
+    .unwrap()
+});
+
+/// SEPA keywords in the purpose of German `:86:` fields.
+static SEPA_KEYWORD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(EREF|KREF|MREF|CRED|DEBT|SVWZ|ABWA|ABWE)\+").unwrap());
+
+pub(super) fn looks_like(text: &str) -> bool {
+    let tags: Vec<&str> = text
+        .lines()
+        .take(50)
+        .map(str::trim_start)
+        .filter(|line| line.starts_with(':'))
+        .collect();
+    [":20:", ":25:", ":60"]
+        .iter()
+        .all(|tag| tags.iter().any(|line| line.starts_with(tag)))
+}
+
+/// Reads every message in the file. They must be for the same account, and
+/// are then taken as consecutive statements.
+pub(super) fn parse(text: &str) -> Result<Statement> {
+    let messages = messages(text);
+    let mut accounts: Vec<&str> = messages
+        .iter()
+        .filter_map(|fields| value(fields, "25"))
+        .collect();
+    accounts.sort();
+    accounts.dedup();
+    if accounts.len() > 1 {
+        return Err(AnalyzerError::Import(format!(
+            "the file holds statements for several accounts: {}",
+            accounts.join(", ")
+        )));
+    }
+
+    let mut statement = Statement::new(StatementFormat::Mt940);
+    statement.account_number = accounts.first().map(|account| account.to_string());
+    for (index, fields) in messages.iter().enumerate() {
+        read_message(fields, index == 0, &mut statement)?;
+    }
+    statement.lines.sort_by_key(|line| line.date);
+    Ok(statement)
+}
+
+type Fields = Vec<(String, String)>;
+
+/// Splits the file into messages at each `:20:` field, dropping the SWIFT
+/// envelope. Lines that do not start a field continue the previous one.
+fn messages(text: &str) -> Vec<Fields> {
+    let mut messages: Vec<Fields> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('{') || line == "-" || line.starts_with("-}") {
+            continue;
+        }
+        match FIELD.captures(line) {
+            Some(captures) => {
+                let tag = captures[1].to_string();
+                if tag == "20" || messages.is_empty() {
+                    messages.push(Vec::new());
+                }
+                if let Some(fields) = messages.last_mut() {
+                    fields.push((tag, captures[2].to_string()));
+                }
+            }
+            None => {
+                if let Some((_, value)) = messages.last_mut().and_then(|fields| fields.last_mut()) {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+        }
+    }
+    messages
+}
+
+fn value<'a>(fields: &'a Fields, tag: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(field, _)| field == tag)
+        .map(|(_, value)| value.trim())
+}
+
+fn read_message(fields: &Fields, first: bool, statement: &mut Statement) -> Result<()> {
+    let label = value(fields, "28C")
+        .or_else(|| value(fields, "20"))
+        .unwrap_or("without reference")
+        .to_string();
+
+    let mut opening = None;
+    let mut closing = None;
+    for (tag, raw) in fields {
+        let (target, name) = match tag.as_str() {
+            "60F" | "60M" => (&mut opening, "opening"),
+            "62F" | "62M" => (&mut closing, "closing"),
+            _ => continue,
+        };
+        let (amount, currency) = balance(raw)?;
+        match &statement.currency {
+            None => statement.currency = Some(currency),
+            Some(account) if *account != currency => statement.issues.push(format!(
+                "The {} balance of statement {} is in {}, not in {}",
+                name, label, currency, account
+            )),
+            Some(_) => {}
+        }
+        *target = Some(amount);
+    }
+    if opening.is_none() {
+        statement.issues.push(format!(
+            "Statement {} has no opening balance (:60F:)",
+            label
+        ));
+    }
+    if closing.is_none() {
+        statement.issues.push(format!(
+            "Statement {} has no closing balance (:62F:)",
+            label
+        ));
+    }
+    statement.chain_balances(&label, first, opening, closing);
+
+    for (index, (tag, raw)) in fields.iter().enumerate() {
+        if tag != "61" {
+            continue;
+        }
+        // The information to the account owner belongs to the line before it.
+        let information = fields
+            .get(index + 1)
+            .filter(|(tag, _)| tag == "86")
+            .map(|(_, value)| value.as_str());
+        statement.lines.push(line(raw, information)?);
+    }
+    Ok(())
+}
+
+fn balance(raw: &str) -> Result<(Decimal, Currency)> {
+    let error = || AnalyzerError::Import(format!("unreadable balance '{}'", raw));
+    let captures = BALANCE.captures(raw.trim()).ok_or_else(error)?;
+    let amount = amount(&captures[4]).ok_or_else(error)?;
+    let currency = Currency::new(&captures[3]).ok_or_else(error)?;
+    Ok((if &captures[1] == "D" { -amount } else { amount }, currency))
+}
+
+fn line(raw: &str, information: Option<&str>) -> Result<StatementLine> {
+    let error = || AnalyzerError::Import(format!("unreadable statement line '{}'", raw));
+    let captures = ENTRY.captures(raw.trim()).ok_or_else(error)?;
+    let value_date = date(&captures[1]).ok_or_else(error)?;
+    // The booking date when given, which has no year of its own.
+    let date = match captures.get(2) {
+        Some(entry) => entry_date(value_date, entry.as_str()).ok_or_else(error)?,
+        None => value_date,
+    };
+    let amount = amount(&captures[5]).ok_or_else(error)?;
+    // A reversed debit puts money back into the account.
+    let amount = match &captures[3] {
+        "D" | "RC" => -amount,
+        _ => amount,
+    };
+
+    let customer = captures
+        .get(7)
+        .map(|m| m.as_str().trim())
+        .filter(|reference| !reference.is_empty() && *reference != "NONREF");
+    let bank = captures
+        .get(8)
+        .map(|m| m.as_str().trim())
+        .filter(|reference| !reference.is_empty());
+    let supplementary = captures.get(9).map(|m| m.as_str().trim().to_string());
+
+    let details = information.map(Details::parse).unwrap_or_default();
+    let description = describe(
+        details
+            .counterparty
+            .as_ref()
+            .map(|party| party.name.clone()),
+        details.purpose,
+    );
+    Ok(StatementLine {
+        date,
+        description: if description.is_empty() {
+            supplementary.unwrap_or_default()
+        } else {
+            description
+        },
+        amount,
+        balance: None,
+        counterparty: details.counterparty,
+        reference: details
+            .reference
+            .or(customer.map(str::to_string))
+            .or(bank.map(str::to_string)),
+    })
+}
+
+/// What `:86:` says about a line. German banks structure it into `?NN`
+/// subfields; other banks' free text is taken as the purpose.
+#[derive(Default)]
+struct Details {
+    purpose: Option<String>,
+    counterparty: Option<Counterparty>,
+    reference: Option<String>,
+}
+
+impl Details {
+    fn parse(information: &str) -> Self {
+        let structured = information.len() > 3
+            && information.as_bytes()[..3].iter().all(u8::is_ascii_digit)
+            && information[3..].starts_with('?');
+        if !structured {
+            let text = information
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join(" ");
+            return Self {
+                purpose: (!text.is_empty()).then_some(text),
+                ..Self::default()
+            };
+        }
+
+        let joined: String = information.lines().collect();
+        let mut booking_text = String::new();
+        let mut purpose = String::new();
+        let mut name = String::new();
+        let mut account = None;
+        for subfield in joined[3..].split('?').skip(1) {
+            let (Some(code), Some(text)) = (subfield.get(..2), subfield.get(2..)) else {
+                continue;
+            };
+            match code.parse::<u8>() {
+                Ok(0) => booking_text.push_str(text),
+                Ok(20..=29 | 60..=63) => purpose.push_str(text),
+                Ok(31) => account = Some(text.trim().to_string()),
+                Ok(32 | 33) => name.push_str(text),
+                _ => {}
+            }
+        }
+
+        // SEPA payments split the purpose into `EREF+`, `SVWZ+` and so on.
+        let mut reference = None;
+        let keywords: Vec<_> = SEPA_KEYWORD.captures_iter(&purpose).collect();
+        if !keywords.is_empty() {
+            let mut remittance = None;
+            for (index, captures) in keywords.iter().enumerate() {
+                let whole = captures.get(0).map_or(0..0, |m| m.range());
+                let end = keywords
+                    .get(index + 1)
+                    .and_then(|next| next.get(0))
+                    .map_or(purpose.len(), |m| m.start());
+                let text = purpose[whole.end..end].trim().to_string();
+                match &captures[1] {
+                    "EREF" if text != "NOTPROVIDED" => reference = Some(text),
+                    "SVWZ" => remittance = Some(text),
+                    _ => {}
+                }
+            }
+            purpose = remittance.unwrap_or_default();
+        }
+
+        let purpose = [purpose.trim(), booking_text.trim()]
+            .into_iter()
+            .find(|text| !text.is_empty())
+            .map(str::to_string);
+        let name = name.trim();
+        Self {
+            purpose,
+            counterparty: (!name.is_empty()).then(|| Counterparty {
+                name: name.to_string(),
+                account: account.filter(|account| !account.is_empty()),
+            }),
+            reference,
+        }
+    }
+}
+
+/// `YYMMDD`.
+fn date(value: &str) -> Option<NaiveDate> {
+    let year = 2000 + value.get(..2)?.parse::<i32>().ok()?;
+    let month = value.get(2..4)?.parse().ok()?;
+    let day = value.get(4..6)?.parse().ok()?;
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// `MMDD` near `value_date`, which can be in the year before or after.
+fn entry_date(value_date: NaiveDate, value: &str) -> Option<NaiveDate> {
+    let month: u32 = value.get(..2)?.parse().ok()?;
+    let day = value.get(2..4)?.parse().ok()?;
+    let year = match (value_date.month(), month) {
+        (1, 12) => value_date.year() - 1,
+        (12, 1) => value_date.year() + 1,
+        _ => value_date.year(),
+    };
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// Amounts use a decimal comma and may end in it, as in `100,`.
+fn amount(value: &str) -> Option<Decimal> {
+    let mut value = value.replace(',', ".");
+    if value.ends_with('.') {
+        value.push('0');
+    }
+    Decimal::from_str(&value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENT: &str = "\
+{1:F01COBADEFFAXXX0000000000}{2:O9400000240401COBADEFFAXXX00000000002404010000N}{4:
+:20:STARTUMS
+:25:37040044/0532013000
+:28C:00001/001
+:60F:C240301EUR1000,00
+:61:2403050305CR700,00NTRFINV-4711//BANK-1
+:86:166?00GUTSCHRIFT?20EREF+INV-4711?21SVWZ+Rechnung 4711?30COBADEFF
+?31DE44500105175407324931?32Kunde AG
+:61:2403120312DR250,NDDTNONREF//BANK-2
+Lastschrift
+:62M:C240312EUR1450,00
+-}
+:20:STARTUMS
+:25:37040044/0532013000
+:28C:00001/002
+:60M:C240312EUR1450,00
+:61:240331D0,50NCHGFEE/2403//BANK-3
+:86:Kontofuehrung
+:62F:C240331EUR1449,50
+-";
+
+    #[test]
+    fn test_mt940_messages_are_chained() {
+        assert!(looks_like(STATEMENT));
+
+        let statement = parse(STATEMENT).unwrap();
+
+        assert!(statement.issues.is_empty(), "{:?}", statement.issues);
+        assert_eq!(
+            statement.account_number.as_deref(),
+            Some("37040044/0532013000")
+        );
+        assert_eq!(statement.currency, Currency::new("EUR"));
+        assert_eq!(statement.opening_balance, Some(Decimal::new(100000, 2)));
+        assert_eq!(statement.closing_balance, Some(Decimal::new(144950, 2)));
+        assert_eq!(statement.lines.len(), 3);
+
+        let credit = &statement.lines[0];
+        assert_eq!(credit.amount, Decimal::new(70000, 2));
+        assert_eq!(credit.description, "Kunde AG (Rechnung 4711)");
+        assert_eq!(credit.reference.as_deref(), Some("INV-4711"));
+        assert_eq!(
+            credit.counterparty.as_ref().unwrap().account.as_deref(),
+            Some("DE44500105175407324931")
+        );
+        assert_eq!(statement.lines[1].amount, Decimal::new(-25000, 2));
+        assert_eq!(statement.lines[1].description, "Lastschrift");
+        assert_eq!(statement.lines[1].reference.as_deref(), Some("BANK-2"));
+        assert_eq!(statement.lines[2].description, "Kontofuehrung");
+        assert_eq!(statement.lines[2].reference.as_deref(), Some("FEE/2403"));
+    }
+
+    #[test]
+    fn test_mt940_balance_tags_are_checked() {
+        let text = STATEMENT
+            .replace(":60M:C240312EUR1450,00", ":60M:C240312EUR1400,00")
+            .replace(":62F:C240331EUR1449,50\n", "");
+
+        let statement = parse(&text).unwrap();
+
+        assert_eq!(
+            statement.issues,
+            vec![
+                "Statement 00001/002 has no closing balance (:62F:)",
+                "Statement 00001/002 opens at €1,400.00, but the statement before it closed at €1,450.00",
+            ]
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use super::{describe, Statement, StatementFormat, StatementLine};
+use crate::dates::DateRange;
+use crate::error::{AnalyzerError, Result};
+use crate::money::Currency;
+use chrono::NaiveDate;
+use regex::Regex;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+/// A start or end tag and the text up to the next tag. OFX 1.x is SGML, so
+/// elements holding a value usually have no end tag.
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(/?)([A-Za-z0-9.]+)>([^<]*)").unwrap());
+
+pub(super) fn looks_like(text: &str) -> bool {
+    let head: String = text.chars().take(1024).collect();
+    head.contains("OFXHEADER") || head.contains("<OFX>")
+}
+
+/// Reads the first bank or credit card statement in the file.
+pub(super) fn parse(text: &str) -> Result<Statement> {
+    let mut statement = Statement::new(StatementFormat::Ofx);
+    let mut open: Vec<String> = Vec::new();
+    let mut transaction: Option<HashMap<String, String>> = None;
+    let mut period = (None, None);
+    let mut found = false;
+
+    for captures in TAG.captures_iter(text) {
+        let name = captures[2].to_ascii_uppercase();
+        if &captures[1] == "/" {
+            // Closes the element and any value elements left open inside it;
+            // end tags of value elements themselves are not on the stack.
+            if let Some(index) = open.iter().rposition(|tag| *tag == name) {
+                open.truncate(index);
+            }
+            match name.as_str() {
+                "STMTTRN" => {
+                    if let Some(fields) = transaction.take() {
+                        statement.lines.push(line(fields)?);
+                    }
+                }
+                "STMTRS" | "CCSTMTRS" => break,
+                _ => {}
+            }
+            continue;
+        }
+
+        let value = decode(captures[3].trim());
+        if value.is_empty() {
+            match name.as_str() {
+                "STMTTRN" => transaction = Some(HashMap::new()),
+                "STMTRS" | "CCSTMTRS" => found = true,
+                _ => {}
+            }
+            open.push(name);
+            continue;
+        }
+
+        let parent = open.last().map(String::as_str).unwrap_or("");
+        if let Some(fields) = transaction.as_mut() {
+            fields.entry(name).or_insert(value);
+            continue;
+        }
+        match (parent, name.as_str()) {
+            (_, "CURDEF") => statement.currency = Currency::new(&value),
+            ("BANKACCTFROM" | "CCACCTFROM", "ACCTID") => statement.account_number = Some(value),
+            ("FI", "ORG") => statement.bank_name = Some(value),
+            ("BANKTRANLIST", "DTSTART") => period.0 = parse_date(&value),
+            ("BANKTRANLIST", "DTEND") => period.1 = parse_date(&value),
+            ("LEDGERBAL", "BALAMT") => statement.closing_balance = Some(parse_amount(&value)?),
+            _ => {}
+        }
+    }
+
+    if !found {
+        return Err(AnalyzerError::Import(
+            "no bank or credit card statement in the OFX file".to_string(),
+        ));
+    }
+    if let (Some(start), Some(end)) = period {
+        statement.period = Some(DateRange { start, end });
+    }
+    statement.lines.sort_by_key(|line| line.date);
+    Ok(statement)
+}
+
+fn line(mut fields: HashMap<String, String>) -> Result<StatementLine> {
+    let posted = fields.remove("DTPOSTED").unwrap_or_default();
+    let date = parse_date(&posted).ok_or_else(|| {
+        AnalyzerError::Import(format!("transaction with unreadable date '{}'", posted))
+    })?;
+    let amount = parse_amount(&fields.remove("TRNAMT").unwrap_or_default())?;
+    Ok(StatementLine {
+        date,
+        description: describe(fields.remove("NAME"), fields.remove("MEMO")),
+        amount,
+        balance: None,
+        counterparty: None,
+        reference: None,
+    })
+}
+
+/// OFX dates are `YYYYMMDD`, optionally followed by a time and time zone.
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
+
+fn parse_amount(value: &str) -> Result<Decimal> {
+    let normalized = value.trim_start_matches('+').replace(',', ".");
+    Decimal::from_str(&normalized)
+        .map_err(|_| AnalyzerError::Import(format!("unreadable amount '{}'", value)))
+}
+
+fn decode(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sgml_ofx_statement() {
+        let text = "\
+OFXHEADER:100
+DATA:OFXSGML
+VERSION:102
+
+<OFX>
+<SIGNONMSGSRSV1><SONRS><FI><ORG>First Bank<FID>1001</FI></SONRS></SIGNONMSGSRSV1>
+<BANKMSGSRSV1><STMTTRNRS><STMTRS>
+<CURDEF>USD
+<BANKACCTFROM><BANKID>121000248<ACCTID>000123456<ACCTTYPE>CHECKING</BANKACCTFROM>
+<BANKTRANLIST>
+<DTSTART>20240101<DTEND>20240131
+<STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240112120000[-5:EST]<TRNAMT>-285.50
+<NAME>Grocery Store<MEMO>Card 1234</STMTTRN>
+<STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240105<TRNAMT>3500.00<NAME>Payroll &amp; Bonus</STMTTRN>
+</BANKTRANLIST>
+<LEDGERBAL><BALAMT>3214.50<DTASOF>20240131</LEDGERBAL>
+</STMTRS></STMTTRNRS></BANKMSGSRSV1>
+</OFX>
+";
+        assert!(looks_like(text));
+
+        let statement = parse(text).unwrap();
+
+        assert_eq!(statement.bank_name.as_deref(), Some("First Bank"));
+        assert_eq!(statement.account_number.as_deref(), Some("000123456"));
+        assert_eq!(statement.currency, Currency::new("USD"));
+        assert_eq!(statement.closing_balance, Some(Decimal::new(321450, 2)));
+        assert_eq!(
+            statement.period.unwrap().end,
+            NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()
+        );
+        assert_eq!(statement.lines.len(), 2);
+        assert_eq!(statement.lines[0].description, "Payroll & Bonus");
+        assert_eq!(statement.lines[1].description, "Grocery Store (Card 1234)");
+        assert_eq!(statement.lines[1].amount, Decimal::new(-28550, 2));
+    }
+
+    #[test]
+    fn test_xml_ofx_credit_card_statement() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<?OFX OFXHEADER="200" VERSION="220"?>
+<OFX><CREDITCARDMSGSRSV1><CCSTMTTRNRS><CCSTMTRS>
+  <CURDEF>EUR</CURDEF>
+  <CCACCTFROM><ACCTID>4111</ACCTID></CCACCTFROM>
+  <BANKTRANLIST>
+    <STMTTRN><DTPOSTED>20240302</DTPOSTED><TRNAMT>-42,10</TRNAMT><NAME>Cafe</NAME><MEMO></MEMO></STMTTRN>
+  </BANKTRANLIST>
+</CCSTMTRS></CCSTMTTRNRS></CREDITCARDMSGSRSV1></OFX>"#;
+
+        let statement = parse(text).unwrap();
+
+        assert_eq!(statement.account_number.as_deref(), Some("4111"));
+        assert_eq!(statement.lines[0].description, "Cafe");
+        assert_eq!(statement.lines[0].amount, Decimal::new(-4210, 2));
+        assert!(parse("<OFX><INVSTMTMSGSRSV1></INVSTMTMSGSRSV1></OFX>").is_err());
+    }
+}
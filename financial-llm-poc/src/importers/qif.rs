@@ -0,0 +1,151 @@
+use super::{describe, parse_amount, parse_dates, Statement, StatementFormat, StatementLine};
+use crate::error::{AnalyzerError, Result};
+
+/// Sections holding the transactions of a bank-like account; investment
+/// sections, categories and memorized transactions are skipped.
+const ACCOUNT_TYPES: [&str; 5] = ["bank", "cash", "ccard", "oth a", "oth l"];
+
+pub(super) fn looks_like(text: &str) -> bool {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .is_some_and(|line| {
+            ["!Type:", "!Account", "!Option"]
+                .iter()
+                .any(|header| line.starts_with(header))
+        })
+}
+
+#[derive(Default)]
+struct Record {
+    date: Option<String>,
+    amount: Option<String>,
+    payee: Option<String>,
+    memo: Option<String>,
+}
+
+pub(super) fn parse(text: &str) -> Result<Statement> {
+    let mut records = Vec::new();
+    let mut in_account = false;
+    let mut record = Record::default();
+
+    for line in text.lines() {
+        let line = line.trim_end();
+        if let Some(header) = line.strip_prefix('!') {
+            in_account = header.strip_prefix("Type:").is_some_and(|kind| {
+                ACCOUNT_TYPES.contains(&kind.trim().to_ascii_lowercase().as_str())
+            });
+            record = Record::default();
+            continue;
+        }
+        if !in_account {
+            continue;
+        }
+        let value = || line[1..].trim().to_string();
+        match line.chars().next() {
+            Some('^') => records.push(std::mem::take(&mut record)),
+            Some('D') => record.date = Some(value().replace('\'', "/").replace(' ', "")),
+            // `U` is the same amount with more digits in newer exports.
+            Some('T' | 'U') => record.amount = Some(value()),
+            Some('P') => record.payee = Some(value()),
+            Some('M') => record.memo = Some(value()),
+            _ => {}
+        }
+    }
+
+    let raw_dates: Vec<&str> = records
+        .iter()
+        .map(|record| record.date.as_deref().unwrap_or(""))
+        .collect();
+    let dates = parse_dates(&raw_dates, None);
+
+    let mut statement = Statement::new(StatementFormat::Qif);
+    for (index, (record, date)) in records.into_iter().zip(dates).enumerate() {
+        let number = index + 1;
+        let date = date.ok_or_else(|| {
+            AnalyzerError::Import(format!(
+                "record {}: unreadable date '{}'",
+                number,
+                record.date.as_deref().unwrap_or("")
+            ))
+        })?;
+        let raw_amount = record.amount.unwrap_or_default();
+        let (amount, currency) = parse_amount(&raw_amount).ok_or_else(|| {
+            AnalyzerError::Import(format!(
+                "record {}: unreadable amount '{}'",
+                number, raw_amount
+            ))
+        })?;
+        if statement.currency.is_none() {
+            statement.currency = currency;
+        }
+        // Quicken starts an account with a record for its opening balance.
+        if index == 0 && record.payee.as_deref() == Some("Opening Balance") {
+            statement.opening_balance = Some(amount);
+            continue;
+        }
+        statement.lines.push(StatementLine {
+            date,
+            description: describe(record.payee, record.memo),
+            amount,
+            balance: None,
+            counterparty: None,
+            reference: None,
+        });
+    }
+
+    if statement.lines.is_empty() && statement.opening_balance.is_none() {
+        return Err(AnalyzerError::Import(
+            "no bank account transactions in the QIF file".to_string(),
+        ));
+    }
+    statement.lines.sort_by_key(|line| line.date);
+    Ok(statement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_qif_bank_account() {
+        let text = "\
+!Type:Bank
+D1/ 1'24
+T1,000.00
+POpening Balance
+L[Checking]
+^
+D1/15'24
+T-1,234.56
+PLandlord
+MJanuary rent
+^
+D1/5'24
+U2,500.00
+T2,500.00
+PEmployer
+^
+!Type:Invst
+D1/20'24
+NBuy
+T500.00
+^
+";
+        assert!(looks_like(text));
+
+        let statement = parse(text).unwrap();
+
+        assert_eq!(statement.opening_balance, Some(Decimal::new(100000, 2)));
+        assert_eq!(statement.lines.len(), 2);
+        assert_eq!(statement.lines[0].description, "Employer");
+        assert_eq!(
+            statement.lines[1].date,
+            NaiveDate::from_ymd_opt(2024, 1, 15).unwrap()
+        );
+        assert_eq!(statement.lines[1].description, "Landlord (January rent)");
+        assert!(parse("!Type:Bank\nDtomorrow\nT1.00\n^\n").is_err());
+    }
+}
@@ -0,0 +1,402 @@
+use crate::document_types::FinancialDocument;
+use crate::error::{AnalyzerError, Result};
+use pdf_extract::{Dictionary, Document, MediaBox, Object, OutputDev, OutputError, Transform};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+/// Separates pages in `PagedText::text`, as `pdftotext` does.
+pub const PAGE_BREAK: char = '\u{c}';
+
+/// Whether `bytes` start like a PDF file.
+pub fn is_pdf(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"%PDF-")
+}
+
+/// The text of a document, one entry per page, so that extracted values can
+/// be traced back to the page they came from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PagedText {
+    pages: Vec<String>,
+}
+
+impl PagedText {
+    pub fn new(pages: Vec<String>) -> Self {
+        Self { pages }
+    }
+
+    /// Extracts the text layer of a PDF. Characters are placed by their
+    /// position on the page, so columns of a table stay lined up. Scanned
+    /// PDFs have no text layer and are rejected; they need OCR first.
+    pub fn from_pdf(bytes: &[u8]) -> Result<Self> {
+        let document = load(bytes)?;
+        let mut layout = LayoutText::default();
+        // pdf-extract panics on some malformed files instead of failing.
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            pdf_extract::output_doc(&document, &mut layout)
+        }))
+        .map_err(|_| AnalyzerError::Pdf("the PDF is malformed".to_string()))?
+        .map_err(pdf_error)?;
+
+        if layout.pages.iter().all(|page| page.trim().is_empty()) {
+            return Err(AnalyzerError::Pdf(
+                "no text layer; scanned PDFs need OCR first".to_string(),
+            ));
+        }
+        Ok(Self::new(layout.pages))
+    }
+
+    pub fn load_pdf(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| AnalyzerError::Pdf(format!("{}: {}", path.display(), e)))?;
+        Self::from_pdf(&bytes)
+    }
+
+    pub fn pages(&self) -> &[String] {
+        &self.pages
+    }
+
+    /// All pages, separated by form feeds.
+    pub fn text(&self) -> String {
+        self.pages.join(&PAGE_BREAK.to_string())
+    }
+
+    /// The first page (1-based) that contains `value`, ignoring case,
+    /// spacing, currency symbols and thousands separators.
+    pub fn page_of(&self, value: &str) -> Option<u32> {
+        find_page(&self.fold_pages(), value)
+    }
+
+    /// Records the page count and where each extracted value, line item and
+    /// transaction was found in `document.metadata`.
+    pub fn annotate(&self, document: &mut FinancialDocument) {
+        let pages = self.fold_pages();
+        let metadata = &mut document.metadata;
+        metadata.page_count = Some(self.pages.len() as u32);
+
+        for (key, value) in &document.extracted_data {
+            if let Some(page) = find_page(&pages, value) {
+                metadata.source_pages.insert(key.clone(), page);
+            }
+        }
+        let line_items = metadata
+            .line_items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (format!("line_items[{}]", index), &item.description));
+        let transactions = metadata
+            .transactions
+            .iter()
+            .enumerate()
+            .map(|(index, transaction)| {
+                (format!("transactions[{}]", index), &transaction.description)
+            });
+        let found: Vec<(String, u32)> = line_items
+            .chain(transactions)
+            .filter_map(|(key, description)| Some((key, find_page(&pages, description)?)))
+            .collect();
+        metadata.source_pages.extend(found);
+    }
+
+    fn fold_pages(&self) -> Vec<String> {
+        self.pages.iter().map(|page| fold(page)).collect()
+    }
+}
+
+/// The files attached to a PDF, by name, such as the XML of a Factur-X
+/// invoice.
+pub fn attachments(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let document = load(bytes)?;
+    let names = document
+        .catalog()
+        .and_then(|catalog| catalog.get_deref(b"Names", &document))
+        .and_then(|names| names.as_dict())
+        .and_then(|names| names.get_deref(b"EmbeddedFiles", &document))
+        .and_then(Object::as_dict);
+    let mut files = Vec::new();
+    if let Ok(tree) = names {
+        collect_attachments(&document, tree, &mut files);
+    }
+    Ok(files)
+}
+
+fn load(bytes: &[u8]) -> Result<Document> {
+    let mut document = Document::load_mem(bytes).map_err(|e| pdf_error(e.into()))?;
+    if document.is_encrypted() {
+        // Many PDFs are encrypted with an empty user password only to
+        // restrict printing or copying.
+        document
+            .decrypt("")
+            .map_err(|_| AnalyzerError::Pdf("the PDF is password protected".to_string()))?;
+    }
+    Ok(document)
+}
+
+/// Walks a name tree of file specifications; attachments that cannot be
+/// read are skipped.
+fn collect_attachments(document: &Document, node: &Dictionary, files: &mut Vec<(String, Vec<u8>)>) {
+    if let Ok(kids) = node.get_deref(b"Kids", document).and_then(Object::as_array) {
+        for kid in kids {
+            if let Ok((_, Object::Dictionary(kid))) = document.dereference(kid) {
+                collect_attachments(document, kid, files);
+            }
+        }
+    }
+    let Ok(names) = node
+        .get_deref(b"Names", document)
+        .and_then(Object::as_array)
+    else {
+        return;
+    };
+    for pair in names.chunks(2) {
+        let [_, spec] = pair else {
+            continue;
+        };
+        let Ok((_, Object::Dictionary(spec))) = document.dereference(spec) else {
+            continue;
+        };
+        let name = [b"UF".as_slice(), b"F"]
+            .iter()
+            .find_map(|key| pdf_extract::decode_text_string(spec.get(key).ok()?).ok());
+        let stream = spec
+            .get_deref(b"EF", document)
+            .and_then(Object::as_dict)
+            .and_then(|files| {
+                files
+                    .get_deref(b"F", document)
+                    .or_else(|_| files.get_deref(b"UF", document))
+            })
+            .and_then(Object::as_stream);
+        if let (Some(name), Ok(stream)) = (name, stream) {
+            // Streams without a filter are stored as they are.
+            let content = match stream.filters() {
+                Ok(_) => stream.decompressed_content(),
+                Err(_) => Ok(stream.content.clone()),
+            };
+            if let Ok(content) = content {
+                files.push((name, content));
+            }
+        }
+    }
+}
+
+fn pdf_error(error: OutputError) -> AnalyzerError {
+    AnalyzerError::Pdf(error.to_string())
+}
+
+fn find_page(folded_pages: &[String], value: &str) -> Option<u32> {
+    let needle = fold(value);
+    // Shorter values such as a quantity of `10` match almost anywhere.
+    if needle.chars().count() < 3 {
+        return None;
+    }
+    folded_pages
+        .iter()
+        .position(|page| page.contains(&needle))
+        .map(|index| index as u32 + 1)
+}
+
+fn fold(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '/'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// A character placed on the page, in points from the top left corner.
+struct Glyph {
+    x: f64,
+    y: f64,
+    end: f64,
+    size: f64,
+    text: String,
+}
+
+/// Collects the characters of each page and lays them out as lines of text
+/// once the page is complete.
+#[derive(Default)]
+struct LayoutText {
+    page_top: f64,
+    glyphs: Vec<Glyph>,
+    pages: Vec<String>,
+}
+
+impl OutputDev for LayoutText {
+    fn begin_page(
+        &mut self,
+        _page_num: u32,
+        media_box: &MediaBox,
+        _art_box: Option<(f64, f64, f64, f64)>,
+    ) -> std::result::Result<(), OutputError> {
+        self.page_top = media_box.ury;
+        self.glyphs.clear();
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> std::result::Result<(), OutputError> {
+        let glyphs = std::mem::take(&mut self.glyphs);
+        self.pages.push(lay_out(glyphs));
+        Ok(())
+    }
+
+    fn output_character(
+        &mut self,
+        trm: &Transform,
+        width: f64,
+        _spacing: f64,
+        font_size: f64,
+        char: &str,
+    ) -> std::result::Result<(), OutputError> {
+        // Spaces are recreated from the gaps between characters.
+        if char.trim().is_empty() {
+            return Ok(());
+        }
+        let size = (font_size * (trm.m11 + trm.m21) * font_size * (trm.m12 + trm.m22))
+            .abs()
+            .sqrt();
+        self.glyphs.push(Glyph {
+            x: trm.m31,
+            y: self.page_top - trm.m32,
+            end: trm.m31 + width * size,
+            size,
+            text: char.to_string(),
+        });
+        Ok(())
+    }
+
+    fn begin_word(&mut self) -> std::result::Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_word(&mut self) -> std::result::Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> std::result::Result<(), OutputError> {
+        Ok(())
+    }
+}
+
+/// Groups glyphs into lines by their baseline and places each word at the
+/// column its position maps to, using the typical character width as the
+/// column width. Larger vertical gaps become blank lines.
+fn lay_out(mut glyphs: Vec<Glyph>) -> String {
+    let mut advances: Vec<f64> = glyphs
+        .iter()
+        .map(|glyph| glyph.end - glyph.x)
+        .filter(|advance| *advance > 0.0)
+        .collect();
+    advances.sort_by(f64::total_cmp);
+    let column = advances
+        .get(advances.len() / 2)
+        .copied()
+        .unwrap_or(5.0)
+        .max(1.0);
+    let left = glyphs
+        .iter()
+        .map(|glyph| glyph.x)
+        .fold(f64::INFINITY, f64::min);
+
+    glyphs.sort_by(|a, b| a.y.total_cmp(&b.y));
+    let mut lines: Vec<Vec<Glyph>> = Vec::new();
+    for glyph in glyphs {
+        match lines.last_mut() {
+            Some(line) if (glyph.y - line[0].y).abs() <= line[0].size * 0.5 => line.push(glyph),
+            _ => lines.push(vec![glyph]),
+        }
+    }
+
+    let mut text = String::new();
+    let mut previous: Option<(f64, f64)> = None;
+    for mut line in lines {
+        line.sort_by(|a, b| a.x.total_cmp(&b.x));
+        let (y, size) = (line[0].y, line[0].size);
+        if previous.is_some_and(|(previous_y, previous_size)| {
+            y - previous_y > previous_size.max(size) * 2.0
+        }) {
+            text.push('\n');
+        }
+        previous = Some((y, size));
+
+        let mut row = String::new();
+        let mut width = 0;
+        let mut end = f64::NEG_INFINITY;
+        for glyph in line {
+            // Only gaps between words are aligned, so proportional text
+            // inside a word is never spread out.
+            if glyph.x - end > column * 0.3 {
+                let target = ((glyph.x - left) / column).round().max(0.0) as usize;
+                let pad = if row.is_empty() {
+                    target
+                } else {
+                    target.saturating_sub(width).max(1)
+                };
+                row.extend(std::iter::repeat_n(' ', pad));
+                width += pad;
+            }
+            row.push_str(&glyph.text);
+            width += glyph.text.chars().count();
+            end = glyph.end;
+        }
+        text.push_str(row.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_types::{DocumentType, LegacyFinancialDocument};
+
+    fn invoice() -> PagedText {
+        PagedText::load_pdf(Path::new("fixtures/pdf/invoice.pdf")).unwrap()
+    }
+
+    #[test]
+    fn test_pdf_pages_keep_table_columns() {
+        let text = invoice();
+
+        assert_eq!(text.pages().len(), 2);
+        let page = &text.pages()[0];
+        let header = page.lines().find(|l| l.starts_with("Description")).unwrap();
+        let row = page.lines().find(|l| l.starts_with("Toner")).unwrap();
+        assert_eq!(header.find("Amount"), row.find("120.00"));
+        assert!(text.pages()[1].contains("Total Due: $178.20"));
+        assert_eq!(text.text().matches(PAGE_BREAK).count(), 1);
+
+        assert!(matches!(
+            PagedText::from_pdf(b"%PDF-1.4 not really"),
+            Err(AnalyzerError::Pdf(_))
+        ));
+    }
+
+    #[test]
+    fn test_annotate_records_source_pages() {
+        let text = invoice();
+        let mut document: FinancialDocument = LegacyFinancialDocument {
+            document_type: "invoice".to_string(),
+            confidence: 0.9,
+            extracted_data: [
+                ("invoice_number", "INV-7731"),
+                ("total_amount", "178.20"),
+                ("quantity", "10"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+            validation_errors: vec![],
+            suggested_categories: vec![],
+            document_insights: vec![],
+        }
+        .into();
+        assert_eq!(document.document_type, DocumentType::Invoice);
+
+        text.annotate(&mut document);
+
+        let pages = &document.metadata.source_pages;
+        assert_eq!(document.metadata.page_count, Some(2));
+        assert_eq!(pages.get("invoice_number"), Some(&1));
+        assert_eq!(pages.get("total_amount"), Some(&2));
+        assert_eq!(pages.get("quantity"), None);
+    }
+}
@@ -0,0 +1,51 @@
+pub mod batch;
+pub mod cache;
+pub mod cost;
+pub mod dates;
+pub mod document_types;
+pub mod einvoice;
+pub mod error;
+pub mod evaluation;
+pub mod fields;
+pub mod financial_analyzer;
+pub mod importers;
+pub mod ingest;
+pub mod llm_provider;
+pub mod money;
+pub mod prompts;
+pub mod redaction;
+pub mod rule_extractor;
+pub mod schema;
+pub mod streaming;
+pub mod transactions;
+pub mod validator;
+mod xml;
+
+// Re-export for easier access
+pub use batch::{BatchOptions, BatchProgress};
+pub use cache::ResponseCache;
+pub use cost::{CostLedger, PriceTable, UsageReport};
+pub use dates::DateRange;
+pub use document_types::{
+    DocumentMetadata, DocumentType, FinancialDocument, LegacyFinancialDocument, LineItem, Party,
+    RiskLevel, Transaction, ValidationResult,
+};
+pub use einvoice::{Invoice, InvoiceSyntax};
+pub use error::AnalyzerError;
+pub use evaluation::{EvalCase, EvalReport, Evaluator};
+pub use fields::{
+    BankStatementFields, DocumentFields, InvoiceFields, PayrollFields, ReceiptFields, W2Fields,
+};
+pub use financial_analyzer::{AnalysisPrompt, FinancialAnalyzer};
+pub use importers::{CsvMapping, Statement, StatementFormat};
+pub use ingest::PagedText;
+pub use llm_provider::{
+    Completion, LlmProvider, LocalProvider, MockProvider, OpenAiProvider, OpenRouterProvider,
+    RecordedProvider, Recording, RetryPolicy, Usage,
+};
+pub use money::{Currency, Money};
+pub use prompts::{PromptLibrary, PromptTemplate};
+pub use redaction::{Redactions, Redactor};
+pub use rule_extractor::RuleBasedExtractor;
+pub use streaming::StreamProgress;
+pub use validator::DocumentValidator;
@@ -0,0 +1,1472 @@
+use crate::error::{AnalyzerError, Result};
+use crate::streaming::{repair_truncated_json, SseParser, StreamProgress};
+use async_trait::async_trait;
+use rand::Rng;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+const OPENAI_CHAT_URL: &str = "https://api.openai.com/v1/chat/completions";
+const OPENROUTER_CHAT_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
+/// Where llama.cpp's `llama-server` listens by default.
+pub const DEFAULT_LOCAL_URL: &str = "http://localhost:8080/v1";
+/// Upper bound for a whole streamed completion.
+const STREAM_TIMEOUT: Duration = Duration::from_secs(600);
+/// A stream that sends nothing for this long is treated as cut off.
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LLMRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    pub temperature: f32,
+    pub max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// A model's answer: the content of the first choice and, when the backend
+/// reports it, the tokens the request consumed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub content: String,
+    pub usage: Option<Usage>,
+    /// The stream was cut off and `content` is what could be recovered.
+    pub truncated: bool,
+}
+
+impl Completion {
+    pub fn new(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            usage: None,
+            truncated: false,
+        }
+    }
+
+    pub fn with_usage(mut self, usage: Usage) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+}
+
+/// The `usage` block of a chat completion.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+impl Message {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: "system".to_string(),
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseFormat {
+    pub r#type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<JsonSchemaFormat>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonSchemaFormat {
+    pub name: String,
+    pub schema: serde_json::Value,
+    pub strict: bool,
+}
+
+impl ResponseFormat {
+    pub fn json_object() -> Self {
+        Self {
+            r#type: "json_object".to_string(),
+            json_schema: None,
+        }
+    }
+
+    /// Constrains the completion to `schema`. Strict mode is off because it
+    /// does not allow free-form maps such as `extracted_data`.
+    pub fn json_schema(name: impl Into<String>, schema: serde_json::Value) -> Self {
+        Self {
+            r#type: "json_schema".to_string(),
+            json_schema: Some(JsonSchemaFormat {
+                name: name.into(),
+                schema,
+                strict: false,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionResponse {
+    choices: Option<Vec<Choice>>,
+    usage: Option<Usage>,
+    error: Option<ProviderErrorBody>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+/// The `error` object OpenAI and OpenRouter return instead of `choices`.
+/// OpenRouter sometimes sends it with HTTP 200, so the status alone is not
+/// enough to detect a failure.
+#[derive(Debug, Deserialize)]
+struct ProviderErrorBody {
+    message: String,
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    code: Option<serde_json::Value>,
+}
+
+impl ProviderErrorBody {
+    fn to_error(&self, status: StatusCode, retry_after: Option<Duration>) -> AnalyzerError {
+        let code = self
+            .status_code()
+            .or(Some(status.as_u16()).filter(|s| *s >= 400));
+        if code == Some(429) {
+            return AnalyzerError::RateLimited {
+                message: self.message.clone(),
+                retry_after,
+            };
+        }
+        AnalyzerError::Provider {
+            message: self.message.clone(),
+            error_type: self.error_type.clone(),
+            code,
+        }
+    }
+
+    fn status_code(&self) -> Option<u16> {
+        match &self.code {
+            Some(serde_json::Value::Number(n)) => n.as_u64().map(|n| n as u16),
+            Some(serde_json::Value::String(s)) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Exponential backoff with jitter. A server-supplied `Retry-After` takes
+/// precedence over the computed delay; one longer than `max_delay` ends the
+/// retries, since retrying sooner than asked would only be refused again.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `attempt` (0-based): half the exponential
+    /// backoff plus a random share of the other half.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let half = exponential / 2;
+        half + half.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match operation().await {
+                Err(error) if error.is_retryable() && attempt < self.max_retries => {
+                    let delay = match &error {
+                        AnalyzerError::RateLimited {
+                            retry_after: Some(retry_after),
+                            ..
+                        } if *retry_after > self.max_delay => {
+                            log::warn!(
+                                "{} (asked to wait {:?}, longer than {:?}; not retrying)",
+                                error,
+                                retry_after,
+                                self.max_delay
+                            );
+                            return Err(error);
+                        }
+                        AnalyzerError::RateLimited {
+                            retry_after: Some(retry_after),
+                            ..
+                        } => *retry_after,
+                        _ => self.backoff(attempt),
+                    };
+                    log::warn!(
+                        "{} (retry {}/{} in {:?})",
+                        error,
+                        attempt + 1,
+                        self.max_retries,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A chat-completion backend. Every analyzer talks to models through this
+/// trait, so the transport can be swapped for tests and offline demos.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short human-readable name used in logs and console output.
+    fn name(&self) -> &str;
+
+    /// Whether the backend honours `response_format`. Free OpenRouter models
+    /// often reject it, so the analyzer only sends it when this is true.
+    fn supports_response_format(&self) -> bool {
+        true
+    }
+
+    /// Whether the backend accepts a `json_schema` response format. Only
+    /// OpenAI implements it reliably, so the default is no.
+    fn supports_json_schema(&self) -> bool {
+        false
+    }
+
+    /// Sends the request and returns the first choice with the token usage.
+    async fn complete(&self, request: &LLMRequest) -> Result<Completion>;
+
+    /// Like `complete`, but streams the answer and calls `on_progress` as it
+    /// arrives. Backends that cannot stream report once, at the end.
+    async fn complete_streaming(
+        &self,
+        request: &LLMRequest,
+        on_progress: &(dyn Fn(StreamProgress) + Send + Sync),
+    ) -> Result<Completion> {
+        let completion = self.complete(request).await?;
+        on_progress(StreamProgress {
+            chunks: 1,
+            bytes: completion.content.len(),
+            finished: true,
+        });
+        Ok(completion)
+    }
+}
+
+/// OpenAI's hosted chat completions API.
+pub struct OpenAiProvider {
+    client: Client,
+    api_key: String,
+    url: String,
+    retry_policy: RetryPolicy,
+}
+
+impl OpenAiProvider {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: Client::new(),
+            api_key,
+            url: OPENAI_CHAT_URL.to_string(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sends requests to `url` instead of the public chat completions endpoint.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    fn post(&self) -> reqwest::RequestBuilder {
+        self.client
+            .post(&self.url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        "OpenAI"
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        true
+    }
+
+    async fn complete(&self, request: &LLMRequest) -> Result<Completion> {
+        self.retry_policy
+            .run(|| send_chat_request(self.post(), request))
+            .await
+    }
+
+    async fn complete_streaming(
+        &self,
+        request: &LLMRequest,
+        on_progress: &(dyn Fn(StreamProgress) + Send + Sync),
+    ) -> Result<Completion> {
+        self.retry_policy
+            .run(|| send_streaming_request(self.post(), request, on_progress))
+            .await
+    }
+}
+
+/// OpenRouter's OpenAI-compatible endpoint, used for the free model chain.
+pub struct OpenRouterProvider {
+    client: Client,
+    api_key: String,
+    url: String,
+    retry_policy: RetryPolicy,
+}
+
+impl OpenRouterProvider {
+    pub fn new(api_key: String) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+
+        Self {
+            client,
+            api_key,
+            url: OPENROUTER_CHAT_URL.to_string(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sends requests to `url` instead of OpenRouter's endpoint.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    fn post(&self) -> reqwest::RequestBuilder {
+        self.client
+            .post(&self.url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .header("HTTP-Referer", "https://github.com")
+            .header("X-Title", "Financial Document POC")
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenRouterProvider {
+    fn name(&self) -> &str {
+        "OpenRouter"
+    }
+
+    fn supports_response_format(&self) -> bool {
+        false
+    }
+
+    async fn complete(&self, request: &LLMRequest) -> Result<Completion> {
+        self.retry_policy
+            .run(|| send_chat_request(self.post(), request))
+            .await
+    }
+
+    // The client's 30 second timeout covers the whole response, so streams
+    // replace it with their own idle timeout.
+    async fn complete_streaming(
+        &self,
+        request: &LLMRequest,
+        on_progress: &(dyn Fn(StreamProgress) + Send + Sync),
+    ) -> Result<Completion> {
+        self.retry_policy
+            .run(|| send_streaming_request(self.post(), request, on_progress))
+            .await
+    }
+}
+
+/// Any OpenAI-compatible server on the local network, such as llama.cpp's
+/// `llama-server`, Ollama, vLLM or LM Studio. Nothing leaves the machine, no
+/// key is sent unless one is configured, and prices default to zero.
+///
+/// Some of these servers reject `response_format`; the request is then sent
+/// again without it and the format is not offered again. Servers that ignore
+/// it are handled by the analyzer, which digs the JSON out of the answer.
+pub struct LocalProvider {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    retry_policy: RetryPolicy,
+    rejects_response_format: AtomicBool,
+}
+
+impl LocalProvider {
+    /// `base_url` is the part before `/chat/completions`, usually ending in
+    /// `/v1`. An address without a path, such as `http://localhost:11434`,
+    /// gets `/v1` appended.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into();
+        let base_url = base_url.trim_end_matches('/');
+        let address = base_url
+            .split_once("://")
+            .map_or(base_url, |(_, address)| address);
+        let base_url = if address.contains('/') {
+            base_url.to_string()
+        } else {
+            format!("{}/v1", base_url)
+        };
+
+        Self {
+            client: Client::new(),
+            base_url,
+            api_key: None,
+            // A local server that is down stays down; retrying only delays
+            // the error.
+            retry_policy: RetryPolicy::none(),
+            rejects_response_format: AtomicBool::new(false),
+        }
+    }
+
+    /// Sends `api_key` as a bearer token, for servers started with one.
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Ids of the models the server offers, from `GET /v1/models`.
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        let response = self
+            .authorize(self.client.get(format!("{}/models", self.base_url)))
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            // Errors look the same as for chat completions, which always
+            // fail on an unsuccessful status.
+            parse_completion(status, None, &body)?;
+        }
+        let list: ModelList =
+            serde_json::from_str(&body).map_err(|e| AnalyzerError::from_parse(&body, e))?;
+        Ok(list.data.into_iter().map(|model| model.id).collect())
+    }
+
+    fn authorize(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(key) => builder.header("Authorization", format!("Bearer {}", key)),
+            None => builder,
+        }
+    }
+
+    fn post(&self) -> reqwest::RequestBuilder {
+        self.authorize(
+            self.client
+                .post(format!("{}/chat/completions", self.base_url))
+                .header("Content-Type", "application/json"),
+        )
+    }
+
+    /// Runs `send` and, if the server refuses the response format, runs it
+    /// once more without one.
+    async fn send_with_fallback<F, Fut>(&self, request: &LLMRequest, send: F) -> Result<Completion>
+    where
+        F: Fn(LLMRequest) -> Fut,
+        Fut: Future<Output = Result<Completion>>,
+    {
+        let mut request = request.clone();
+        if self.rejects_response_format.load(Ordering::Relaxed) {
+            request.response_format = None;
+        }
+        if request.response_format.is_none() {
+            return send(request).await;
+        }
+        match send(request.clone()).await {
+            Err(e) if is_response_format_rejection(&e) => {
+                log::warn!(
+                    "{} does not accept response_format ({}); sending without it",
+                    self.base_url,
+                    e
+                );
+                self.rejects_response_format.store(true, Ordering::Relaxed);
+                request.response_format = None;
+                send(request).await
+            }
+            result => result,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for LocalProvider {
+    fn name(&self) -> &str {
+        "Local"
+    }
+
+    fn supports_response_format(&self) -> bool {
+        !self.rejects_response_format.load(Ordering::Relaxed)
+    }
+
+    async fn complete(&self, request: &LLMRequest) -> Result<Completion> {
+        self.send_with_fallback(request, |request| async move {
+            self.retry_policy
+                .run(|| send_chat_request(self.post(), &request))
+                .await
+        })
+        .await
+    }
+
+    async fn complete_streaming(
+        &self,
+        request: &LLMRequest,
+        on_progress: &(dyn Fn(StreamProgress) + Send + Sync),
+    ) -> Result<Completion> {
+        self.send_with_fallback(request, |request| async move {
+            self.retry_policy
+                .run(|| send_streaming_request(self.post(), &request, on_progress))
+                .await
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+fn is_response_format_rejection(error: &AnalyzerError) -> bool {
+    match error {
+        AnalyzerError::Provider {
+            message,
+            code: Some(400 | 422),
+            ..
+        } => {
+            let message = message.to_lowercase();
+            message.contains("response_format") || message.contains("response format")
+        }
+        _ => false,
+    }
+}
+
+async fn send_chat_request(
+    builder: reqwest::RequestBuilder,
+    request: &LLMRequest,
+) -> Result<Completion> {
+    let response = builder.json(request).send().await?;
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    let body = response.text().await?;
+
+    parse_completion(status, retry_after, &body)
+}
+
+/// The request body with streaming switched on.
+#[derive(Serialize)]
+struct StreamingRequest<'a> {
+    #[serde(flatten)]
+    request: &'a LLMRequest,
+    stream: bool,
+    stream_options: StreamOptions,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+/// One `data:` event of a streamed chat completion.
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    usage: Option<Usage>,
+    error: Option<ProviderErrorBody>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: StreamDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StreamDelta {
+    content: Option<String>,
+}
+
+async fn send_streaming_request(
+    builder: reqwest::RequestBuilder,
+    request: &LLMRequest,
+    on_progress: &(dyn Fn(StreamProgress) + Send + Sync),
+) -> Result<Completion> {
+    let body = StreamingRequest {
+        request,
+        stream: true,
+        stream_options: StreamOptions {
+            include_usage: true,
+        },
+    };
+    let mut response = builder
+        .timeout(STREAM_TIMEOUT)
+        .header("Accept", "text/event-stream")
+        .json(&body)
+        .send()
+        .await?;
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    if !status.is_success() {
+        let body = response.text().await?;
+        return parse_completion(status, retry_after, &body);
+    }
+
+    let mut parser = SseParser::default();
+    let mut stream = StreamState::default();
+    loop {
+        let bytes = match tokio::time::timeout(STREAM_IDLE_TIMEOUT, response.chunk()).await {
+            Ok(Ok(Some(bytes))) => bytes,
+            Ok(Ok(None)) => break,
+            Ok(Err(e)) => {
+                log::warn!("Stream from {} interrupted: {}", request.model, e);
+                break;
+            }
+            Err(_) => {
+                log::warn!(
+                    "Stream from {} stalled for {:?}",
+                    request.model,
+                    STREAM_IDLE_TIMEOUT
+                );
+                break;
+            }
+        };
+        for data in parser.feed(&bytes) {
+            stream.handle(&data, status, retry_after, on_progress)?;
+        }
+        if stream.done {
+            break;
+        }
+    }
+    stream.finish(parser, status, retry_after, on_progress, &request.model)
+}
+
+/// What has been assembled from a stream so far.
+#[derive(Default)]
+struct StreamState {
+    content: String,
+    usage: Option<Usage>,
+    chunks: usize,
+    finished: bool,
+    done: bool,
+}
+
+impl StreamState {
+    fn handle(
+        &mut self,
+        data: &str,
+        status: StatusCode,
+        retry_after: Option<Duration>,
+        on_progress: &(dyn Fn(StreamProgress) + Send + Sync),
+    ) -> Result<()> {
+        if data.trim() == "[DONE]" {
+            self.done = true;
+            return Ok(());
+        }
+        let chunk: StreamChunk = match serde_json::from_str(data) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                log::debug!("Skipping unreadable stream event ({}): {}", e, data);
+                return Ok(());
+            }
+        };
+        if let Some(error) = chunk.error {
+            return Err(error.to_error(status, retry_after));
+        }
+        if chunk.usage.is_some() {
+            self.usage = chunk.usage;
+        }
+        for choice in chunk.choices.into_iter().take(1) {
+            if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+                self.content.push_str(&content);
+                self.chunks += 1;
+                on_progress(StreamProgress {
+                    chunks: self.chunks,
+                    bytes: self.content.len(),
+                    finished: false,
+                });
+            }
+            self.finished |= choice.finish_reason.is_some();
+        }
+        Ok(())
+    }
+
+    /// Handles an event left in `parser` without a trailing blank line and
+    /// reports the end of the stream.
+    fn finish(
+        mut self,
+        mut parser: SseParser,
+        status: StatusCode,
+        retry_after: Option<Duration>,
+        on_progress: &(dyn Fn(StreamProgress) + Send + Sync),
+        model: &str,
+    ) -> Result<Completion> {
+        if !self.done {
+            if let Some(data) = parser.finish() {
+                self.handle(&data, status, retry_after, on_progress)?;
+            }
+        }
+        on_progress(StreamProgress {
+            chunks: self.chunks,
+            bytes: self.content.len(),
+            finished: true,
+        });
+        self.into_completion(model)
+    }
+
+    // A stream that ends without `[DONE]` or a finish reason was cut off;
+    // keep the complete part of the JSON received so far.
+    fn into_completion(self, model: &str) -> Result<Completion> {
+        if self.content.trim().is_empty() {
+            return Err(AnalyzerError::EmptyCompletion);
+        }
+        if self.done || self.finished {
+            return Ok(Completion {
+                content: self.content,
+                usage: self.usage,
+                truncated: false,
+            });
+        }
+
+        let content =
+            repair_truncated_json(&self.content).ok_or_else(|| AnalyzerError::Provider {
+                message: format!(
+                    "stream from {} was cut off after {} bytes and nothing could be recovered",
+                    model,
+                    self.content.len()
+                ),
+                error_type: None,
+                code: None,
+            })?;
+        log::warn!(
+            "Stream from {} was cut off after {} bytes; recovered {} bytes of JSON",
+            model,
+            self.content.len(),
+            content.len()
+        );
+        Ok(Completion {
+            content,
+            usage: self.usage,
+            truncated: true,
+        })
+    }
+}
+
+/// Turns an HTTP response into the first choice's content or a typed error.
+fn parse_completion(
+    status: StatusCode,
+    retry_after: Option<Duration>,
+    body: &str,
+) -> Result<Completion> {
+    let completion = serde_json::from_str::<ChatCompletionResponse>(body);
+
+    if let Ok(ChatCompletionResponse {
+        error: Some(error), ..
+    }) = &completion
+    {
+        return Err(error.to_error(status, retry_after));
+    }
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(AnalyzerError::RateLimited {
+            message: body.to_string(),
+            retry_after,
+        });
+    }
+    if !status.is_success() {
+        return Err(AnalyzerError::Provider {
+            message: body.to_string(),
+            error_type: None,
+            code: Some(status.as_u16()),
+        });
+    }
+
+    let completion = completion.map_err(|e| AnalyzerError::Provider {
+        message: format!("unreadable response: {}", e),
+        error_type: None,
+        code: None,
+    })?;
+
+    let content = completion
+        .choices
+        .and_then(|choices| choices.into_iter().next())
+        .map(|choice| choice.message.content)
+        .filter(|content| !content.trim().is_empty())
+        .ok_or(AnalyzerError::EmptyCompletion)?;
+    Ok(Completion {
+        content,
+        usage: completion.usage,
+        truncated: false,
+    })
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = httpdate::parse_http_date(value.trim()).ok()?;
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// In-process provider that replays scripted responses in order and records
+/// every request it receives. Never touches the network.
+#[derive(Default)]
+pub struct MockProvider {
+    responses: Mutex<VecDeque<Scripted>>,
+    requests: Mutex<Vec<LLMRequest>>,
+}
+
+enum Scripted {
+    Reply(Result<Completion>),
+    /// The body of a streamed answer, as `data:` events.
+    Stream(String),
+}
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_responses<I, S>(responses: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let provider = Self::new();
+        for response in responses {
+            provider.push_response(response);
+        }
+        provider
+    }
+
+    pub fn push_response(&self, content: impl Into<String>) {
+        self.push_completion(Completion::new(content));
+    }
+
+    pub fn push_completion(&self, completion: Completion) {
+        self.responses
+            .lock()
+            .unwrap()
+            .push_back(Scripted::Reply(Ok(completion)));
+    }
+
+    /// Scripts a streamed answer: `events` is the raw event stream body,
+    /// assembled like a real one, so a body that stops without `[DONE]`
+    /// plays a stream that was cut off.
+    pub fn push_stream(&self, events: impl Into<String>) {
+        self.responses
+            .lock()
+            .unwrap()
+            .push_back(Scripted::Stream(events.into()));
+    }
+
+    pub fn push_error(&self, message: impl Into<String>) {
+        self.push_failure(AnalyzerError::Provider {
+            message: message.into(),
+            error_type: None,
+            code: None,
+        });
+    }
+
+    pub fn push_failure(&self, error: AnalyzerError) {
+        self.responses
+            .lock()
+            .unwrap()
+            .push_back(Scripted::Reply(Err(error)));
+    }
+
+    /// Requests received so far, in call order.
+    pub fn requests(&self) -> Vec<LLMRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn name(&self) -> &str {
+        "Mock"
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        true
+    }
+
+    async fn complete(&self, request: &LLMRequest) -> Result<Completion> {
+        self.complete_streaming(request, &|_| {}).await
+    }
+
+    async fn complete_streaming(
+        &self,
+        request: &LLMRequest,
+        on_progress: &(dyn Fn(StreamProgress) + Send + Sync),
+    ) -> Result<Completion> {
+        self.requests.lock().unwrap().push(request.clone());
+
+        let scripted = self.responses.lock().unwrap().pop_front();
+        match scripted {
+            Some(Scripted::Reply(Ok(completion))) => {
+                on_progress(StreamProgress {
+                    chunks: 1,
+                    bytes: completion.content.len(),
+                    finished: true,
+                });
+                Ok(completion)
+            }
+            Some(Scripted::Reply(Err(error))) => Err(error),
+            Some(Scripted::Stream(events)) => {
+                let mut parser = SseParser::default();
+                let mut stream = StreamState::default();
+                for data in parser.feed(events.as_bytes()) {
+                    stream.handle(&data, StatusCode::OK, None, on_progress)?;
+                    if stream.done {
+                        break;
+                    }
+                }
+                stream.finish(parser, StatusCode::OK, None, on_progress, &request.model)
+            }
+            None => Err(AnalyzerError::Provider {
+                message: "MockProvider has no scripted responses left".to_string(),
+                error_type: None,
+                code: None,
+            }),
+        }
+    }
+}
+
+/// A completion captured from a real model, replayed by `RecordedProvider`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub model: String,
+    /// Text the prompt must contain for this recording to be used, such as a
+    /// document's invoice number. Pick something that is not redacted.
+    #[serde(rename = "match")]
+    pub matches: String,
+    /// The completion, either as a string or as the JSON the model returned.
+    pub response: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+/// Offline provider that answers from recorded completions, so evaluations
+/// and tests are reproducible without network access or API keys.
+#[derive(Debug, Default)]
+pub struct RecordedProvider {
+    recordings: Vec<Recording>,
+}
+
+impl RecordedProvider {
+    pub fn new(recordings: Vec<Recording>) -> Self {
+        Self { recordings }
+    }
+
+    /// Loads every `*.json` file in `dir`; each holds one recording or an
+    /// array of them.
+    pub fn load_dir(dir: &Path) -> std::io::Result<Self> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<_>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+        paths.sort();
+
+        let mut recordings = Vec::new();
+        for path in paths {
+            let invalid = |e: serde_json::Error| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            };
+            let value: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(&path)?).map_err(invalid)?;
+            if value.is_array() {
+                recordings
+                    .extend(serde_json::from_value::<Vec<Recording>>(value).map_err(invalid)?);
+            } else {
+                recordings.push(serde_json::from_value(value).map_err(invalid)?);
+            }
+        }
+        Ok(Self::new(recordings))
+    }
+
+    pub fn len(&self) -> usize {
+        self.recordings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recordings.is_empty()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RecordedProvider {
+    fn name(&self) -> &str {
+        "Recorded"
+    }
+
+    async fn complete(&self, request: &LLMRequest) -> Result<Completion> {
+        let prompt = request
+            .messages
+            .last()
+            .map(|message| message.content.as_str())
+            .unwrap_or_default();
+        let recording = self
+            .recordings
+            .iter()
+            .find(|r| r.model == request.model && prompt.contains(&r.matches))
+            .ok_or_else(|| AnalyzerError::Provider {
+                message: format!("no recording for {} matches this prompt", request.model),
+                error_type: None,
+                code: None,
+            })?;
+
+        let content = match &recording.response {
+            serde_json::Value::String(text) => text.clone(),
+            json => json.to_string(),
+        };
+        Ok(Completion {
+            content,
+            usage: recording.usage,
+            truncated: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn request(model: &str) -> LLMRequest {
+        LLMRequest {
+            model: model.to_string(),
+            messages: vec![Message::user("hello")],
+            temperature: 0.1,
+            max_tokens: 10,
+            response_format: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_replays_in_order() {
+        let mock = MockProvider::with_responses(["first", "second"]);
+        mock.push_error("boom");
+
+        assert_eq!(mock.complete(&request("a")).await.unwrap().content, "first");
+        assert_eq!(
+            mock.complete(&request("b")).await.unwrap().content,
+            "second"
+        );
+        assert!(mock.complete(&request("c")).await.is_err());
+        assert!(mock.complete(&request("d")).await.is_err());
+
+        let models: Vec<_> = mock.requests().into_iter().map(|r| r.model).collect();
+        assert_eq!(models, vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_response_format_omitted_when_none() {
+        let json = serde_json::to_value(request("m")).unwrap();
+        assert!(json.get("response_format").is_none());
+    }
+
+    #[test]
+    fn test_parse_completion_classifies_errors() {
+        let ok = r#"{"choices": [{"message": {"role": "assistant", "content": "{}"}}],
+                     "usage": {"prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15}}"#;
+        assert_eq!(
+            parse_completion(StatusCode::OK, None, ok).unwrap(),
+            Completion::new("{}").with_usage(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 3
+            })
+        );
+
+        let empty = parse_completion(StatusCode::OK, None, r#"{"choices": []}"#);
+        assert!(matches!(empty, Err(AnalyzerError::EmptyCompletion)));
+
+        // OpenRouter reports upstream rate limits inside a 200 response.
+        let limited = parse_completion(
+            StatusCode::OK,
+            None,
+            r#"{"error": {"message": "slow down", "code": 429}}"#,
+        );
+        assert!(matches!(limited, Err(AnalyzerError::RateLimited { .. })));
+
+        let provider = parse_completion(
+            StatusCode::BAD_REQUEST,
+            None,
+            r#"{"error": {"message": "bad model", "type": "invalid_request_error"}}"#,
+        );
+        match provider {
+            Err(e @ AnalyzerError::Provider { .. }) => assert!(!e.is_retryable()),
+            other => panic!("expected provider error, got {:?}", other),
+        }
+
+        let unavailable = parse_completion(StatusCode::SERVICE_UNAVAILABLE, None, "oops");
+        assert!(unavailable.unwrap_err().is_retryable());
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("7"), Some(Duration::from_secs(7)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_honours_retry_after() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_secs(60),
+            max_delay: Duration::from_secs(60),
+        };
+        let attempts = Mutex::new(0);
+
+        let started = std::time::Instant::now();
+        let result = policy
+            .run(|| {
+                let attempt = {
+                    let mut attempts = attempts.lock().unwrap();
+                    *attempts += 1;
+                    *attempts
+                };
+                async move {
+                    if attempt == 1 {
+                        Err(AnalyzerError::RateLimited {
+                            message: "slow down".to_string(),
+                            retry_after: Some(Duration::from_millis(10)),
+                        })
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), 2);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // A wait longer than the policy allows is not sat out.
+        let attempts = Mutex::new(0);
+        let started = std::time::Instant::now();
+        let result: Result<()> = RetryPolicy::default()
+            .run(|| {
+                *attempts.lock().unwrap() += 1;
+                async {
+                    Err(AnalyzerError::RateLimited {
+                        message: "come back tomorrow".to_string(),
+                        retry_after: Some(Duration::from_secs(86_400)),
+                    })
+                }
+            })
+            .await;
+
+        assert!(matches!(result, Err(AnalyzerError::RateLimited { .. })));
+        assert_eq!(*attempts.lock().unwrap(), 1);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_stops_on_permanent_errors() {
+        let attempts = Mutex::new(0);
+        let result: Result<()> = RetryPolicy::default()
+            .run(|| {
+                *attempts.lock().unwrap() += 1;
+                async { Err(AnalyzerError::EmptyCompletion) }
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(*attempts.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        let first = policy.backoff(0);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        assert!(policy.backoff(8) <= Duration::from_secs(1));
+        assert!(policy.backoff(8) >= Duration::from_millis(500));
+    }
+
+    /// Answers one request per entry of `responses`, each written in the
+    /// given parts and followed by closing the connection. Yields the
+    /// requests received.
+    async fn serve(responses: Vec<Vec<String>>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for parts in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                requests.push(read_request(&mut socket).await);
+                for part in parts {
+                    socket.write_all(part.as_bytes()).await.unwrap();
+                    socket.flush().await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            }
+            requests
+        });
+        (address, server)
+    }
+
+    async fn serve_stream(parts: Vec<String>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let mut response = vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        ];
+        response.extend(parts);
+        let (address, server) = serve(vec![response]).await;
+        (format!("{}/v1/chat/completions", address), server)
+    }
+
+    fn json_response(status: &str, body: serde_json::Value) -> Vec<String> {
+        let body = body.to_string();
+        vec![format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )]
+    }
+
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+        let mut received = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let n = socket.read(&mut buffer).await.unwrap();
+            received.extend_from_slice(&buffer[..n]);
+            let text = String::from_utf8_lossy(&received);
+            let Some(end) = text.find("\r\n\r\n") else {
+                assert!(n > 0, "connection closed mid-request");
+                continue;
+            };
+            let length = text[..end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if received.len() >= end + 4 + length || n == 0 {
+                return text.into_owned();
+            }
+        }
+    }
+
+    fn delta(content: &str) -> String {
+        format!(
+            "data: {}\n\n",
+            serde_json::json!({"choices": [{"delta": {"content": content}}]})
+        )
+    }
+
+    fn provider(url: String) -> OpenAiProvider {
+        OpenAiProvider::new("test-key".to_string()).with_url(url)
+    }
+
+    #[tokio::test]
+    async fn test_streamed_completion_is_assembled() {
+        let mut parts = vec![": keep-alive\n\n".to_string()];
+        let events = delta(r#"{"amount": "#) + &delta("12.50}");
+        // Network chunks need not line up with events.
+        let (head, tail) = events.split_at(20);
+        parts.push(head.to_string());
+        parts.push(tail.to_string());
+        parts.push(
+            "data: {\"choices\": [], \"usage\": {\"prompt_tokens\": 7, \"completion_tokens\": 4}}\n\n"
+                .to_string(),
+        );
+        parts.push("data: [DONE]\n\n".to_string());
+        let (url, server) = serve_stream(parts).await;
+
+        let progress = Mutex::new(Vec::new());
+        let completion = provider(url)
+            .complete_streaming(&request("gpt-4o-mini"), &|p| {
+                progress.lock().unwrap().push(p)
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(completion.content, r#"{"amount": 12.50}"#);
+        assert!(!completion.truncated);
+        assert_eq!(
+            completion.usage,
+            Some(Usage {
+                prompt_tokens: 7,
+                completion_tokens: 4
+            })
+        );
+        let progress = progress.into_inner().unwrap();
+        assert_eq!(progress.first().map(|p| p.chunks), Some(1));
+        assert_eq!(
+            progress.last(),
+            Some(&StreamProgress {
+                chunks: 2,
+                bytes: completion.content.len(),
+                finished: true
+            })
+        );
+        let sent = server.await.unwrap();
+        assert!(sent[0].contains(r#""stream":true"#), "{}", sent[0]);
+    }
+
+    #[tokio::test]
+    async fn test_cut_off_stream_keeps_complete_fields() {
+        let parts = vec![
+            delta(r#"{"vendor": "Acme", "#),
+            delta(r#""total": "2,7"#),
+            // The connection drops halfway through an event.
+            r#"data: {"choices": [{"delta": {"content": "5"#.to_string(),
+        ];
+        let (url, _server) = serve_stream(parts).await;
+
+        let completion = provider(url)
+            .complete_streaming(&request("gpt-4o-mini"), &|_| {})
+            .await
+            .unwrap();
+
+        assert!(completion.truncated);
+        assert_eq!(completion.content, r#"{"vendor": "Acme"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_local_provider_discovers_models_without_auth() {
+        let (address, server) = serve(vec![json_response(
+            "200 OK",
+            serde_json::json!({"object": "list", "data": [
+                {"id": "qwen2.5-7b-instruct", "object": "model"},
+                {"id": "llama3.1:8b", "object": "model"}
+            ]}),
+        )])
+        .await;
+
+        let provider = LocalProvider::new(format!("{}/", address));
+        assert_eq!(provider.base_url(), format!("{}/v1", address));
+        let models = provider.list_models().await.unwrap();
+
+        assert_eq!(models, ["qwen2.5-7b-instruct", "llama3.1:8b"]);
+        let sent = server.await.unwrap();
+        assert!(sent[0].starts_with("GET /v1/models "), "{}", sent[0]);
+        assert!(!sent[0].to_lowercase().contains("authorization"));
+    }
+
+    #[tokio::test]
+    async fn test_local_provider_drops_rejected_response_format() {
+        let completion = serde_json::json!({
+            "choices": [{"message": {"role": "assistant", "content": "{}"}}]
+        });
+        let (address, server) = serve(vec![
+            json_response(
+                "400 Bad Request",
+                serde_json::json!({"error": {
+                    "message": "'response_format.type' must be 'json_schema' or 'text'",
+                    "code": 400
+                }}),
+            ),
+            json_response("200 OK", completion.clone()),
+            json_response("200 OK", completion),
+        ])
+        .await;
+        let provider = LocalProvider::new(format!("{}/v1", address));
+        let mut request = request("local-model");
+        request.response_format = Some(ResponseFormat::json_object());
+
+        assert_eq!(provider.complete(&request).await.unwrap().content, "{}");
+        assert!(!provider.supports_response_format());
+        provider.complete(&request).await.unwrap();
+
+        let sent = server.await.unwrap();
+        assert!(sent[0].contains("response_format"));
+        assert!(!sent[1].contains("response_format"));
+        // The format is not offered again once refused.
+        assert!(!sent[2].contains("response_format"));
+    }
+}
@@ -1,256 +1,528 @@
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::time::Duration;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct FinancialDocument {
-    document_type: String,
-    confidence: f32,
-    extracted_data: HashMap<String, String>,
-    validation_errors: Vec<String>,
-    suggested_categories: Vec<String>,
-    document_insights: Vec<String>, // New field for AI insights
-}
-
-#[derive(Debug, Serialize)]
-struct OpenRouterRequest {
-    model: String,
-    messages: Vec<Message>,
-    temperature: f32,
-    max_tokens: u32,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Message {
-    role: String,
-    content: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct OpenRouterResponse {
-    choices: Option<Vec<Choice>>,
-    error: Option<OpenRouterError>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Choice {
-    message: Message,
+mod cli;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use cli::{
+    collect_inputs, Cli, Command, CommandArgs, EvalArgs, InputDocument, OutputFormat,
+    ProviderKind, EXIT_OK, EXIT_PROCESSING_ERROR, EXIT_VALIDATION_FAILED,
+};
+use financial_llm_poc::batch::{run_batch, BatchOptions};
+use financial_llm_poc::cache::ResponseCache;
+use financial_llm_poc::cost::{ModelPrice, PriceTable};
+use financial_llm_poc::document_types::{DocumentType, FinancialDocument, ValidationResult};
+use financial_llm_poc::einvoice;
+use financial_llm_poc::error::AnalyzerError;
+use financial_llm_poc::evaluation::{load_corpus, EvalReport, Evaluator};
+use financial_llm_poc::financial_analyzer::{AnalysisPrompt, FinancialAnalyzer};
+use financial_llm_poc::importers::{self, CsvMapping};
+use financial_llm_poc::llm_provider::{
+    LlmProvider, LocalProvider, OpenRouterProvider, RecordedProvider, DEFAULT_LOCAL_URL,
+};
+use financial_llm_poc::prompts::PromptLibrary;
+use financial_llm_poc::rule_extractor::RuleBasedExtractor;
+use financial_llm_poc::validator::DocumentValidator;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+const OPENROUTER_MODELS: [&str; 2] = [
+    "meta-llama/llama-3.2-3b-instruct:free", // Primary - we know this works
+    "google/gemini-2.0-flash-exp:free",      // Backup
+];
+
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    dotenv::dotenv().ok();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let cli = Cli::parse();
+
+    match cli.command.unwrap_or(Command::Demo) {
+        Command::Analyze(args) => run(Mode::Analyze, &args).await,
+        Command::Validate(args) => run(Mode::Validate, &args).await,
+        Command::Convert(args) => run(Mode::Convert, &args).await,
+        Command::Eval(args) => run_eval(&args).await,
+        Command::Demo => {
+            run_demo().await?;
+            Ok(ExitCode::SUCCESS)
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct OpenRouterError {
-    message: String,
-    #[serde(rename = "type")]
-    error_type: String,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Analyze,
+    Validate,
+    Convert,
 }
 
-struct FinancialAnalyzer {
-    api_key: String,
+/// Either a model-backed analyzer or the offline rule engine.
+enum Engine {
+    Llm(Box<FinancialAnalyzer>),
+    Rules(RuleBasedExtractor),
 }
 
-impl FinancialAnalyzer {
-    fn new(api_key: String) -> Self {
-        Self { api_key }
+impl Engine {
+    async fn from_args(args: &CommandArgs) -> Result<Self> {
+        let openrouter_key = args
+            .api_key
+            .clone()
+            .or_else(|| std::env::var("OPENROUTER_API_KEY").ok());
+
+        let provider = match args.provider {
+            ProviderKind::Auto => match &openrouter_key {
+                Some(key) if is_openrouter_key(key) => ProviderKind::Openrouter,
+                _ => ProviderKind::Rules,
+            },
+            other => other,
+        };
+
+        // Models served locally cost nothing unless the price table says so.
+        let mut local_models = Vec::new();
+        let analyzer = match provider {
+            ProviderKind::Rules => return Ok(Engine::Rules(RuleBasedExtractor::new())),
+            ProviderKind::Openrouter => {
+                let key = openrouter_key.context("OPENROUTER_API_KEY or --api-key is required")?;
+                let models = if args.models.is_empty() {
+                    OPENROUTER_MODELS.iter().map(|m| m.to_string()).collect()
+                } else {
+                    args.models.clone()
+                };
+                FinancialAnalyzer::with_provider(Arc::new(OpenRouterProvider::new(key)))
+                    .with_models(models)
+                    .with_prompt(AnalysisPrompt::Smart)
+            }
+            ProviderKind::Openai => {
+                let key = args
+                    .api_key
+                    .clone()
+                    .or_else(|| std::env::var("OPENAI_API_KEY").ok())
+                    .context("OPENAI_API_KEY or --api-key is required")?;
+                let analyzer = FinancialAnalyzer::new(key);
+                if args.models.is_empty() {
+                    analyzer
+                } else {
+                    analyzer.with_models(args.models.clone())
+                }
+            }
+            ProviderKind::Local => {
+                let provider = local_provider(args.base_url.as_deref(), args.api_key.as_deref());
+                local_models = if args.models.is_empty() {
+                    // Switching between local models means reloading them,
+                    // so only the first is used.
+                    vec![discover_models(&provider).await?.swap_remove(0)]
+                } else {
+                    args.models.clone()
+                };
+                FinancialAnalyzer::with_provider(Arc::new(provider))
+                    .with_models(local_models.clone())
+            }
+            ProviderKind::Auto => unreachable!("auto is resolved above"),
+        };
+
+        let analyzer = if args.llm_validation {
+            analyzer.with_llm_validation()
+        } else {
+            analyzer
+        };
+        let analyzer = if args.no_redact {
+            analyzer.without_redaction()
+        } else {
+            analyzer
+        };
+        let analyzer = match &args.prompts_dir {
+            Some(dir) => analyzer.with_prompts(PromptLibrary::load_dir(dir)?),
+            None => analyzer,
+        };
+        let mut prices = match &args.prices {
+            Some(path) => PriceTable::load(path)
+                .with_context(|| format!("Failed to load prices {}", path.display()))?,
+            None => PriceTable::default(),
+        };
+        for model in local_models {
+            if prices.price(&model).is_none() {
+                let free = ModelPrice::per_million(Decimal::ZERO, Decimal::ZERO);
+                prices = prices.with_price(model, free);
+            }
+        }
+        let analyzer = analyzer.with_price_table(prices);
+        let analyzer = match args.budget {
+            Some(budget) => analyzer.with_budget(budget),
+            None => analyzer,
+        };
+        let analyzer = if args.stream {
+            analyzer.with_streaming(|progress| {
+                eprint!("\r📡 {} bytes received", progress.bytes);
+                if progress.finished {
+                    eprintln!();
+                }
+            })
+        } else {
+            analyzer
+        };
+        let analyzer = if args.no_cache {
+            analyzer
+        } else {
+            let dir = args
+                .cache_dir
+                .clone()
+                .unwrap_or_else(ResponseCache::default_dir);
+            let cache = ResponseCache::new(dir);
+            analyzer.with_cache(if args.refresh {
+                cache.refreshing()
+            } else {
+                cache
+            })
+        };
+        Ok(Engine::Llm(Box::new(if args.no_fallback {
+            analyzer
+        } else {
+            analyzer.with_rule_fallback()
+        })))
     }
 
-    fn analyze_document(&self, text: &str) -> Result<FinancialDocument> {
-        let prompt = self.build_smart_analysis_prompt(text);
-
-        let models = vec![
-            "meta-llama/llama-3.2-3b-instruct:free", // Primary - we know this works
-            "google/gemini-2.0-flash-exp:free",      // Backup
-        ];
-
-        for model in models {
-            println!("🔄 Trying model: {}", model);
-
-            let request = OpenRouterRequest {
-                model: model.to_string(),
-                messages: vec![
-                    Message {
-                        role: "system".to_string(),
-                        content: "You are a financial document analysis expert. Analyze the document type and extract relevant fields accordingly. Return ONLY valid JSON.".to_string(),
-                    },
-                    Message {
-                        role: "user".to_string(),
-                        content: prompt.clone(),
-                    },
-                ],
-                temperature: 0.1,
-                max_tokens: 2000,
-            };
+    async fn analyze(&self, text: &str) -> Result<FinancialDocument> {
+        match self {
+            Engine::Llm(analyzer) => Ok(analyzer.analyze_document(text).await?),
+            Engine::Rules(extractor) => Ok(extractor.extract(text)),
+        }
+    }
 
-            let request_json = serde_json::to_string(&request)?;
+    async fn validate(&self, document: &FinancialDocument) -> Result<ValidationResult> {
+        match self {
+            Engine::Llm(analyzer) => Ok(analyzer.validate_document(document).await?),
+            Engine::Rules(_) => Ok(DocumentValidator::new().validate(document)),
+        }
+    }
 
-            let response = ureq::post("https://openrouter.ai/api/v1/chat/completions")
-                .set("Authorization", &format!("Bearer {}", self.api_key))
-                .set("Content-Type", "application/json")
-                .set("HTTP-Referer", "https://github.com")
-                .set("X-Title", "Financial Document POC")
-                .timeout(Duration::from_secs(30))
-                .send_string(&request_json);
+    async fn convert(&self, text: &str) -> Result<serde_json::Value> {
+        match self {
+            Engine::Llm(analyzer) => Ok(analyzer.convert_to_json(text).await?),
+            Engine::Rules(extractor) => Ok(serde_json::to_value(extractor.extract_fields(text))?),
+        }
+    }
+}
 
-            match response {
-                Ok(resp) => {
-                    let status = resp.status();
+/// One line of `json`/`jsonl` output.
+#[derive(Serialize)]
+struct Record {
+    source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    analysis: Option<FinancialDocument>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    validation: Option<ValidationResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json: Option<serde_json::Value>,
+    /// Where the invoice was written as UBL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ubl: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
 
-                    if status == 200 {
-                        let response_text = resp.into_string()?;
-                        let api_response: OpenRouterResponse = serde_json::from_str(&response_text)?;
+async fn process(
+    engine: &Engine,
+    mode: Mode,
+    mapping: &CsvMapping,
+    ubl_dir: Option<&Path>,
+    document: &InputDocument,
+) -> Record {
+    let mut record = Record {
+        source: document.source.clone(),
+        analysis: None,
+        validation: None,
+        json: None,
+        ubl: None,
+        error: None,
+    };
 
-                        if let Some(error) = api_response.error {
-                            println!("❌ Model error: {}", error.message);
-                            continue;
-                        }
+    let content = match &document.content {
+        Ok(content) => content,
+        Err(e) => {
+            record.error = Some(format!("{:#}", e));
+            return record;
+        }
+    };
 
-                        if let Some(choices) = api_response.choices {
-                            if let Some(choice) = choices.into_iter().next() {
-                                let analysis_json = &choice.message.content;
-                                println!("✅ Received valid response from {}", model);
-
-                                let clean_json = analysis_json
-                                    .trim()
-                                    .trim_start_matches("```json")
-                                    .trim_start_matches("```")
-                                    .trim_end_matches("```")
-                                    .trim();
-
-                                match serde_json::from_str::<FinancialDocument>(clean_json) {
-                                    Ok(mut analysis) => {
-                                        // Post-process the analysis for better insights
-                                        self.enhance_analysis(&mut analysis);
-                                        println!("🎯 Successfully analyzed with {}", model);
-                                        return Ok(analysis);
-                                    }
-                                    Err(e) => {
-                                        println!("❌ JSON parse error: {}", e);
-                                        if let Some(fixed_json) = extract_json_from_text(clean_json) {
-                                            match serde_json::from_str::<FinancialDocument>(&fixed_json) {
-                                                Ok(mut analysis) => {
-                                                    self.enhance_analysis(&mut analysis);
-                                                    return Ok(analysis);
-                                                }
-                                                Err(_) => continue,
-                                            }
-                                        } else {
-                                            continue;
-                                        }
-                                    }
-                                }
+    // E-invoices and bank exports are read as they are instead of by the engine.
+    let imported = einvoice::import_invoice(&content.text)
+        .or_else(|| importers::import_statement(&content.text, mapping))
+        .map(|statement| statement.map_err(anyhow::Error::from));
+    let result = match mode {
+        Mode::Convert => match imported {
+            Some(statement) => statement.and_then(|s| Ok(serde_json::to_value(s)?)),
+            None => engine.convert(&content.text).await,
+        }
+        .map(|json| {
+            record.json = Some(json);
+        }),
+        Mode::Analyze | Mode::Validate => {
+            let analysis = match imported {
+                Some(statement) => statement,
+                None => engine.analyze(&content.text).await,
+            };
+            match analysis {
+                Ok(mut analysis) => {
+                    if let Some(pages) = &content.pages {
+                        pages.annotate(&mut analysis);
+                    }
+                    if let Some(dir) = ubl_dir {
+                        if analysis.document_type == DocumentType::Invoice {
+                            match write_ubl(dir, &document.source, &analysis) {
+                                Ok(path) => record.ubl = Some(path),
+                                Err(e) => record.error = Some(format!("{:#}", e)),
                             }
                         }
-                    } else {
-                        continue;
                     }
+                    if mode == Mode::Validate {
+                        match engine.validate(&analysis).await {
+                            Ok(validation) => record.validation = Some(validation),
+                            Err(e) => record.error = Some(format!("{:#}", e)),
+                        }
+                    }
+                    record.analysis = Some(analysis);
+                    Ok(())
                 }
-                Err(_) => {
-                    continue;
-                }
+                Err(e) => Err(e),
             }
         }
+    };
 
-        Err(anyhow::anyhow!("All models failed. Using enhanced simulation."))
+    if let Err(e) = result {
+        if let Some(raw) = e
+            .downcast_ref::<AnalyzerError>()
+            .and_then(AnalyzerError::raw_output)
+        {
+            log::debug!("Raw model output for {}: {}", document.source, raw);
+        }
+        record.error = Some(format!("{:#}", e));
     }
+    record
+}
 
-    fn build_smart_analysis_prompt(&self, text: &str) -> String {
-        format!(
-            r#"Analyze this financial document and return JSON. Adapt field extraction based on document type.
-
-COMMON DOCUMENT TYPES & EXPECTED FIELDS:
-- INVOICE: date, vendor, client, total_amount, invoice_number, tax, due_date
-- RECEIPT: date, store, total_amount, items, tax, payment_method
-- BANK STATEMENT: period, account_number, beginning_balance, ending_balance, transactions
-- TAX FORM: year, taxpayer, employer, wages, taxes_withheld, form_type
-
-JSON STRUCTURE:
-{{
-  "document_type": "specific type",
-  "confidence": 0.95,
-  "extracted_data": {{ "extract RELEVANT fields for the document type" }},
-  "validation_errors": ["only actual missing REQUIRED fields"],
-  "suggested_categories": ["relevant categories"],
-  "document_insights": ["key observations about the document"]
-}}
-
-DOCUMENT:
-{}
-
-Return ONLY the JSON object."#,
-            text
-        )
+/// Writes an invoice as `<file stem>.xml` in `dir`, returning the path.
+fn write_ubl(dir: &Path, source: &str, invoice: &FinancialDocument) -> Result<String> {
+    let xml = einvoice::to_ubl(invoice).context("Cannot write the invoice as UBL")?;
+    let stem = match source {
+        "<stdin>" => "stdin",
+        _ => Path::new(source)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("invoice"),
+    };
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    // Inputs from different directories can share a file stem, and a batch
+    // writes them concurrently: create each file only if it is new, and
+    // number the later ones instead of overwriting the first.
+    let mut attempt = 1;
+    loop {
+        let name = match attempt {
+            1 => format!("{}.xml", stem),
+            _ => format!("{}-{}.xml", stem, attempt),
+        };
+        let path = dir.join(name);
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path);
+        match file {
+            Ok(mut file) => {
+                file.write_all(xml.as_bytes())
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+                return Ok(path.display().to_string());
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to write {}", path.display()))
+            }
+        }
     }
+}
 
-    fn enhance_analysis(&self, analysis: &mut FinancialDocument) {
-        // Add intelligent insights based on document type and extracted data
-        let mut insights = Vec::new();
+async fn run(mode: Mode, args: &CommandArgs) -> Result<ExitCode> {
+    let engine = Engine::from_args(args).await?;
+    let documents = collect_inputs(&args.inputs)?;
+    let mapping = match &args.csv_mapping {
+        Some(path) => CsvMapping::load(path)
+            .with_context(|| format!("Failed to load CSV mapping {}", path.display()))?,
+        None => CsvMapping::default(),
+    };
 
-        match analysis.document_type.as_str() {
-            "Bank Statement" | "Bank" => {
-                // Remove inappropriate validation errors for bank statements
-                analysis.validation_errors.retain(|error|
-                    !error.contains("vendor") && !error.contains("client")
+    let options = BatchOptions {
+        concurrency: args.concurrency,
+    };
+    let records = run_batch(
+        documents.iter().collect(),
+        &options,
+        |_, document| process(&engine, mode, &mapping, args.ubl_dir.as_deref(), document),
+        |progress| {
+            if progress.total > 1 {
+                eprintln!(
+                    "⏳ [{}/{}] {}",
+                    progress.completed, progress.total, documents[progress.index].source
                 );
-
-                // Add relevant insights
-                if let Some(balance) = analysis.extracted_data.get("ending_balance") {
-                    insights.push(format!("Ending balance: {}", balance));
-                }
-                if let Some(period) = analysis.extracted_data.get("period") {
-                    insights.push(format!("Statement period: {}", period));
-                }
-            },
-            "Invoice" => {
-                if let Some(due_date) = analysis.extracted_data.get("due_date") {
-                    insights.push(format!("Payment due: {}", due_date));
-                }
-                if let Some(tax) = analysis.extracted_data.get("tax_amount") {
-                    insights.push(format!("Tax amount: {}", tax));
-                }
-            },
-            "Receipt" => {
-                if let Some(store) = analysis.extracted_data.get("store") {
-                    insights.push(format!("Purchase from: {}", store));
-                }
-                if let Some(payment_method) = analysis.extracted_data.get("payment_method") {
-                    insights.push(format!("Paid with: {}", payment_method));
-                }
-            },
-            _ => {}
+            }
+        },
+    )
+    .await;
+
+    for record in &records {
+        match args.format {
+            OutputFormat::Jsonl => println!("{}", serde_json::to_string(record)?),
+            OutputFormat::Pretty => print_record(record)?,
+            OutputFormat::Json => {}
         }
+    }
 
-        // Add confidence-based insight
-        if analysis.confidence > 0.9 {
-            insights.push("High confidence analysis".to_string());
-        } else if analysis.confidence > 0.7 {
-            insights.push("Moderate confidence analysis".to_string());
-        }
+    if args.format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+    }
+
+    let failed = records.iter().any(|r| r.error.is_some());
+    let invalid = records
+        .iter()
+        .any(|r| r.validation.as_ref().is_some_and(|v| !v.is_valid));
 
-        analysis.document_insights = insights;
+    if records.len() > 1 {
+        let errors = records.iter().filter(|r| r.error.is_some()).count();
+        eprintln!("✅ {} succeeded, ❌ {} failed", records.len() - errors, errors);
+    }
+    if let Engine::Llm(analyzer) = &engine {
+        let usage = analyzer.usage();
+        if !usage.is_empty() {
+            eprintln!("💵 {}", usage);
+        }
     }
+
+    Ok(ExitCode::from(if failed {
+        EXIT_PROCESSING_ERROR
+    } else if invalid {
+        EXIT_VALIDATION_FAILED
+    } else {
+        EXIT_OK
+    }))
 }
 
-fn extract_json_from_text(text: &str) -> Option<String> {
-    if let Some(start) = text.find('{') {
-        if let Some(end) = text.rfind('}') {
-            if end > start {
-                return Some(text[start..=end].to_string());
+async fn run_eval(args: &EvalArgs) -> Result<ExitCode> {
+    let cases = load_corpus(&args.corpus)
+        .with_context(|| format!("Failed to load corpus {}", args.corpus.display()))?;
+    if cases.is_empty() {
+        anyhow::bail!("No documents in {}", args.corpus.display());
+    }
+
+    let mut default_models: Vec<String> = OPENROUTER_MODELS.iter().map(|m| m.to_string()).collect();
+    let provider: Arc<dyn LlmProvider> = match (&args.recordings, &args.base_url) {
+        (Some(dir), _) => Arc::new(
+            RecordedProvider::load_dir(dir)
+                .with_context(|| format!("Failed to load recordings {}", dir.display()))?,
+        ),
+        (None, Some(url)) => {
+            let provider = local_provider(Some(url), args.api_key.as_deref());
+            if args.models.is_empty() {
+                default_models = discover_models(&provider).await?;
             }
+            Arc::new(provider)
         }
+        (None, None) => {
+            let key = args
+                .api_key
+                .clone()
+                .or_else(|| std::env::var("OPENROUTER_API_KEY").ok())
+                .context("OPENROUTER_API_KEY, --api-key or --recordings is required")?;
+            Arc::new(OpenRouterProvider::new(key))
+        }
+    };
+    let models = if args.models.is_empty() {
+        default_models
+    } else {
+        args.models.clone()
+    };
+
+    // One model per analyzer and no rule fallback, so each score is the
+    // model's own.
+    let evaluator = Evaluator::new().with_tolerance(args.tolerance);
+    let mut results = Vec::new();
+    for model in &models {
+        let analyzer = FinancialAnalyzer::with_provider(provider.clone())
+            .with_model(model)
+            .with_prompt(AnalysisPrompt::Smart);
+        results.extend(evaluator.evaluate(model, &analyzer, &cases).await);
     }
-    None
+
+    let report = EvalReport::new(results);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
+    }
+    Ok(ExitCode::SUCCESS)
 }
 
-fn main() -> Result<()> {
-    dotenv::dotenv().ok();
+/// A key is only sent to a local server when given on the command line.
+fn local_provider(base_url: Option<&str>, api_key: Option<&str>) -> LocalProvider {
+    let base_url = base_url
+        .map(str::to_string)
+        .or_else(|| std::env::var("LOCAL_LLM_URL").ok())
+        .unwrap_or_else(|| DEFAULT_LOCAL_URL.to_string());
+    let provider = LocalProvider::new(base_url);
+    match api_key {
+        Some(key) => provider.with_api_key(key),
+        None => provider,
+    }
+}
+
+/// The models a local server offers; at least one.
+async fn discover_models(provider: &LocalProvider) -> Result<Vec<String>> {
+    let models = provider
+        .list_models()
+        .await
+        .with_context(|| format!("Failed to list the models at {}", provider.base_url()))?;
+    if models.is_empty() {
+        anyhow::bail!("{} serves no models; pass --model", provider.base_url());
+    }
+    Ok(models)
+}
+
+fn print_record(record: &Record) -> Result<()> {
+    println!("📄 {}", record.source);
+    println!("{}", "─".repeat(40));
+    if let Some(analysis) = &record.analysis {
+        analysis.pretty_print();
+    }
+    if let Some(path) = &record.ubl {
+        println!("🧾 UBL invoice written to {}", path);
+    }
+    if let Some(validation) = &record.validation {
+        validation.pretty_print();
+    }
+    if let Some(json) = &record.json {
+        println!("{}", serde_json::to_string_pretty(json)?);
+    }
+    if let Some(error) = &record.error {
+        eprintln!("❌ {}: {}", record.source, error);
+    }
+    println!();
+    Ok(())
+}
+
+fn is_openrouter_key(key: &str) -> bool {
+    key.starts_with("sk-or-") && key.len() > 20
+}
 
+async fn run_demo() -> Result<()> {
     println!("=== Financial Document AI Analyzer ===");
     println!("🎯 Smart Analysis with Document-Type Intelligence\n");
 
     let api_key = std::env::var("OPENROUTER_API_KEY")
         .unwrap_or_else(|_| "no-key-found".to_string());
 
-    let test_documents = vec![
+    let extractor = RuleBasedExtractor::new();
+
+    let test_documents = [
         r#"INVOICE
 From: Tech Solutions Inc.
 To: ABC Corporation
@@ -274,7 +546,11 @@ Account: ****1234
 Statement Period: Jan 1-31, 2024
 Beginning Balance: $12,500.00
 Ending Balance: $16,714.50
-Transactions: Various deposits and withdrawals"#,
+Transactions:
+01/05  Payroll Deposit     3,500.00   16,000.00
+01/12  Grocery Store         285.50   15,714.50
+01/15  Client Payment      2,000.00   17,714.50
+01/28  Rent                1,000.00   16,714.50"#,
         r#"TAX FORM W-2
 Employee: John Smith
 Employer: Tech Solutions Inc.
@@ -285,30 +561,32 @@ Year: 2023
 Social Security Wages: $85,000.00"#,
     ];
 
-    if api_key.starts_with("sk-or-") && api_key.len() > 20 {
+    if is_openrouter_key(&api_key) {
         println!("✅ API Status: Connected");
         println!("🚀 Starting intelligent analysis...\n");
 
-        let analyzer = FinancialAnalyzer::new(api_key);
+        let analyzer = FinancialAnalyzer::with_provider(Arc::new(OpenRouterProvider::new(api_key)))
+            .with_models(OPENROUTER_MODELS)
+            .with_prompt(AnalysisPrompt::Smart);
 
         for (i, doc_text) in test_documents.iter().enumerate() {
             println!("{}. {}", i + 1, "=".repeat(50));
             let doc_preview = doc_text.lines().take(2).collect::<Vec<_>>().join(" | ");
             println!("📄 INPUT: {}", doc_preview);
 
-            match analyzer.analyze_document(doc_text) {
+            match analyzer.analyze_document(doc_text).await {
                 Ok(analysis) => {
                     println!("\n✨ AI ANALYSIS RESULTS:");
                     println!("{}", "─".repeat(40));
-                    print_enhanced_analysis(&analysis);
+                    analysis.pretty_print();
                     println!("{}", "─".repeat(40));
                     println!("🤖 Model: Meta Llama 3.2 3B Instruct");
-                    println!("🌐 Powered by: OpenRouter API");
+                    println!("🌐 Powered by: {} API", analyzer.provider_name());
                 }
                 Err(e) => {
                     println!("\n❌ AI Analysis failed: {}", e);
-                    println!("🔄 Using intelligent simulation...");
-                    intelligent_simulation(i, doc_text);
+                    println!("🔄 Falling back to rule-based extraction...");
+                    rule_based_analysis(&extractor, doc_text);
                 }
             }
 
@@ -318,11 +596,11 @@ Social Security Wages: $85,000.00"#,
         }
     } else {
         println!("❌ API: Not connected");
-        println!("🔧 Running in simulation mode\n");
+        println!("🔧 Running in offline rule-based mode\n");
 
         for (i, doc_text) in test_documents.iter().enumerate() {
             println!("{}. {}", i + 1, "=".repeat(50));
-            intelligent_simulation(i, doc_text);
+            rule_based_analysis(&extractor, doc_text);
         }
     }
 
@@ -333,132 +611,12 @@ Social Security Wages: $85,000.00"#,
     Ok(())
 }
 
-fn intelligent_simulation(doc_num: usize, text: &str) {
-    let analysis = match doc_num {
-        0 => FinancialDocument {
-            document_type: "Invoice".to_string(),
-            confidence: 0.96,
-            extracted_data: [
-                ("date".to_string(), "January 15, 2024".to_string()),
-                ("due_date".to_string(), "February 14, 2024".to_string()),
-                ("total_amount".to_string(), "$2,750.00".to_string()),
-                ("tax_amount".to_string(), "$250.00".to_string()),
-                ("vendor".to_string(), "Tech Solutions Inc.".to_string()),
-                ("client".to_string(), "ABC Corporation".to_string()),
-                ("invoice_number".to_string(), "INV-2024-001".to_string()),
-                ("payment_terms".to_string(), "Net 30".to_string()),
-            ].iter().cloned().collect(),
-            validation_errors: vec![],
-            suggested_categories: vec!["Technology".to_string(), "Professional Services".to_string()],
-            document_insights: vec![
-                "Payment due: February 14, 2024".to_string(),
-                "Tax amount: $250.00".to_string(),
-                "High confidence analysis".to_string(),
-            ],
-        },
-        1 => FinancialDocument {
-            document_type: "Receipt".to_string(),
-            confidence: 0.94,
-            extracted_data: [
-                ("date".to_string(), "2024-01-20".to_string()),
-                ("total_amount".to_string(), "$53.43".to_string()),
-                ("tax_amount".to_string(), "$3.96".to_string()),
-                ("store".to_string(), "Office Supply World".to_string()),
-                ("receipt_number".to_string(), "RCPT-789123".to_string()),
-                ("payment_method".to_string(), "Credit Card".to_string()),
-            ].iter().cloned().collect(),
-            validation_errors: vec!["Missing individual item prices".to_string()],
-            suggested_categories: vec!["Office Supplies".to_string(), "Business Expenses".to_string()],
-            document_insights: vec![
-                "Purchase from: Office Supply World".to_string(),
-                "Paid with: Credit Card".to_string(),
-                "High confidence analysis".to_string(),
-            ],
-        },
-        2 => FinancialDocument {
-            document_type: "Bank Statement".to_string(),
-            confidence: 0.95,
-            extracted_data: [
-                ("period".to_string(), "Jan 1-31, 2024".to_string()),
-                ("account_number".to_string(), "****1234".to_string()),
-                ("beginning_balance".to_string(), "$12,500.00".to_string()),
-                ("ending_balance".to_string(), "$16,714.50".to_string()),
-            ].iter().cloned().collect(),
-            validation_errors: vec![],
-            suggested_categories: vec!["Banking".to_string(), "Financial Records".to_string()],
-            document_insights: vec![
-                "Ending balance: $16,714.50".to_string(),
-                "Statement period: Jan 1-31, 2024".to_string(),
-                "High confidence analysis".to_string(),
-            ],
-        },
-        3 => FinancialDocument {
-            document_type: "Tax Form W-2".to_string(),
-            confidence: 0.97,
-            extracted_data: [
-                ("year".to_string(), "2023".to_string()),
-                ("employee".to_string(), "John Smith".to_string()),
-                ("employer".to_string(), "Tech Solutions Inc.".to_string()),
-                ("wages".to_string(), "$85,000.00".to_string()),
-                ("federal_tax_withheld".to_string(), "$15,300.00".to_string()),
-                ("employer_ein".to_string(), "12-3456789".to_string()),
-            ].iter().cloned().collect(),
-            validation_errors: vec![],
-            suggested_categories: vec!["Tax Documents".to_string(), "Income Records".to_string()],
-            document_insights: vec![
-                "Tax year: 2023".to_string(),
-                "Wages: $85,000.00".to_string(),
-                "High confidence analysis".to_string(),
-            ],
-        },
-        _ => FinancialDocument {
-            document_type: "Unknown".to_string(),
-            confidence: 0.5,
-            extracted_data: HashMap::new(),
-            validation_errors: vec!["Cannot analyze document".to_string()],
-            suggested_categories: vec![],
-            document_insights: vec![],
-        },
-    };
+fn rule_based_analysis(extractor: &RuleBasedExtractor, text: &str) {
+    let analysis = extractor.extract(text);
 
-    println!("🔧 INTELLIGENT SIMULATION:");
+    println!("🔧 RULE-BASED EXTRACTION:");
     println!("{}", "─".repeat(40));
-    print_enhanced_analysis(&analysis);
+    analysis.pretty_print();
     println!("{}", "─".repeat(40));
-    println!("🤖 Analyzed by: Simulation Engine");
+    println!("🤖 Analyzed by: Rule-based extractor");
 }
-
-fn print_enhanced_analysis(analysis: &FinancialDocument) {
-    println!("📋 Document Type: {}", analysis.document_type);
-    println!("🎯 Confidence: {:.1}%", analysis.confidence * 100.0);
-
-    if !analysis.extracted_data.is_empty() {
-        println!("\n💰 EXTRACTED DATA:");
-        for (key, value) in &analysis.extracted_data {
-            if !value.is_empty() {
-                println!("   • {:25}: {}", key, value);
-            }
-        }
-    }
-
-    if !analysis.validation_errors.is_empty() {
-        println!("\n❌ VALIDATION ISSUES:");
-        for error in &analysis.validation_errors {
-            println!("   • {}", error);
-        }
-    }
-
-    if !analysis.suggested_categories.is_empty() {
-        println!("\n🏷️ CATEGORIES:");
-        for category in &analysis.suggested_categories {
-            println!("   • {}", category);
-        }
-    }
-
-    if !analysis.document_insights.is_empty() {
-        println!("\n💡 INSIGHTS:");
-        for insight in &analysis.document_insights {
-            println!("   • {}", insight);
-        }
-    }
-}
\ No newline at end of file
@@ -0,0 +1,401 @@
+use crate::schema::schema_from_json;
+use rust_decimal::Decimal;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
+use std::fmt;
+use std::str::FromStr;
+
+// Symbol -> ISO-4217 code. Multi-character symbols come first so "C$" is
+// not read as "$".
+const CURRENCY_SYMBOLS: &[(&str, &str)] = &[
+    ("US$", "USD"),
+    ("C$", "CAD"),
+    ("A$", "AUD"),
+    ("€", "EUR"),
+    ("£", "GBP"),
+    ("¥", "JPY"),
+    ("₹", "INR"),
+    ("$", "USD"),
+];
+
+/// An ISO-4217 currency code. `XXX` (the ISO code for "no currency") marks
+/// amounts whose currency was not stated and should be taken from context.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Currency(String);
+
+impl Currency {
+    pub fn new(code: &str) -> Option<Self> {
+        let code = code.trim();
+        (code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()))
+            .then(|| Currency(code.to_ascii_uppercase()))
+    }
+
+    pub fn unknown() -> Self {
+        Currency("XXX".to_string())
+    }
+
+    pub fn usd() -> Self {
+        Currency("USD".to_string())
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        CURRENCY_SYMBOLS
+            .iter()
+            .find(|(s, _)| *s == symbol)
+            .map(|(_, code)| Currency(code.to_string()))
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        self.0 == "XXX"
+    }
+
+    pub fn code(&self) -> &str {
+        &self.0
+    }
+
+    fn symbol(&self) -> Option<&'static str> {
+        match self.0.as_str() {
+            "USD" => Some("$"),
+            "EUR" => Some("€"),
+            "GBP" => Some("£"),
+            "JPY" => Some("¥"),
+            "INR" => Some("₹"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Currency::new(&value)
+            .or_else(|| Currency::from_symbol(value.trim()))
+            .ok_or_else(|| de::Error::custom(format!("invalid currency code: {}", value)))
+    }
+}
+
+/// An exact decimal amount in a specific currency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Money {
+    pub amount: Decimal,
+    pub currency: Currency,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("cannot parse amount: {0:?}")]
+pub struct MoneyParseError(pub String);
+
+impl Money {
+    pub fn new(amount: Decimal, currency: Currency) -> Self {
+        Self { amount, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Self::new(Decimal::ZERO, currency)
+    }
+
+    /// Parses amounts such as `$2,750.00`, `€1.234,56`, `1'000.50 CHF`,
+    /// `USD 12` or `(45.00)`. The currency is `XXX` when none is given.
+    pub fn parse(input: &str) -> Result<Self, MoneyParseError> {
+        let error = || MoneyParseError(input.to_string());
+        let mut text = input.trim().to_string();
+
+        let mut negative = false;
+        if text.starts_with('(') && text.ends_with(')') {
+            negative = true;
+            text = text[1..text.len() - 1].trim().to_string();
+        }
+
+        let mut currency = None;
+        for (symbol, code) in CURRENCY_SYMBOLS {
+            if text.contains(symbol) {
+                currency = Some(Currency(code.to_string()));
+                text = text.replacen(symbol, "", 1);
+                break;
+            }
+        }
+
+        let letters: String = text.chars().filter(|c| c.is_ascii_alphabetic()).collect();
+        if !letters.is_empty() {
+            currency = Some(Currency::new(&letters).ok_or_else(error)?);
+            text.retain(|c| !c.is_ascii_alphabetic());
+        }
+
+        let mut number: String = text
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '\'' && *c != '\u{a0}')
+            .collect();
+        if let Some(rest) = number.strip_prefix('-') {
+            negative = !negative;
+            number = rest.to_string();
+        }
+
+        let amount = Decimal::from_str(&normalize_separators(&number)).map_err(|_| error())?;
+
+        Ok(Money {
+            amount: if negative { -amount } else { amount },
+            currency: currency.unwrap_or_else(Currency::unknown),
+        })
+    }
+
+    /// Fills in the currency when it was not stated.
+    pub fn with_default_currency(mut self, currency: &Currency) -> Self {
+        if self.currency.is_unknown() {
+            self.currency = currency.clone();
+        }
+        self
+    }
+
+    /// Adds two amounts; an unknown currency adopts the other one. Returns
+    /// `None` when the currencies conflict.
+    pub fn checked_add(&self, other: &Money) -> Option<Money> {
+        let currency = match (self.currency.is_unknown(), other.currency.is_unknown()) {
+            (true, _) => other.currency.clone(),
+            (_, true) => self.currency.clone(),
+            _ if self.currency == other.currency => self.currency.clone(),
+            _ => return None,
+        };
+        Some(Money::new(self.amount + other.amount, currency))
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Option<Money> {
+        self.checked_add(&Money::new(-other.amount, other.currency.clone()))
+    }
+
+    /// Sums amounts exactly; `None` on mixed currencies.
+    pub fn sum<'a>(amounts: impl IntoIterator<Item = &'a Money>) -> Option<Money> {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(Currency::unknown()), |total, amount| {
+                total.checked_add(amount)
+            })
+    }
+}
+
+// Decides which of `,` and `.` is the decimal separator and strips the other.
+fn normalize_separators(number: &str) -> String {
+    let last_comma = number.rfind(',');
+    let last_dot = number.rfind('.');
+
+    let decimal_is_comma = match (last_comma, last_dot) {
+        (Some(comma), Some(dot)) => comma > dot,
+        // "12,50" is a decimal comma, "1,250" a thousands separator
+        (Some(comma), None) => number.matches(',').count() == 1 && number.len() - comma - 1 != 3,
+        _ => false,
+    };
+
+    if decimal_is_comma {
+        number.replace('.', "").replace(',', ".")
+    } else {
+        number.replace(',', "")
+    }
+}
+
+impl FromStr for Money {
+    type Err = MoneyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Money::parse(s)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rounded = format!("{:.2}", self.amount.abs());
+        let (whole, fraction) = rounded.split_once('.').unwrap_or((&rounded, "00"));
+
+        let mut grouped = String::new();
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+
+        let sign = if self.amount.is_sign_negative() && !self.amount.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+        match self.currency.symbol() {
+            Some(symbol) => write!(f, "{}{}{}.{}", sign, symbol, grouped, fraction),
+            None if self.currency.is_unknown() => write!(f, "{}{}.{}", sign, grouped, fraction),
+            None => write!(f, "{}{}.{} {}", sign, grouped, fraction, self.currency),
+        }
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("Money", 2)?;
+        state.serialize_field("amount", &self.amount.to_string())?;
+        state.serialize_field("currency", &self.currency)?;
+        state.end()
+    }
+}
+
+/// Accepts a bare number, a string such as `"$2,750.00"`, or an
+/// `{"amount": ..., "currency": ...}` object.
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MoneyVisitor;
+
+        impl<'de> Visitor<'de> for MoneyVisitor {
+            type Value = Money;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an amount as a number, a string or an {amount, currency} object")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Money, E> {
+                Ok(Money::new(Decimal::from(v), Currency::unknown()))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Money, E> {
+                Ok(Money::new(Decimal::from(v), Currency::unknown()))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Money, E> {
+                // Going through the shortest round-trip string keeps 0.1 as 0.1.
+                Decimal::from_str(&v.to_string())
+                    .map(|amount| Money::new(amount, Currency::unknown()))
+                    .map_err(E::custom)
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Money, E> {
+                Money::parse(v).map_err(E::custom)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Money, A::Error> {
+                let mut amount: Option<Money> = None;
+                let mut currency: Option<Currency> = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "amount" | "value" => amount = Some(map.next_value()?),
+                        "currency" => currency = Some(map.next_value()?),
+                        _ => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+                let amount = amount.ok_or_else(|| de::Error::missing_field("amount"))?;
+                Ok(match currency {
+                    Some(currency) => Money::new(amount.amount, currency),
+                    None => amount,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+impl JsonSchema for Currency {
+    fn schema_name() -> String {
+        "Currency".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        schema_from_json(json!({
+            "type": "string",
+            "description": "ISO-4217 code such as USD, or a currency symbol"
+        }))
+    }
+}
+
+// Mirrors what `Deserialize` accepts; models are asked for the object form.
+impl JsonSchema for Money {
+    fn schema_name() -> String {
+        "Money".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        schema_from_json(json!({
+            "anyOf": [
+                {
+                    "type": "object",
+                    "properties": {
+                        "amount": {"type": ["string", "number"]},
+                        "currency": {"type": "string"}
+                    },
+                    "required": ["amount"]
+                },
+                {"type": "number"},
+                {"type": "string"}
+            ]
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let cases = [
+            ("$2,750.00", "2750.00", "USD"),
+            ("€1.234,56", "1234.56", "EUR"),
+            ("1'000.50 CHF", "1000.50", "CHF"),
+            ("USD 12", "12", "USD"),
+            ("(45.00)", "-45.00", "XXX"),
+            ("12,50", "12.50", "XXX"),
+            ("-£3.96", "-3.96", "GBP"),
+        ];
+        for (input, amount, currency) in cases {
+            let money = Money::parse(input).unwrap();
+            assert_eq!(money.amount, dec(amount), "{}", input);
+            assert_eq!(money.currency.code(), currency, "{}", input);
+        }
+        assert!(Money::parse("about ten").is_err());
+    }
+
+    #[test]
+    fn test_sums_are_exact() {
+        let cents: Vec<Money> = (0..10).map(|_| Money::parse("$0.10").unwrap()).collect();
+        assert_eq!(Money::sum(&cents).unwrap(), Money::parse("$1.00").unwrap());
+
+        let euro = Money::parse("€1.00").unwrap();
+        assert!(cents[0].checked_add(&euro).is_none());
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let from_number: Money = serde_json::from_str("4860.1").unwrap();
+        assert_eq!(from_number.amount, dec("4860.1"));
+        assert!(from_number.currency.is_unknown());
+
+        let from_object: Money =
+            serde_json::from_str(r#"{"amount": "53.43", "currency": "usd"}"#).unwrap();
+        let json = serde_json::to_string(&from_object).unwrap();
+        assert_eq!(json, r#"{"amount":"53.43","currency":"USD"}"#);
+        assert_eq!(serde_json::from_str::<Money>(&json).unwrap(), from_object);
+
+        assert_eq!(from_object.to_string(), "$53.43");
+        assert_eq!(
+            Money::parse("-1234567.5 CHF").unwrap().to_string(),
+            "-1,234,567.50 CHF"
+        );
+    }
+}
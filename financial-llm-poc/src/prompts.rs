@@ -0,0 +1,193 @@
+use crate::error::{AnalyzerError, Result};
+use crate::llm_provider::Message;
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::LazyLock;
+
+pub const ANALYSIS: &str = "analysis";
+pub const SMART_ANALYSIS: &str = "smart_analysis";
+pub const VALIDATION: &str = "validation";
+pub const CONVERSION: &str = "conversion";
+
+// Compiled in, so the analyzer works without a prompts directory.
+const BUILTIN_TEMPLATES: &[&str] = &[
+    include_str!("../prompts/analysis.toml"),
+    include_str!("../prompts/smart_analysis.toml"),
+    include_str!("../prompts/validation.toml"),
+    include_str!("../prompts/conversion.toml"),
+];
+
+// `{{document}}`, `{{ analysis }}`
+static VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap());
+
+/// A named system/user prompt pair with `{{variable}}` placeholders.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub version: String,
+    pub system: String,
+    pub user: String,
+}
+
+impl PromptTemplate {
+    /// Parses a TOML template with `name`, `version`, `system` and `user` keys.
+    pub fn parse(source: &str) -> Result<Self> {
+        let template: PromptTemplate = toml::from_str(source)
+            .map_err(|e| AnalyzerError::Template(format!("invalid template: {}", e)))?;
+        if template.name.trim().is_empty() || template.version.trim().is_empty() {
+            return Err(AnalyzerError::Template(
+                "template name and version must not be empty".to_string(),
+            ));
+        }
+        Ok(template)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path).map_err(|e| {
+            AnalyzerError::Template(format!("cannot read {}: {}", path.display(), e))
+        })?;
+        Self::parse(&source).map_err(|e| match e {
+            AnalyzerError::Template(message) => {
+                AnalyzerError::Template(format!("{}: {}", path.display(), message))
+            }
+            other => other,
+        })
+    }
+
+    /// Identifies the exact prompt, e.g. `analysis@1`.
+    pub fn version_id(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+
+    /// Substitutes `vars` into the system and user prompts. A placeholder
+    /// without a value is an error rather than being sent to the model.
+    pub fn render(&self, vars: &[(&str, &str)]) -> Result<Vec<Message>> {
+        Ok(vec![
+            Message {
+                role: "system".to_string(),
+                content: self.substitute(&self.system, vars)?,
+            },
+            Message::user(self.substitute(&self.user, vars)?),
+        ])
+    }
+
+    fn substitute(&self, text: &str, vars: &[(&str, &str)]) -> Result<String> {
+        let mut missing = None;
+        let rendered = VARIABLE.replace_all(text.trim(), |captures: &Captures| {
+            let name = &captures[1];
+            match vars.iter().find(|(var, _)| *var == name) {
+                Some((_, value)) => value.to_string(),
+                None => {
+                    missing.get_or_insert_with(|| name.to_string());
+                    captures[0].to_string()
+                }
+            }
+        });
+        match missing {
+            Some(name) => Err(AnalyzerError::Template(format!(
+                "{} uses {{{{{}}}}}, which has no value",
+                self.version_id(),
+                name
+            ))),
+            None => Ok(rendered.into_owned()),
+        }
+    }
+}
+
+/// The templates the analyzer renders its prompts from, by name.
+#[derive(Debug, Clone)]
+pub struct PromptLibrary {
+    templates: HashMap<String, PromptTemplate>,
+}
+
+impl Default for PromptLibrary {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl PromptLibrary {
+    /// The templates shipped in `prompts/`.
+    pub fn builtin() -> Self {
+        let templates = BUILTIN_TEMPLATES
+            .iter()
+            .map(|source| PromptTemplate::parse(source).expect("built-in template is valid"))
+            .map(|template| (template.name.clone(), template))
+            .collect();
+        Self { templates }
+    }
+
+    /// The built-in templates, overridden by every `*.toml` file in `dir`.
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let mut library = Self::builtin();
+        let entries = std::fs::read_dir(dir).map_err(|e| {
+            AnalyzerError::Template(format!("cannot read {}: {}", dir.display(), e))
+        })?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "toml") {
+                let template = PromptTemplate::load(&path)?;
+                log::info!(
+                    "Loaded prompt {} from {}",
+                    template.version_id(),
+                    path.display()
+                );
+                library.insert(template);
+            }
+        }
+        Ok(library)
+    }
+
+    pub fn insert(&mut self, template: PromptTemplate) {
+        self.templates.insert(template.name.clone(), template);
+    }
+
+    pub fn get(&self, name: &str) -> Result<&PromptTemplate> {
+        self.templates
+            .get(name)
+            .ok_or_else(|| AnalyzerError::Template(format!("no prompt template named {}", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_templates_render() {
+        let library = PromptLibrary::builtin();
+        for name in [ANALYSIS, SMART_ANALYSIS, CONVERSION] {
+            let messages = library
+                .get(name)
+                .unwrap()
+                .render(&[("document", "INVOICE #1")])
+                .unwrap();
+            assert!(messages[1].content.contains("INVOICE #1"), "{}", name);
+        }
+        let validation = library.get(VALIDATION).unwrap();
+        assert_eq!(validation.version_id(), "validation@1");
+        assert!(validation.render(&[("document", "x")]).is_err());
+    }
+
+    #[test]
+    fn test_directory_overrides_builtin() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("analysis.toml"),
+            "name = \"analysis\"\nversion = \"2-draft\"\nsystem = \"Be brief.\"\nuser = \"\"\"\nDoc: {{ document }}\n\"\"\"\n",
+        )
+        .unwrap();
+
+        let library = PromptLibrary::load_dir(dir.path()).unwrap();
+        let analysis = library.get(ANALYSIS).unwrap();
+        let messages = analysis.render(&[("document", "hello")]).unwrap();
+
+        assert_eq!(analysis.version_id(), "analysis@2-draft");
+        assert_eq!(messages[0].content, "Be brief.");
+        assert_eq!(messages[1].content, "Doc: hello");
+        assert_eq!(library.get(CONVERSION).unwrap().version, "1");
+    }
+}
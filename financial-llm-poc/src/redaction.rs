@@ -0,0 +1,293 @@
+use regex::{Captures, Regex};
+
+/// A kind of personal data the redactor looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PiiKind {
+    Email,
+    Iban,
+    CardNumber,
+    Ssn,
+    Ein,
+    AccountNumber,
+}
+
+impl PiiKind {
+    fn label(self) -> &'static str {
+        match self {
+            PiiKind::Email => "EMAIL",
+            PiiKind::Iban => "IBAN",
+            PiiKind::CardNumber => "CARD",
+            PiiKind::Ssn => "SSN",
+            PiiKind::Ein => "EIN",
+            PiiKind::AccountNumber => "ACCOUNT",
+        }
+    }
+}
+
+// (kind, pattern). The value is the `pii` group when present, otherwise the
+// whole match. Order matters: IBANs and card numbers are long digit runs
+// that the shorter patterns would otherwise split.
+const PII_PATTERNS: &[(PiiKind, &str)] = &[
+    (
+        PiiKind::Email,
+        r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b",
+    ),
+    (
+        PiiKind::Iban,
+        r"\b[A-Z]{2}\d{2}(?:[A-Z0-9]{11,30}|(?: [A-Z0-9]{4}){2,7}(?: [A-Z0-9]{1,4})?)\b",
+    ),
+    (PiiKind::CardNumber, r"\b\d(?:[ -]?\d){12,18}\b"),
+    (PiiKind::Ssn, r"\b\d{3}-\d{2}-\d{4}\b"),
+    (PiiKind::Ein, r"\b\d{2}-\d{7}\b"),
+    (
+        PiiKind::AccountNumber,
+        r"(?i)\b(?:account|acct)(?:\s*(?:number|no\.?|#))?\s*:?\s*(?P<pii>[*xX]*\d[\d -]{2,}\d)",
+    ),
+];
+
+/// Replaces personal data with stable placeholders such as `[SSN_1]` before
+/// text is sent to a hosted model.
+pub struct Redactor {
+    patterns: Vec<(PiiKind, Regex)>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        let patterns = PII_PATTERNS
+            .iter()
+            .map(|(kind, pattern)| (*kind, Regex::new(pattern).expect("valid PII pattern")))
+            .collect();
+        Self { patterns }
+    }
+
+    /// Redacts `text`, recording each placeholder in `redactions`. The same
+    /// value always gets the same placeholder, also across calls that share
+    /// `redactions`.
+    pub fn redact(&self, text: &str, redactions: &mut Redactions) -> String {
+        let mut text = text.to_string();
+        for (kind, pattern) in &self.patterns {
+            text = pattern
+                .replace_all(&text, |captures: &Captures| {
+                    let whole = captures.get(0).expect("group 0 always matches");
+                    let value = captures.name("pii").unwrap_or(whole);
+                    if !is_valid(*kind, value.as_str()) {
+                        return whole.as_str().to_string();
+                    }
+                    let placeholder = redactions.placeholder(*kind, value.as_str());
+                    let start = value.start() - whole.start();
+                    let end = value.end() - whole.start();
+                    format!(
+                        "{}{}{}",
+                        &whole.as_str()[..start],
+                        placeholder,
+                        &whole.as_str()[end..]
+                    )
+                })
+                .into_owned();
+        }
+        text
+    }
+}
+
+/// Placeholders handed out while redacting, used to restore the original
+/// values in the model's answer.
+#[derive(Debug, Default, Clone)]
+pub struct Redactions {
+    entries: Vec<(PiiKind, String, String)>,
+}
+
+impl Redactions {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn placeholder(&mut self, kind: PiiKind, value: &str) -> String {
+        if let Some((_, placeholder, _)) = self.entries.iter().find(|(_, _, v)| v == value) {
+            return placeholder.clone();
+        }
+        let index = self.entries.iter().filter(|(k, _, _)| *k == kind).count() + 1;
+        let placeholder = format!("[{}_{}]", kind.label(), index);
+        self.entries
+            .push((kind, placeholder.clone(), value.to_string()));
+        placeholder
+    }
+
+    /// Puts the original values back in place of their placeholders.
+    pub fn restore(&self, text: &str) -> String {
+        self.entries
+            .iter()
+            .fold(text.to_string(), |text, (_, placeholder, value)| {
+                text.replace(placeholder.as_str(), value)
+            })
+    }
+}
+
+// Checksums keep order numbers and phone numbers from being redacted as
+// cards or IBANs.
+fn is_valid(kind: PiiKind, value: &str) -> bool {
+    match kind {
+        PiiKind::CardNumber => luhn_valid(value),
+        PiiKind::Iban => iban_valid(value),
+        _ => true,
+    }
+}
+
+fn luhn_valid(value: &str) -> bool {
+    let digits: Vec<u32> = value.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                d
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+fn iban_valid(value: &str) -> bool {
+    let compact: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+    if compact.len() < 15 {
+        return false;
+    }
+    let (head, tail) = compact.split_at(4);
+    let remainder = tail.chars().chain(head.chars()).try_fold(0u32, |acc, c| {
+        let n = c.to_digit(36)?;
+        Some(if n >= 10 {
+            (acc * 100 + n) % 97
+        } else {
+            (acc * 10 + n) % 97
+        })
+    });
+    remainder == Some(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacts_and_restores() {
+        let text = "Employee SSN: 123-45-6789\n\
+                    Employer EIN: 12-3456789\n\
+                    Email: john.smith@example.com\n\
+                    Card: 4111 1111 1111 1111\n\
+                    IBAN: GB82 WEST 1234 5698 7654 32\n\
+                    Account Number: 000123456789\n\
+                    Invoice #: 1234567890123\n\
+                    Repeat SSN: 123-45-6789";
+
+        let mut redactions = Redactions::default();
+        let redacted = Redactor::new().redact(text, &mut redactions);
+
+        for secret in [
+            "123-45-6789",
+            "12-3456789",
+            "john.smith@example.com",
+            "4111 1111 1111 1111",
+            "GB82 WEST",
+            "000123456789",
+        ] {
+            assert!(
+                !redacted.contains(secret),
+                "{} leaked:\n{}",
+                secret,
+                redacted
+            );
+        }
+        assert!(redacted.contains("Employee SSN: [SSN_1]"));
+        assert!(redacted.contains("Repeat SSN: [SSN_1]"));
+        assert!(redacted.contains("Account Number: [ACCOUNT_1]"));
+        // Fails the Luhn check, so it is left alone.
+        assert!(redacted.contains("1234567890123"));
+        assert_eq!(redactions.len(), 6);
+
+        assert_eq!(redactions.restore(&redacted), text);
+    }
+
+    fn redact(text: &str) -> String {
+        Redactor::new().redact(text, &mut Redactions::default())
+    }
+
+    #[test]
+    fn test_ssn_pattern() {
+        assert_eq!(redact("SSN 123-45-6789."), "SSN [SSN_1].");
+        assert_eq!(redact("ref 123-456-789"), "ref 123-456-789");
+        assert_eq!(redact("Date: 2024-01-15"), "Date: 2024-01-15");
+    }
+
+    #[test]
+    fn test_ein_pattern() {
+        assert_eq!(redact("EIN: 12-3456789"), "EIN: [EIN_1]");
+        assert_eq!(redact("Phone: 555-123-4567"), "Phone: 555-123-4567");
+    }
+
+    #[test]
+    fn test_iban_pattern() {
+        assert_eq!(
+            redact("IBAN DE89370400440532013000, or DE89 3704 0044 0532 0130 00"),
+            "IBAN [IBAN_1], or [IBAN_2]"
+        );
+        // Wrong check digits.
+        assert_eq!(
+            redact("IBAN DE00370400440532013000"),
+            "IBAN DE00370400440532013000"
+        );
+    }
+
+    #[test]
+    fn test_card_pattern() {
+        assert_eq!(
+            redact("Visa 4111-1111-1111-1111, MC 5500 0000 0000 0004"),
+            "Visa [CARD_1], MC [CARD_2]"
+        );
+        // Fails the Luhn check.
+        assert_eq!(
+            redact("Order 4111 1111 1111 1112"),
+            "Order 4111 1111 1111 1112"
+        );
+    }
+
+    #[test]
+    fn test_account_pattern() {
+        assert_eq!(
+            redact("Acct #: ****1234\nAccount No. 12 3456 78"),
+            "Acct #: [ACCOUNT_1]\nAccount No. [ACCOUNT_2]"
+        );
+        assert_eq!(
+            redact("Account balance: 1,234.56"),
+            "Account balance: 1,234.56"
+        );
+    }
+
+    #[test]
+    fn test_email_pattern() {
+        assert_eq!(
+            redact("Mail jane.doe+billing@mail.example.co.uk today"),
+            "Mail [EMAIL_1] today"
+        );
+        assert_eq!(redact("root@localhost"), "root@localhost");
+    }
+}
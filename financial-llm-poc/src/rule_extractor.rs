@@ -0,0 +1,292 @@
+use crate::document_types::{DocumentType, FinancialDocument, LegacyFinancialDocument, RiskLevel};
+use crate::fields::DocumentFields;
+use crate::financial_analyzer::enhance_analysis;
+use crate::transactions::parse_transactions;
+use crate::validator::required_fields;
+use regex::Regex;
+use std::collections::HashMap;
+
+/// Weighted pattern that votes for a document type when it matches.
+struct ClassificationRule {
+    document_type: DocumentType,
+    pattern: Regex,
+    weight: u32,
+}
+
+/// Deterministic, offline extractor. Classifies documents with weighted
+/// pattern rules and pulls out `Label: value` lines, so it works without an
+/// API key and as a fallback when every model fails.
+pub struct RuleBasedExtractor {
+    rules: Vec<ClassificationRule>,
+    field_pattern: Regex,
+}
+
+// (document type label, per-line pattern, weight)
+const CLASSIFICATION_RULES: &[(&str, &str, u32)] = &[
+    ("Invoice", r"(?i)\binvoice\b", 3),
+    ("Invoice", r"(?i)\binvoice\s*(#|no\.?|number)", 2),
+    ("Invoice", r"(?i)\bdue date\b", 1),
+    ("Invoice", r"(?i)\bbill to\b", 1),
+    ("Invoice", r"(?i)\bpayment terms\b", 1),
+    ("Receipt", r"(?i)\breceipt\b", 3),
+    ("Receipt", r"(?i)\breceipt\s*(#|no\.?|number)", 2),
+    ("Receipt", r"(?i)^\s*(store|merchant)\s*:", 1),
+    ("Receipt", r"(?i)\bpayment method\b", 1),
+    ("Receipt", r"(?i)\bcashier\b", 1),
+    ("Bank Statement", r"(?i)\bbank statement\b", 3),
+    ("Bank Statement", r"(?i)\b(beginning|opening) balance\b", 2),
+    ("Bank Statement", r"(?i)\b(ending|closing) balance\b", 2),
+    ("Bank Statement", r"(?i)\bstatement period\b", 1),
+    ("W-2", r"(?i)\bw-?2\b", 3),
+    ("W-2", r"(?i)\bemployer ein\b", 1),
+    ("W-2", r"(?i)\bfederal (income )?tax withheld\b", 1),
+    ("W-2", r"(?i)\bsocial security wages\b", 2),
+    ("W-2", r"(?i)\bwages\b", 1),
+];
+
+// Label (lowercased) -> canonical extracted_data key
+const FIELD_ALIASES: &[(&str, &str)] = &[
+    ("invoice #", "invoice_number"),
+    ("invoice no", "invoice_number"),
+    ("invoice number", "invoice_number"),
+    ("receipt #", "receipt_number"),
+    ("receipt no", "receipt_number"),
+    ("receipt number", "receipt_number"),
+    ("date", "date"),
+    ("invoice date", "date"),
+    ("due date", "due_date"),
+    ("total", "total_amount"),
+    ("total amount", "total_amount"),
+    ("amount due", "total_amount"),
+    ("total due", "total_amount"),
+    ("tax", "tax_amount"),
+    ("sales tax", "tax_amount"),
+    ("vat", "tax_amount"),
+    ("from", "vendor"),
+    ("vendor", "vendor"),
+    ("to", "client"),
+    ("bill to", "client"),
+    ("client", "client"),
+    ("store", "store"),
+    ("merchant", "store"),
+    ("payment method", "payment_method"),
+    ("payment terms", "payment_terms"),
+    ("account", "account_number"),
+    ("account number", "account_number"),
+    ("statement period", "period"),
+    ("period", "period"),
+    ("beginning balance", "beginning_balance"),
+    ("opening balance", "beginning_balance"),
+    ("ending balance", "ending_balance"),
+    ("closing balance", "ending_balance"),
+    ("employee", "employee"),
+    ("employer", "employer"),
+    ("employer ein", "employer_ein"),
+    ("ein", "employer_ein"),
+    ("wages", "wages"),
+    ("federal tax withheld", "federal_tax_withheld"),
+    ("federal income tax withheld", "federal_tax_withheld"),
+    ("social security wages", "social_security_wages"),
+    ("year", "year"),
+    ("tax year", "year"),
+];
+
+impl Default for RuleBasedExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RuleBasedExtractor {
+    pub fn new() -> Self {
+        let rules = CLASSIFICATION_RULES
+            .iter()
+            .map(|(label, pattern, weight)| ClassificationRule {
+                document_type: DocumentType::from_label(label),
+                pattern: Regex::new(pattern).expect("valid classification pattern"),
+                weight: *weight,
+            })
+            .collect();
+
+        Self {
+            rules,
+            field_pattern: Regex::new(r"^\s*([A-Za-z][A-Za-z0-9 #./-]{0,40}?)\s*:\s*(.+?)\s*$")
+                .expect("valid field pattern"),
+        }
+    }
+
+    /// Picks the document type with the highest rule score, along with a
+    /// confidence derived from how strongly and clearly it won.
+    pub fn classify(&self, text: &str) -> (DocumentType, f32) {
+        let mut scores: Vec<(DocumentType, u32)> = Vec::new();
+
+        for rule in &self.rules {
+            let matched = text.lines().any(|line| rule.pattern.is_match(line));
+            if !matched {
+                continue;
+            }
+            match scores.iter_mut().find(|(t, _)| *t == rule.document_type) {
+                Some((_, score)) => *score += rule.weight,
+                None => scores.push((rule.document_type.clone(), rule.weight)),
+            }
+        }
+
+        scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+        match scores.as_slice() {
+            [] => (DocumentType::Unknown, 0.3),
+            [(best, score)] => (best.clone(), confidence(*score, 0)),
+            [(best, score), (_, runner_up), ..] => (best.clone(), confidence(*score, *runner_up)),
+        }
+    }
+
+    /// Collects `Label: value` lines, mapping known labels onto the keys the
+    /// rest of the crate expects (`invoice_number`, `tax_amount`, ...).
+    pub fn extract_fields(&self, text: &str) -> HashMap<String, String> {
+        let mut fields = HashMap::new();
+
+        for line in text.lines() {
+            let Some(captures) = self.field_pattern.captures(line) else {
+                continue;
+            };
+            let label = captures[1].trim().to_lowercase();
+            let value = captures[2].trim().to_string();
+
+            let key = FIELD_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == label)
+                .map(|(_, key)| key.to_string())
+                .unwrap_or_else(|| to_snake_case(&label));
+
+            if !key.is_empty() {
+                fields.entry(key).or_insert(value);
+            }
+        }
+
+        fields
+    }
+
+    pub fn extract(&self, text: &str) -> FinancialDocument {
+        let (document_type, confidence) = self.classify(text);
+        let extracted_data = self.extract_fields(text);
+
+        let validation_errors: Vec<String> = required_fields(&document_type)
+            .iter()
+            .filter(|field| !extracted_data.contains_key(**field))
+            .map(|field| format!("Missing {}", field))
+            .collect();
+
+        let (categories, tax_implications): (&[&str], &[&str]) = match document_type {
+            DocumentType::Invoice => (&["Accounts Payable"], &["Potential business expense"]),
+            DocumentType::Receipt => (
+                &["Business Expenses"],
+                &["Potential business expense deduction"],
+            ),
+            DocumentType::BankStatement => (&["Banking", "Financial Records"], &[]),
+            DocumentType::TaxForm(_) => (
+                &["Tax Documents", "Income Records"],
+                &["Report wages and withholding on the annual return"],
+            ),
+            _ => (&[], &[]),
+        };
+
+        let legacy = LegacyFinancialDocument {
+            document_type: String::new(),
+            confidence,
+            extracted_data,
+            validation_errors,
+            suggested_categories: categories.iter().map(|c| c.to_string()).collect(),
+            document_insights: Vec::new(),
+        };
+
+        let mut document = FinancialDocument::from(legacy);
+        document.document_type = document_type;
+        document.refresh_fields();
+        document.tax_implications = tax_implications.iter().map(|t| t.to_string()).collect();
+        if let DocumentFields::BankStatement(statement) = document.fields.clone() {
+            document.metadata.transactions =
+                parse_transactions(text, statement.period, statement.beginning_balance.as_ref());
+            document.normalize();
+        }
+        if !document.validation_errors.is_empty() {
+            document.risk_assessment = RiskLevel::Medium;
+        }
+
+        enhance_analysis(&mut document);
+        document
+            .document_insights
+            .push("Extracted by rule-based engine (no model output)".to_string());
+
+        document
+    }
+}
+
+// Confidence grows with both the winning score and its margin over the
+// runner-up; a single weak keyword stays close to a coin flip.
+fn confidence(best: u32, runner_up: u32) -> f32 {
+    let margin = (best - runner_up) as f32 / best as f32;
+    let strength = (best as f32 / 6.0).min(1.0);
+    0.5 + 0.45 * margin * strength
+}
+
+fn to_snake_case(label: &str) -> String {
+    label
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::PagedText;
+    use std::path::Path;
+
+    #[test]
+    fn test_extracts_invoice_fields() {
+        let text = "INVOICE\nFrom: Tech Solutions Inc.\nInvoice #: INV-2024-001\n\
+                    Due Date: February 14, 2024\nTotal: $2,750.00\nTax: $250.00";
+
+        let document = RuleBasedExtractor::new().extract(text);
+
+        assert_eq!(document.document_type, DocumentType::Invoice);
+        assert_eq!(document.extracted_data["invoice_number"], "INV-2024-001");
+        assert_eq!(document.extracted_data["total_amount"], "$2,750.00");
+        assert_eq!(document.extracted_data["tax_amount"], "$250.00");
+        assert_eq!(document.extracted_data["due_date"], "2024-02-14");
+        assert_eq!(document.validation_errors, vec!["Missing date"]);
+    }
+
+    #[test]
+    fn test_classifies_by_content_not_position() {
+        let extractor = RuleBasedExtractor::new();
+
+        let receipt = "Store: Office Supply World\nReceipt #: RCPT-1\nPayment Method: Cash";
+        let statement = "Beginning Balance: $1.00\nEnding Balance: $2.00";
+        let w2 = "Employer EIN: 12-3456789\nSocial Security Wages: $85,000.00";
+
+        assert_eq!(extractor.classify(receipt).0, DocumentType::Receipt);
+        assert_eq!(extractor.classify(statement).0, DocumentType::BankStatement);
+        assert_eq!(
+            extractor.classify(w2).0,
+            DocumentType::TaxForm("W-2".to_string())
+        );
+        assert_eq!(extractor.classify("hello world").0, DocumentType::Unknown);
+    }
+
+    #[test]
+    fn test_extracts_pdf_invoice_end_to_end() {
+        let text = PagedText::load_pdf(Path::new("fixtures/pdf/invoice.pdf")).unwrap();
+
+        let mut document = RuleBasedExtractor::new().extract(&text.text());
+        text.annotate(&mut document);
+
+        assert_eq!(document.document_type, DocumentType::Invoice);
+        // "Total Due: $178.20" on the second page.
+        assert_eq!(document.extracted_data["total_amount"], "$178.20");
+        assert!(!document.extracted_data.contains_key("total_due"));
+        assert_eq!(document.metadata.source_pages.get("total_amount"), Some(&2));
+        assert_eq!(document.validation_errors, vec!["Missing vendor"]);
+    }
+}
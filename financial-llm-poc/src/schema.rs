@@ -0,0 +1,131 @@
+use crate::document_types::FinancialDocument;
+use jsonschema::Validator;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use serde_json::{json, Value};
+use std::sync::LazyLock;
+
+static FINANCIAL_DOCUMENT_SCHEMA: LazyLock<Value> = LazyLock::new(|| {
+    serde_json::to_value(schemars::schema_for!(FinancialDocument))
+        .expect("schema serializes to JSON")
+});
+
+static FINANCIAL_DOCUMENT_VALIDATOR: LazyLock<Validator> = LazyLock::new(|| {
+    jsonschema::validator_for(&FINANCIAL_DOCUMENT_SCHEMA).expect("generated schema is valid")
+});
+
+/// JSON Schema for `FinancialDocument`, generated from the Rust types so the
+/// schema sent to models and the one checked locally cannot drift apart.
+pub fn financial_document_schema() -> &'static Value {
+    &FINANCIAL_DOCUMENT_SCHEMA
+}
+
+/// Checks a parsed model response against the `FinancialDocument` schema.
+/// Each violation is reported with the path of the offending field, e.g.
+/// `metadata.parties[0].name: 42 is not of type "string"`.
+pub fn validate_financial_document(value: &Value) -> Result<(), Vec<String>> {
+    let errors: Vec<String> = FINANCIAL_DOCUMENT_VALIDATOR
+        .iter_errors(value)
+        .map(|error| {
+            let path = field_path(error.instance_path.as_str());
+            format!("{}: {}", path, error)
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// "/metadata/parties/0/name" -> "metadata.parties[0].name"
+fn field_path(pointer: &str) -> String {
+    let mut path = String::new();
+    for segment in pointer.split('/').skip(1) {
+        let segment = segment.replace("~1", "/").replace("~0", "~");
+        if segment.chars().all(|c| c.is_ascii_digit()) {
+            path.push_str(&format!("[{}]", segment));
+        } else {
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(&segment);
+        }
+    }
+    if path.is_empty() {
+        "(root)".to_string()
+    } else {
+        path
+    }
+}
+
+/// Decimals are accepted as JSON numbers or numeric strings.
+pub(crate) fn decimal_schema(_: &mut SchemaGenerator) -> Schema {
+    schema_from_json(json!({
+        "type": ["number", "string", "null"]
+    }))
+}
+
+pub(crate) fn schema_from_json(value: Value) -> Schema {
+    serde_json::from_value(value).expect("hand-written schema is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_describes_document() {
+        let schema = financial_document_schema();
+        let required = schema["required"].as_array().unwrap();
+        assert!(required.contains(&json!("document_type")));
+        assert!(!required.contains(&json!("document_insights")));
+        assert!(schema["definitions"]["Money"].is_object());
+    }
+
+    #[test]
+    fn test_validation_reports_field_paths() {
+        let document = json!({
+            "document_type": 7,
+            "confidence": "high",
+            "extracted_data": {},
+            "validation_errors": [],
+            "suggested_categories": [],
+            "tax_implications": [],
+            "risk_assessment": true,
+            "metadata": {
+                "parties": [{"role": "payee", "name": 42}],
+                "line_items": []
+            }
+        });
+
+        let errors = validate_financial_document(&document).unwrap_err();
+
+        assert!(errors.iter().any(|e| e.starts_with("confidence: ")));
+        assert!(errors.iter().any(|e| e.starts_with("document_type: ")));
+        assert!(errors.iter().any(|e| e.starts_with("risk_assessment: ")));
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with("metadata.parties[0].name: ")));
+    }
+
+    #[test]
+    fn test_risk_score_out_of_range_is_a_schema_error() {
+        let mut document = json!({
+            "document_type": "Invoice",
+            "confidence": 0.9,
+            "extracted_data": {},
+            "validation_errors": [],
+            "suggested_categories": [],
+            "tax_implications": [],
+            "risk_assessment": 0.75,
+            "metadata": {"parties": [], "line_items": []}
+        });
+        assert_eq!(validate_financial_document(&document), Ok(()));
+
+        document["risk_assessment"] = json!(40);
+        let errors = validate_financial_document(&document).unwrap_err();
+        assert!(errors.iter().any(|e| e.starts_with("risk_assessment: ")));
+    }
+}
@@ -0,0 +1,173 @@
+/// Reported as streamed content arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamProgress {
+    /// Events carrying content received so far.
+    pub chunks: usize,
+    /// Bytes of content assembled so far.
+    pub bytes: usize,
+    /// Whether the stream has ended.
+    pub finished: bool,
+}
+
+/// Splits a `text/event-stream` body into the `data` of each event. Network
+/// chunks may end anywhere, including inside a line.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Consumes `bytes` and returns the events they complete.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                events.extend(self.dispatch());
+            } else if let Some(data) = line.strip_prefix("data:") {
+                self.data
+                    .push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+            // Comments (`:`) and the `event`, `id` and `retry` fields carry
+            // nothing the chat completion APIs use.
+        }
+        events
+    }
+
+    /// The last event, when the stream ended without a blank line after it.
+    pub fn finish(&mut self) -> Option<String> {
+        let mut events = self.feed(b"\n");
+        events.extend(self.dispatch());
+        events.pop()
+    }
+
+    fn dispatch(&mut self) -> Option<String> {
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(std::mem::take(&mut self.data).join("\n"))
+    }
+}
+
+/// Recovers the complete part of JSON that was cut off mid-stream: values
+/// that did not finish are dropped and the open objects and arrays closed.
+/// A partial value is never kept, so a truncated `"2,75` cannot pass for an
+/// amount. Returns `None` when nothing usable was received.
+pub fn repair_truncated_json(partial: &str) -> Option<String> {
+    let start = partial.find(['{', '['])?;
+    let text = &partial[start..];
+    if serde_json::from_str::<serde_json::Value>(text).is_ok() {
+        return Some(text.to_string());
+    }
+
+    // Where the text could be cut, with the containers open at that point.
+    let mut cut: Option<(usize, Vec<u8>)> = None;
+    let mut stack: Vec<u8> = Vec::new();
+    // Whether the next string in the innermost object is a key.
+    let mut expect_key: Vec<bool> = Vec::new();
+    let mut in_string = false;
+    let mut string_is_key = false;
+    let mut escaped = false;
+
+    for (i, byte) in text.bytes().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+                if !string_is_key {
+                    cut = Some((i + 1, stack.clone()));
+                }
+            }
+            continue;
+        }
+        match byte {
+            b'"' => {
+                in_string = true;
+                string_is_key = stack.last() == Some(&b'{') && expect_key.last() == Some(&true);
+            }
+            b'{' | b'[' => {
+                stack.push(byte);
+                expect_key.push(byte == b'{');
+                cut = Some((i + 1, stack.clone()));
+            }
+            b'}' | b']' => {
+                stack.pop();
+                expect_key.pop();
+                if stack.is_empty() {
+                    return Some(text[..=i].to_string());
+                }
+                cut = Some((i + 1, stack.clone()));
+            }
+            b':' => {
+                if let Some(key) = expect_key.last_mut() {
+                    *key = false;
+                }
+            }
+            b',' => {
+                // The value before the comma is complete.
+                cut = Some((i, stack.clone()));
+                if let Some(key) = expect_key.last_mut() {
+                    *key = stack.last() == Some(&b'{');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (end, open) = cut?;
+    let mut repaired = text[..end].trim_end().trim_end_matches(',').to_string();
+    for container in open.iter().rev() {
+        repaired.push(if *container == b'{' { '}' } else { ']' });
+    }
+    serde_json::from_str::<serde_json::Value>(&repaired)
+        .is_ok()
+        .then_some(repaired)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sse_parser_handles_split_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed(b": keep-alive\n\nda").is_empty());
+        assert_eq!(
+            parser.feed(b"ta: {\"a\":\r\ndata: 1}\n\ndata: [DONE]"),
+            vec!["{\"a\":\n1}"]
+        );
+        assert_eq!(parser.finish().as_deref(), Some("[DONE]"));
+    }
+
+    #[test]
+    fn test_repair_keeps_only_complete_values() {
+        let cases = [
+            (r#"```json {"a": 1}"#, json!({"a": 1})),
+            (r#"{"a": "x", "b": "2,75"#, json!({"a": "x"})),
+            (r#"{"a": "x", "b":"#, json!({"a": "x"})),
+            (r#"{"a": "x", "b": "y""#, json!({"a": "x", "b": "y"})),
+            (
+                r#"{"a": {"b": ["c", "d"], "e": tr"#,
+                json!({"a": {"b": ["c", "d"]}}),
+            ),
+            (
+                r#"{"items": [{"q": 2}, {"q"#,
+                json!({"items": [{"q": 2}, {}]}),
+            ),
+        ];
+        for (partial, expected) in cases {
+            let repaired = repair_truncated_json(partial).unwrap();
+            let value: serde_json::Value = serde_json::from_str(&repaired).unwrap();
+            assert_eq!(value, expected, "{}", partial);
+        }
+        assert_eq!(repair_truncated_json("no json here"), None);
+    }
+}
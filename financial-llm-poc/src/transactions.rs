@@ -0,0 +1,264 @@
+use crate::dates::{parse_date, DateRange};
+use crate::document_types::{FinancialDocument, Transaction};
+use crate::fields::DocumentFields;
+use crate::money::{Currency, Money};
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+use rust_decimal::Decimal;
+use std::sync::LazyLock;
+
+// A statement line: date, description, signed amount and an optional
+// running balance, e.g. `01/05/2024  Payroll Deposit  +3,500.00  16,000.00`.
+static TRANSACTION_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    let date =
+        r"\d{4}-\d{2}-\d{2}|\d{1,2}/\d{1,2}(?:/\d{2,4})?|[A-Za-z]{3,9}\.? \d{1,2}(?:, \d{4})?";
+    let amount = r"[-+(]?[$€£]?\d[\d,]*\.\d{2}\)?(?:\s?(?:CR|DR))?";
+    Regex::new(&format!(
+        r"^\s*({date})\s+(.+?)\s+({amount})(?:\s+({amount}))?\s*$"
+    ))
+    .expect("valid transaction pattern")
+});
+
+const CREDIT_KEYWORDS: &[&str] = &[
+    "deposit", "credit", "payroll", "salary", "interest", "refund", "received", "incoming",
+];
+
+/// Reads transaction lines from statement text. Dates without a year take
+/// it from `period`; unsigned amounts are classified by the running balance
+/// when there is one, otherwise by keywords in the description.
+pub fn parse_transactions(
+    text: &str,
+    period: Option<DateRange>,
+    opening_balance: Option<&Money>,
+) -> Vec<Transaction> {
+    let mut previous_balance = opening_balance.map(|balance| balance.amount);
+    let mut transactions = Vec::new();
+
+    for line in text.lines() {
+        let Some(captures) = TRANSACTION_LINE.captures(line) else {
+            continue;
+        };
+        let (Some(date), Some(amount)) = (
+            parse_transaction_date(&captures[1], period),
+            parse_amount(&captures[3]),
+        ) else {
+            continue;
+        };
+        let balance = captures.get(4).and_then(|m| parse_amount(m.as_str()));
+        let description = captures[2].trim().to_string();
+
+        let is_credit = match amount.sign {
+            Some(sign) => sign,
+            None => match (previous_balance, &balance) {
+                (Some(previous), Some(balance)) => balance.money.amount > previous,
+                _ => {
+                    let lower = description.to_lowercase();
+                    CREDIT_KEYWORDS.iter().any(|k| lower.contains(k))
+                }
+            },
+        };
+        if let Some(balance) = &balance {
+            previous_balance = Some(balance.money.amount);
+        }
+
+        let money = amount.money;
+        transactions.push(Transaction {
+            date: Some(date),
+            description,
+            debit: (!is_credit).then(|| money.clone()),
+            credit: is_credit.then_some(money),
+            balance: balance.map(|balance| balance.money),
+            reference: None,
+        });
+    }
+
+    transactions
+}
+
+struct ParsedAmount {
+    money: Money,
+    /// `Some(true)` for a credit, `Some(false)` for a debit, `None` unsigned.
+    sign: Option<bool>,
+}
+
+fn parse_amount(text: &str) -> Option<ParsedAmount> {
+    let text = text.trim();
+    let (text, marker) = match text.strip_suffix("CR") {
+        Some(rest) => (rest.trim(), Some(true)),
+        None => match text.strip_suffix("DR") {
+            Some(rest) => (rest.trim(), Some(false)),
+            None => (text, None),
+        },
+    };
+    let explicit_credit = text.starts_with('+');
+    let money = Money::parse(text.trim_start_matches('+')).ok()?;
+    let sign = if money.amount.is_sign_negative() {
+        Some(false)
+    } else if explicit_credit {
+        Some(true)
+    } else {
+        marker
+    };
+    Some(ParsedAmount {
+        money: Money::new(money.amount.abs(), money.currency),
+        sign,
+    })
+}
+
+fn parse_transaction_date(text: &str, period: Option<DateRange>) -> Option<NaiveDate> {
+    if let Some(parsed) = parse_date(text) {
+        return Some(parsed.date);
+    }
+    // "01/05" or "Jan 5": borrow the year from the statement period.
+    let period = period?;
+    [period.start.year(), period.end.year()]
+        .into_iter()
+        .filter_map(|year| {
+            let separator = if text.contains('/') { "/" } else { ", " };
+            parse_date(&format!("{}{}{}", text, separator, year))
+        })
+        .map(|parsed| parsed.date)
+        .find(|date| period.contains(*date))
+}
+
+/// Problems found when replaying the transactions from the beginning
+/// balance: running balances that do not follow, and an ending balance that
+/// does not equal the beginning balance plus the net movement.
+pub fn reconciliation_issues(document: &FinancialDocument) -> Vec<String> {
+    let transactions = &document.metadata.transactions;
+    let DocumentFields::BankStatement(statement) = &document.fields else {
+        return Vec::new();
+    };
+    if transactions.is_empty() {
+        return Vec::new();
+    }
+
+    let tolerance = Decimal::new(1, 2);
+    let currency = document
+        .metadata
+        .currency()
+        .unwrap_or_else(Currency::unknown);
+    let mut issues = Vec::new();
+
+    let mut net = Money::zero(currency);
+    let mut running = statement.beginning_balance.clone();
+    for transaction in transactions {
+        let Some((movement, sum)) = transaction
+            .net()
+            .and_then(|movement| Some((movement.clone(), net.checked_add(&movement)?)))
+        else {
+            issues.push(format!(
+                "Transaction '{}' is in a different currency",
+                transaction.description
+            ));
+            return issues;
+        };
+        net = sum;
+
+        running = running.and_then(|balance| balance.checked_add(&movement));
+        if let (Some(expected), Some(stated)) = (&running, &transaction.balance) {
+            let off = expected
+                .checked_sub(stated)
+                .is_none_or(|difference| difference.amount.abs() > tolerance);
+            if off {
+                issues.push(format!(
+                    "Running balance after '{}' should be {}, statement shows {}",
+                    transaction.description, expected, stated
+                ));
+                // Continue from the stated balance so one error is not
+                // reported on every later line.
+                running = Some(stated.clone());
+            }
+        }
+    }
+
+    if let (Some(beginning), Some(ending)) =
+        (&statement.beginning_balance, &statement.ending_balance)
+    {
+        let expected = beginning.checked_add(&net);
+        let reconciles = expected
+            .as_ref()
+            .and_then(|expected| expected.checked_sub(ending))
+            .is_some_and(|difference| difference.amount.abs() <= tolerance);
+        if !reconciles {
+            issues.push(format!(
+                "Balances do not reconcile: beginning balance {} plus net movement {} is {}, but ending balance is {}",
+                beginning,
+                net,
+                expected.map(|m| m.to_string()).unwrap_or_else(|| "undefined".to_string()),
+                ending
+            ));
+        }
+    }
+
+    issues
+}
+
+/// Adds any reconciliation issues to `validation_errors`.
+pub fn reconcile(document: &mut FinancialDocument) {
+    for issue in reconciliation_issues(document) {
+        if !document.validation_errors.contains(&issue) {
+            document.validation_errors.push(issue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule_extractor::RuleBasedExtractor;
+
+    const STATEMENT: &str = "BANK STATEMENT
+Account: ****1234
+Statement Period: Jan 1-31, 2024
+Beginning Balance: $12,500.00
+Ending Balance: $16,714.50
+Transactions:
+01/05  Payroll Deposit   3,500.00   16,000.00
+01/12  Grocery Store     285.50     15,714.50
+Jan 15 Client Payment    +2,000.00
+2024-01-28  Rent         -1,000.00  16,714.50";
+
+    #[test]
+    fn test_parse_transactions() {
+        let document = RuleBasedExtractor::new().extract(STATEMENT);
+        let transactions = &document.metadata.transactions;
+
+        assert_eq!(transactions.len(), 4);
+        assert_eq!(transactions[0].date, NaiveDate::from_ymd_opt(2024, 1, 5));
+        assert_eq!(
+            transactions[0].credit,
+            Some(Money::parse("$3,500.00").unwrap())
+        );
+        // Unsigned, but the running balance went down.
+        assert_eq!(
+            transactions[1].debit,
+            Some(Money::parse("$285.50").unwrap())
+        );
+        assert_eq!(transactions[2].date, NaiveDate::from_ymd_opt(2024, 1, 15));
+        assert!(transactions[2].credit.is_some());
+        assert!(transactions[3].debit.is_some());
+        assert!(
+            document.validation_errors.is_empty(),
+            "{:?}",
+            document.validation_errors
+        );
+    }
+
+    #[test]
+    fn test_reconciliation_flags_mismatches() {
+        let text = STATEMENT
+            .replace("285.50     15,714.50", "285.50     15,700.00")
+            .replace("Ending Balance: $16,714.50", "Ending Balance: $17,000.00");
+        let document = RuleBasedExtractor::new().extract(&text);
+
+        assert_eq!(
+            document.validation_errors,
+            vec![
+                "Running balance after 'Grocery Store' should be $15,714.50, statement shows $15,700.00",
+                "Running balance after 'Rent' should be $16,700.00, statement shows $16,714.50",
+                "Balances do not reconcile: beginning balance $12,500.00 plus net movement $4,214.50 is $16,714.50, but ending balance is $17,000.00",
+            ]
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use crate::document_types::{DocumentType, FinancialDocument, LineItem, ValidationResult};
+use crate::fields::DocumentFields;
+use crate::money::Money;
+use crate::transactions::reconciliation_issues;
+use rust_decimal::Decimal;
+
+// Rubric weights. Criteria that do not apply to a document (no line items,
+// no total) are left out and the remaining weights are rescaled.
+const REQUIRED_FIELDS_WEIGHT: f32 = 0.4;
+const LINE_ITEMS_WEIGHT: f32 = 0.3;
+const TOTALS_WEIGHT: f32 = 0.3;
+const EXTRACTION_WEIGHT: f32 = 0.2;
+// The score of a document none of the criteria apply to: nothing was found
+// wrong, but nothing could be checked either.
+const UNCHECKED_SCORE: f32 = 0.5;
+
+/// Fields every document of a type is expected to carry. Only W-2s are
+/// known among tax forms, as in `FinancialDocument::fields`.
+pub fn required_fields(document_type: &DocumentType) -> &'static [&'static str] {
+    match document_type {
+        DocumentType::Invoice => &["invoice_number", "date", "total_amount", "vendor"],
+        DocumentType::Receipt => &["date", "total_amount", "store"],
+        DocumentType::BankStatement => &[
+            "account_number",
+            "period",
+            "beginning_balance",
+            "ending_balance",
+        ],
+        DocumentType::TaxForm(form) if form.is_empty() || form == "W-2" => {
+            &["year", "employee", "employer", "wages"]
+        }
+        _ => &[],
+    }
+}
+
+/// Deterministic checks on an analysed document: required fields, line item
+/// arithmetic, totals and statement balances. No model is involved.
+#[derive(Debug, Clone)]
+pub struct DocumentValidator {
+    tolerance: Decimal,
+}
+
+impl Default for DocumentValidator {
+    fn default() -> Self {
+        Self {
+            tolerance: Decimal::new(1, 2),
+        }
+    }
+}
+
+impl DocumentValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Largest difference still treated as a rounding error.
+    pub fn with_tolerance(mut self, tolerance: Decimal) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn validate(&self, document: &FinancialDocument) -> ValidationResult {
+        let mut rubric = Vec::new();
+
+        let required = required_fields(&document.document_type);
+        let missing_fields: Vec<String> = required
+            .iter()
+            .filter(|field| !has_field(document, field))
+            .map(|field| field.to_string())
+            .collect();
+        if !required.is_empty() {
+            let present = required.len() - missing_fields.len();
+            rubric.push((
+                REQUIRED_FIELDS_WEIGHT,
+                present as f32 / required.len() as f32,
+            ));
+        }
+
+        let mut data_quality_issues = Vec::new();
+
+        let line_items = &document.metadata.line_items;
+        if !line_items.is_empty() {
+            let mut consistent = 0;
+            for item in line_items {
+                match self.check_line_item(item) {
+                    Ok(()) => consistent += 1,
+                    Err(issue) => data_quality_issues.push(issue),
+                }
+            }
+            rubric.push((
+                LINE_ITEMS_WEIGHT,
+                consistent as f32 / line_items.len() as f32,
+            ));
+        }
+
+        if let Some(result) = self.check_totals(document) {
+            let score = match result {
+                Ok(()) => 1.0,
+                Err(issue) => {
+                    data_quality_issues.push(issue);
+                    0.0
+                }
+            };
+            rubric.push((TOTALS_WEIGHT, score));
+        }
+
+        if !document.metadata.transactions.is_empty() {
+            let issues = reconciliation_issues(document);
+            rubric.push((TOTALS_WEIGHT, if issues.is_empty() { 1.0 } else { 0.0 }));
+            data_quality_issues.extend(issues);
+        }
+
+        // Problems the extraction reported itself, such as a cut-off answer
+        // or an ambiguous date, unless they were found again above.
+        let extraction_issues: Vec<String> = document
+            .validation_errors
+            .iter()
+            .filter(|error| !data_quality_issues.contains(error))
+            .filter(|error| {
+                !missing_fields
+                    .iter()
+                    .any(|field| **error == format!("Missing {}", field))
+            })
+            .cloned()
+            .collect();
+        if !extraction_issues.is_empty() {
+            rubric.push((EXTRACTION_WEIGHT, 0.0));
+            data_quality_issues.extend(extraction_issues);
+        }
+
+        let total_weight: f32 = rubric.iter().map(|(weight, _)| weight).sum();
+        let overall_score = if total_weight > 0.0 {
+            rubric
+                .iter()
+                .map(|(weight, score)| weight * score)
+                .sum::<f32>()
+                / total_weight
+        } else {
+            UNCHECKED_SCORE
+        };
+
+        ValidationResult {
+            is_valid: missing_fields.is_empty() && data_quality_issues.is_empty(),
+            missing_fields,
+            data_quality_issues,
+            compliance_issues: Vec::new(),
+            overall_score,
+        }
+    }
+
+    fn check_line_item(&self, item: &LineItem) -> Result<(), String> {
+        let (Some(quantity), Some(unit_price)) = (item.quantity, &item.unit_price) else {
+            return Ok(());
+        };
+        let expected = Money::new(quantity * unit_price.amount, unit_price.currency.clone());
+        match self.difference(&expected, &item.amount) {
+            Some(difference) if difference <= self.tolerance => Ok(()),
+            Some(_) => Err(format!(
+                "Line item '{}': {} x {} = {}, but amount is {}",
+                item.description, quantity, unit_price, expected, item.amount
+            )),
+            None => Err(format!(
+                "Line item '{}': unit price {} and amount {} use different currencies",
+                item.description, unit_price, item.amount
+            )),
+        }
+    }
+
+    // Line items plus tax must add up to the total. Without line items a
+    // stated subtotal is used instead; with neither there is nothing to check.
+    fn check_totals(&self, document: &FinancialDocument) -> Option<Result<(), String>> {
+        let total = document
+            .metadata
+            .total_amount
+            .clone()
+            .or_else(|| document.amount("total_amount"))?;
+        let (subtotal, tax) = match &document.fields {
+            DocumentFields::Invoice(invoice) => {
+                (invoice.subtotal.clone(), invoice.tax_amount.clone())
+            }
+            DocumentFields::Receipt(receipt) => {
+                (receipt.subtotal.clone(), receipt.tax_amount.clone())
+            }
+            _ => (document.amount("subtotal"), document.amount("tax_amount")),
+        };
+        let (base, label) = match document.metadata.line_items_total() {
+            Some(sum) => (sum, "line items"),
+            None => (subtotal?, "subtotal"),
+        };
+
+        let expected = match &tax {
+            Some(tax) => match base.checked_add(tax) {
+                Some(sum) => sum,
+                None => {
+                    return Some(Err(format!(
+                        "{} {} and tax {} use different currencies",
+                        capitalize(label),
+                        base,
+                        tax
+                    )))
+                }
+            },
+            None => base.clone(),
+        };
+
+        Some(match self.difference(&expected, &total) {
+            Some(difference) if difference <= self.tolerance => Ok(()),
+            Some(_) => Err(match tax {
+                Some(tax) => format!(
+                    "{} {} plus tax {} is {}, but total is {}",
+                    capitalize(label),
+                    base,
+                    tax,
+                    expected,
+                    total
+                ),
+                None => format!(
+                    "{} add up to {}, but total is {}",
+                    capitalize(label),
+                    expected,
+                    total
+                ),
+            }),
+            None => Err(format!(
+                "Total {} and {} {} use different currencies",
+                total, label, expected
+            )),
+        })
+    }
+
+    fn difference(&self, a: &Money, b: &Money) -> Option<Decimal> {
+        a.checked_sub(b).map(|difference| difference.amount.abs())
+    }
+}
+
+fn has_field(document: &FinancialDocument, field: &str) -> bool {
+    let present = document
+        .extracted_data
+        .get(field)
+        .is_some_and(|value| !value.trim().is_empty());
+    present
+        || match field {
+            "date" => document.metadata.document_date.is_some(),
+            "period" => document.metadata.period.is_some(),
+            "total_amount" => document.metadata.total_amount.is_some(),
+            "vendor" | "store" => has_party(document, "payee"),
+            "client" => has_party(document, "payer"),
+            "employer" | "employee" => has_party(document, field),
+            _ => false,
+        }
+}
+
+fn has_party(document: &FinancialDocument, role: &str) -> bool {
+    document
+        .metadata
+        .parties
+        .iter()
+        .any(|party| party.role == role && !party.name.trim().is_empty())
+}
+
+fn capitalize(label: &str) -> String {
+    let mut chars = label.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_types::{DocumentMetadata, RiskLevel};
+    use std::collections::HashMap;
+
+    fn usd(amount: &str) -> Money {
+        Money::parse(&format!("USD {}", amount)).unwrap()
+    }
+
+    fn invoice(line_items: Vec<LineItem>, total: &str, tax: &str) -> FinancialDocument {
+        let extracted_data: HashMap<String, String> = [
+            ("invoice_number", "INV-2024-001"),
+            ("date", "2024-01-15"),
+            ("vendor", "Tech Solutions Inc."),
+            ("tax_amount", tax),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let mut document = FinancialDocument {
+            document_type: DocumentType::Invoice,
+            confidence: 0.9,
+            extracted_data,
+            validation_errors: vec![],
+            suggested_categories: vec![],
+            tax_implications: vec![],
+            risk_assessment: RiskLevel::Low,
+            metadata: DocumentMetadata {
+                document_date: None,
+                period: None,
+                total_amount: Some(usd(total)),
+                currency: Some("USD".to_string()),
+                parties: vec![],
+                line_items,
+                transactions: vec![],
+                page_count: None,
+                source_pages: Default::default(),
+            },
+            document_insights: vec![],
+            prompt_version: None,
+            usage: None,
+            fields: DocumentFields::default(),
+        };
+        document.refresh_fields();
+        document
+    }
+
+    fn item(description: &str, quantity: i64, unit_price: &str, amount: &str) -> LineItem {
+        LineItem {
+            description: description.to_string(),
+            quantity: Some(Decimal::from(quantity)),
+            unit_price: Some(usd(unit_price)),
+            amount: usd(amount),
+            tax_rate: None,
+            tax_category: None,
+        }
+    }
+
+    #[test]
+    fn test_consistent_invoice_is_valid() {
+        let document = invoice(
+            vec![
+                item("Consulting", 10, "150.00", "1500.00"),
+                item("Support", 1, "1000.00", "1000.00"),
+            ],
+            "2750.00",
+            "250.00",
+        );
+
+        let result = DocumentValidator::new().validate(&document);
+
+        assert!(result.is_valid, "{:?}", result);
+        assert_eq!(result.overall_score, 1.0);
+    }
+
+    #[test]
+    fn test_reports_arithmetic_and_missing_fields() {
+        let mut document = invoice(
+            vec![item("Consulting", 10, "150.00", "1400.00")],
+            "2000.00",
+            "250.00",
+        );
+        document.extracted_data.remove("vendor");
+
+        let result = DocumentValidator::new().validate(&document);
+
+        assert!(!result.is_valid);
+        assert_eq!(result.missing_fields, vec!["vendor"]);
+        assert_eq!(
+            result.data_quality_issues,
+            vec![
+                "Line item 'Consulting': 10 x $150.00 = $1,500.00, but amount is $1,400.00",
+                "Line items $1,400.00 plus tax $250.00 is $1,650.00, but total is $2,000.00",
+            ]
+        );
+        // 3 of 4 required fields, no consistent line items, totals wrong.
+        assert!((result.overall_score - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_form_kind_and_extraction_errors_are_respected() {
+        let mut form = invoice(vec![], "0.00", "0.00");
+        form.document_type = DocumentType::TaxForm("1099-MISC".to_string());
+        form.extracted_data.clear();
+        form.metadata.total_amount = None;
+
+        // No W-2 boxes are expected of a 1099, and nothing else applies.
+        let result = DocumentValidator::new().validate(&form);
+        assert!(result.is_valid, "{:?}", result);
+        assert!(result.missing_fields.is_empty());
+        assert_eq!(result.overall_score, UNCHECKED_SCORE);
+
+        let mut document = invoice(vec![], "2750.00", "250.00");
+        document.extracted_data.remove("vendor");
+        document.validation_errors = vec![
+            "Missing vendor".to_string(),
+            "Model output was cut off".to_string(),
+        ];
+
+        let result = DocumentValidator::new().validate(&document);
+        assert!(!result.is_valid);
+        assert_eq!(result.missing_fields, vec!["vendor"]);
+        assert_eq!(result.data_quality_issues, vec!["Model output was cut off"]);
+        // 3 of 4 required fields, no totals to check, extraction flawed.
+        assert!((result.overall_score - 0.3 / 0.6).abs() < 1e-6);
+    }
+}
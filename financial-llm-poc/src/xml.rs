@@ -0,0 +1,23 @@
+use roxmltree::Node;
+
+/// The element at `path` below `node`, following the first match of each
+/// step. Elements are matched by local name, so the namespace prefixes a
+/// sender chose do not matter.
+pub(crate) fn child<'a, 'input>(node: Node<'a, 'input>, path: &[&str]) -> Option<Node<'a, 'input>> {
+    path.iter().try_fold(node, |node, tag| {
+        node.children().find(|child| child.has_tag_name(*tag))
+    })
+}
+
+pub(crate) fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    tag: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| child.has_tag_name(tag))
+}
+
+/// The trimmed text of the element at `path`, if it has any.
+pub(crate) fn text(node: Node, path: &[&str]) -> Option<String> {
+    let text = child(node, path)?.text()?.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}